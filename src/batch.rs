@@ -0,0 +1,40 @@
+//! # Batch Module
+//! Groups writes to several trees, so that an entity and its bookkeeping (versions, instance siblings,
+//! relation descriptors, changelog) are written in a single transaction.
+
+use std::collections::HashMap;
+
+use crate::error::Result;
+use crate::Error;
+use sled::transaction::ConflictableTransactionError;
+use sled::{Batch, Db, Transactional};
+
+/// One `sled` batch per tree name, applied atomically by [`apply`](#method.apply).
+#[derive(Default)]
+pub(crate) struct TreeBatches {
+    batches: HashMap<String, Batch>,
+}
+
+impl TreeBatches {
+    /// Returns the batch of the tree `tree_name`.
+    pub(crate) fn tree(&mut self, tree_name: &str) -> &mut Batch {
+        self.batches.entry(String::from(tree_name)).or_default()
+    }
+
+    /// Applies every batch in a single transaction.
+    pub(crate) fn apply(self, db: &Db) -> Result<()> {
+        let mut trees = Vec::with_capacity(self.batches.len());
+        let mut batches = Vec::with_capacity(self.batches.len());
+        for (tree_name, batch) in self.batches {
+            trees.push(db.open_tree(tree_name)?);
+            batches.push(batch);
+        }
+        trees.as_slice().transaction(|trees| {
+            for (tree, batch) in trees.iter().zip(&batches) {
+                tree.apply_batch(batch)?;
+            }
+            Ok::<(), ConflictableTransactionError<Error>>(())
+        })?;
+        Ok(())
+    }
+}
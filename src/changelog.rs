@@ -0,0 +1,127 @@
+//! # Changelog Module
+//! This module provides an opt-in changelog of every entity saved or removed from the database.
+//! It is only available with the `changelog` feature, since it adds a write for every save and remove operation.
+//!
+//! ⚠ Apart from batch writes such as [`replace_all`](../entity/trait.Entity.html#method.replace_all), changes are
+//! recorded right after the entity is written, not in the same transaction : if the process crashes in between,
+//! the entity is written but its change is not recorded. The changelog is meant for synchronization and auditing,
+//! not for recovery.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::batch::TreeBatches;
+use crate::config::{internal_prefix, internal_tree_name};
+use crate::error::Result;
+use serde_derive::{Deserialize, Serialize};
+use sled::Db;
+
+/// The kind of operation recorded in a [`ChangeRecord`](changelog/struct.ChangeRecord.html)
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ChangeOp {
+    /// The entity was saved (created or updated)
+    Save,
+    /// The entity was removed
+    Remove,
+}
+
+/// A single entry of the changelog, describing an operation on an entity.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct ChangeRecord {
+    /// Time of the operation, in milliseconds since the UNIX epoch
+    pub timestamp: u64,
+    /// Name of the store the entity belongs to
+    pub store: String,
+    /// Binary representation of the entity's key
+    pub key: Vec<u8>,
+    /// Operation performed on the entity
+    pub op: ChangeOp,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+pub(crate) fn record(store: &str, key: &[u8], op: ChangeOp, db: &Db) -> Result<()> {
+    if let Some((record_key, record)) = entry(store, key, op, db)? {
+        db.open_tree(internal_tree_name("changelog"))?
+            .insert(record_key, record)?;
+    }
+    Ok(())
+}
+
+/// Same as [`record`], as part of a batch of writes.
+pub(crate) fn record_in(
+    store: &str,
+    key: &[u8],
+    op: ChangeOp,
+    batches: &mut TreeBatches,
+    db: &Db,
+) -> Result<()> {
+    if let Some((record_key, record)) = entry(store, key, op, db)? {
+        batches
+            .tree(&internal_tree_name("changelog"))
+            .insert(record_key, record);
+    }
+    Ok(())
+}
+
+/// Returns the key and the value of the changelog entry of an operation, internal stores having none.
+fn entry(store: &str, key: &[u8], op: ChangeOp, db: &Db) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    if store.starts_with(internal_prefix()) {
+        return Ok(None);
+    }
+    let record = ChangeRecord {
+        timestamp: now(),
+        store: String::from(store),
+        key: key.to_vec(),
+        op,
+    };
+    let record_key = [
+        record.timestamp.to_be_bytes(),
+        db.generate_id()?.to_be_bytes(),
+    ]
+    .concat();
+    Ok(Some((record_key, bincode::serialize(&record)?)))
+}
+
+/// Returns every change recorded at or after `timestamp` (in milliseconds since the UNIX epoch),
+/// in chronological order.
+///
+/// ### Example
+/// ```rust,ignore
+/// let changes = reindeer::changes_since(last_sync, &db)?;
+/// ```
+pub fn changes_since(timestamp: u64, db: &Db) -> Result<Vec<ChangeRecord>> {
+    let mut result = Vec::new();
+    for elem in db
+        .open_tree(internal_tree_name("changelog"))?
+        .range(timestamp.to_be_bytes()..)
+    {
+        let (_, value) = elem?;
+        result.push(bincode::deserialize(&value)?);
+    }
+    Ok(result)
+}
+
+/// Removes every change recorded strictly before `timestamp` (in milliseconds since the UNIX epoch)
+/// and returns the number of removed records.
+///
+/// ### Example
+/// ```rust,ignore
+/// reindeer::prune_changelog(last_sync, &db)?;
+/// ```
+pub fn prune_changelog(timestamp: u64, db: &Db) -> Result<usize> {
+    let tree = db.open_tree(internal_tree_name("changelog"))?;
+    let mut batch = sled::Batch::default();
+    let mut count = 0;
+    for elem in tree.range(..timestamp.to_be_bytes()) {
+        let (key, _) = elem?;
+        batch.remove(key);
+        count += 1;
+    }
+    tree.apply_batch(batch)?;
+    Ok(count)
+}
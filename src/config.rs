@@ -0,0 +1,219 @@
+//! # Config Module
+//! This module provides [`OpenConfig`](struct.OpenConfig.html), to open a database with non-default options,
+//! such as the [`SledMode`](enum.SledMode.html) trading speed for disk space,
+//! and keeps track of the prefix reserved for the trees `reindeer` uses for its own bookkeeping
+//! (relations, families, siblings, metadata...).
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::error::Result;
+use crate::storage::{set_bincode_options, StorageFormat};
+use crate::{Error, ErrorKind};
+use sled::Db;
+
+const DEFAULT_INTERNAL_PREFIX: &str = "__$";
+const INTERNAL_PREFIX_KEY: &str = "internal_prefix";
+
+const DEFAULT_FLUSH_EVERY_MS: Option<u64> = Some(500);
+
+/// Whether `sled` favours disk space or speed, as set with [`OpenConfig::mode`](struct.OpenConfig.html#method.mode).
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum SledMode {
+    /// Uses less disk space, compacting the database more aggressively. This is the default.
+    #[default]
+    LowSpace,
+    /// Writes faster, at the cost of more disk space.
+    HighThroughput,
+}
+
+impl From<SledMode> for sled::Mode {
+    fn from(mode: SledMode) -> sled::Mode {
+        match mode {
+            SledMode::LowSpace => sled::Mode::LowSpace,
+            SledMode::HighThroughput => sled::Mode::HighThroughput,
+        }
+    }
+}
+
+static INTERNAL_PREFIX: OnceLock<String> = OnceLock::new();
+
+/// Returns the prefix of every internal tree name. It is set once for the whole process,
+/// either by [`OpenConfig::open`](struct.OpenConfig.html#method.open) or by the first use of a database.
+pub(crate) fn internal_prefix() -> &'static str {
+    INTERNAL_PREFIX.get_or_init(|| String::from(DEFAULT_INTERNAL_PREFIX))
+}
+
+/// Returns the name of the internal tree `name`.
+pub(crate) fn internal_tree_name(name: &str) -> String {
+    format!("{}{}", internal_prefix(), name)
+}
+
+/// Records the internal prefix in the meta tree of a database, failing with an `IntegrityError`
+/// if the database was created with another prefix, whose meta tree records it as well.
+pub(crate) fn check_internal_prefix(db: &Db) -> Result<()> {
+    let prefix = internal_prefix();
+    for name in db.tree_names() {
+        let other_prefix = match name.strip_suffix(b"meta".as_slice()) {
+            Some(other_prefix) if !other_prefix.is_empty() && other_prefix != prefix.as_bytes() => {
+                other_prefix
+            }
+            _ => continue,
+        };
+        if db.open_tree(&name)?.get(INTERNAL_PREFIX_KEY)?.as_deref() == Some(other_prefix) {
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Cannot use internal tree prefix {} : the database was created with prefix {}",
+                    prefix,
+                    String::from_utf8_lossy(other_prefix)
+                ),
+            ));
+        }
+    }
+    // the result is ignored : an existing record holds the same prefix
+    let _ = db.open_tree(internal_tree_name("meta"))?.compare_and_swap(
+        INTERNAL_PREFIX_KEY,
+        None as Option<&[u8]>,
+        Some(prefix),
+    )?;
+    Ok(())
+}
+
+/// Options used to open a database.
+///
+/// ### Example
+/// ```rust,ignore
+/// let db = OpenConfig::new("./my-db")
+///     .internal_prefix("__reindeer$")
+///     .bincode_varint(true)
+///     .flush_every_ms(Some(100))
+///     .mode(SledMode::HighThroughput)
+///     .cache_capacity(256 * 1024 * 1024)
+///     .open()?;
+/// ```
+pub struct OpenConfig {
+    path: PathBuf,
+    internal_prefix: String,
+    bincode_varint: Option<bool>,
+    bincode_limit: Option<u64>,
+    flush_every_ms: Option<u64>,
+    mode: SledMode,
+    cache_capacity: Option<u64>,
+}
+
+impl OpenConfig {
+    /// Creates a configuration with default options for a database stored at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> OpenConfig {
+        OpenConfig {
+            path: path.as_ref().to_path_buf(),
+            internal_prefix: String::from(DEFAULT_INTERNAL_PREFIX),
+            bincode_varint: None,
+            bincode_limit: None,
+            flush_every_ms: DEFAULT_FLUSH_EVERY_MS,
+            mode: SledMode::default(),
+            cache_capacity: None,
+        }
+    }
+
+    /// Sets the prefix of the trees `reindeer` uses internally, `__$` by default.
+    ///
+    /// Registering an entity whose store name starts with this prefix results in an `IntegrityError`,
+    /// so that user data can never collide with relation or family descriptors.
+    ///
+    /// ⚠ The prefix is shared by every database of the process, and can only be set once :
+    /// it must be set before any database is used, and opening a database with a different prefix
+    /// afterwards results in an `IntegrityError`. The prefix is recorded in the database, and opening
+    /// or registering entities in a database created with another prefix results in an `IntegrityError` as well.
+    pub fn internal_prefix(mut self, prefix: &str) -> OpenConfig {
+        self.internal_prefix = String::from(prefix);
+        self
+    }
+
+    /// Sets whether entities are stored with a variable-length integer encoding, which is more compact
+    /// for stores full of small integers. Fixed-length integers are used by default.
+    ///
+    /// The encoding is recorded in the database. ⚠ Changing it for a database that already contains
+    /// entities results in an `IntegrityError` when opening it, since they could not be read anymore.
+    /// It cannot be set for JSON-backed databases.
+    pub fn bincode_varint(mut self, varint: bool) -> OpenConfig {
+        self.bincode_varint = Some(varint);
+        self
+    }
+
+    /// Sets the maximum size of an entity, in bytes. Saving or reading a larger entity results
+    /// in a `SerializationError`, which guards against absurd payloads, for instance in untrusted imports.
+    ///
+    /// The limit is recorded in the database, and can be raised or lowered every time the database is opened.
+    /// It cannot be set for JSON-backed databases.
+    pub fn bincode_limit(mut self, limit: u64) -> OpenConfig {
+        self.bincode_limit = Some(limit);
+        self
+    }
+
+    /// Sets how often `sled` flushes the database to disk in the background, every 500ms by default.
+    /// `None` disables background flushes.
+    ///
+    /// Writes are only durable once flushed : if the process crashes or is killed, every change made since
+    /// the last flush is lost, but the database stays consistent. Flush explicitly with `db.flush()`,
+    /// or use [`save_durable`](entity/trait.Entity.html#method.save_durable), when a change must not be lost,
+    /// and flush before closing the application (which a [`Reindeer`](struct.Reindeer.html) handle does when dropped).
+    pub fn flush_every_ms(mut self, every_ms: Option<u64>) -> OpenConfig {
+        self.flush_every_ms = every_ms;
+        self
+    }
+
+    /// Sets whether `sled` favours disk space or speed, `SledMode::LowSpace` by default.
+    pub fn mode(mut self, mode: SledMode) -> OpenConfig {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the maximum size of the in-memory page cache of `sled`, in bytes, 1GB by default.
+    pub fn cache_capacity(mut self, capacity: u64) -> OpenConfig {
+        self.cache_capacity = Some(capacity);
+        self
+    }
+
+    /// Opens the database with this configuration.
+    pub fn open(self) -> Result<Db> {
+        if self.internal_prefix.is_empty() {
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                String::from("The internal tree prefix cannot be empty"),
+            ));
+        }
+        let prefix = INTERNAL_PREFIX.get_or_init(|| self.internal_prefix.clone());
+        if *prefix != self.internal_prefix {
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Cannot use internal tree prefix {} : prefix {} is already in use",
+                    self.internal_prefix, prefix
+                ),
+            ));
+        }
+        let mut config = sled::Config::new()
+            .path(self.path)
+            .flush_every_ms(self.flush_every_ms)
+            .mode(self.mode.into());
+        if let Some(capacity) = self.cache_capacity {
+            config = config.cache_capacity(capacity);
+        }
+        let db = config.open()?;
+        check_internal_prefix(&db)?;
+        if self.bincode_varint.is_some() || self.bincode_limit.is_some() {
+            // options that are not set keep their recorded value
+            let (varint, limit) = match StorageFormat::of(&db)? {
+                StorageFormat::BincodeWith { varint, limit } => (varint, limit),
+                _ => (false, None),
+            };
+            set_bincode_options(
+                &db,
+                self.bincode_varint.unwrap_or(varint),
+                self.bincode_limit.or(limit),
+            )?;
+        }
+        Ok(db)
+    }
+}
@@ -2,18 +2,38 @@
 //! This module provides the `Entity` trait as well as other utilities to manipulate entities and entity stores.
 //! For relation-related definitions, take a look a the [`relation` module](relation/index.html).
 
-use std::{fs::File, mem::size_of};
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::{
+    cell::Cell,
+    collections::{hash_map::RandomState, BTreeSet, HashMap, HashSet},
+    fs::File,
+    hash::{BuildHasher, Hasher},
+    mem::size_of,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use crate::batch::TreeBatches;
+#[cfg(feature = "changelog")]
+use crate::changelog::ChangeOp;
+use crate::config::{check_internal_prefix, internal_prefix, internal_tree_name};
 use crate::error::Result;
-use crate::relation::{DeletionBehaviour, EntityRelations, FamilyDescriptor, Relation};
+#[cfg(feature = "metrics")]
+use crate::instrument::{self, OperationTimer};
+use crate::relation::{DeletionBehaviour, FamilyDescriptor, Relation, RelationBuilder};
+use crate::storage::StorageFormat;
+use crate::{Error, ErrorKind};
 use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::{Deserialize, Serialize};
+use sled::transaction::ConflictableTransactionError;
 use sled::{Batch, Db, IVec, Tree};
 use std::convert::TryInto;
 
 /// The `Entity` trait provides document store capabilities for any struct that implements it.
 ///
 /// ### Example
-/// ```rust
+/// ```rust,ignore
 /// use reindeer::{Entity, Serialize,Deserialize,open};
 ///
 /// #[derive(Serialize,Deserialize)]
@@ -33,12 +53,12 @@ use std::convert::TryInto;
 /// }
 /// ```
 ///
-/// ```rust
+/// ```rust,ignore
 /// let db = open("./my-db")?;
 /// let my_struct = MyStruct { key : 2 , prop1 : String::from("Hello, World!")};
 /// my_struct.save(&db)?;
 /// ```
-/// ```rust
+/// ```rust,ignore
 /// let my_struct_0 = MyStruct::get(&2,&db)?;
 /// ```
 ///
@@ -54,6 +74,8 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///  - `u64`
     ///  - `i32`
     ///  - `i64`
+    ///  - `f32` and `f64` (encoded so that keys sort in numeric order)
+    ///  - `[u8; N]`
     type Key: AsBytes + Clone;
 
     /// The name of the store, as a string.
@@ -61,7 +83,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// A recommendation is to return the name of the struct in `snake_case`.
     /// ### Example
-    /// ```rust
+    /// ```rust,ignore
     /// impl Entity for MyStruct {
     ///     fn store_name() -> &'static str {
     ///         "my_struct"
@@ -73,7 +95,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// A function that returns a reference to the key for this entity instance.
     ///
     /// ### Example
-    /// ```rust
+    /// ```rust,ignore
     /// impl Entity for MyStruct {
     ///     fn get_key(&self) -> &Self::Key {
     ///         &self.key
@@ -89,7 +111,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// [`save_next`](entity/trait.AutoIncrementEntity.html#tymethod.save_next)
     ///
     /// ### Example
-    /// ```rust
+    /// ```rust,ignore
     /// impl Entity for MyStruct {
     ///     fn set_key(&mut self, key : &Self::Key) {
     ///         self.key = key.clone();
@@ -109,7 +131,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// **not** bet set to `DeletionBehaviour::Error` to avoid creating a deadlock.
     ///
     /// ### Example
-    /// ```rust
+    /// ```rust,ignore
     /// impl Entity for MyStruct {
     ///     fn get_sibling_trees() -> Vec<(&'static str, DeletionBehaviour)> {
     ///         vec![
@@ -123,6 +145,31 @@ pub trait Entity: Serialize + DeserializeOwned {
         Vec::new()
     }
 
+    /// A function that returns additional sibling trees for this particular instance,
+    /// as well as the [`DeletionBehaviour`](relation/enum.DeletionBehaviour.html) to apply to them.
+    ///
+    /// It is consulted each time the instance is saved, and the result is stored so that deletion
+    /// integrity checks use it along with [`get_sibling_trees`](entity/trait.Entity.html#method.get_sibling_trees).
+    /// If a store is declared by both, the behaviour from `get_sibling_trees` is used.
+    ///
+    /// This is useful for generic entities whose instantiations relate to different stores.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// impl<K: TokenKind> Entity for Token<K> {
+    ///     fn sibling_trees_for(&self) -> Vec<(&'static str, DeletionBehaviour)> {
+    ///         if K::NAME == "session" {
+    ///             vec![("scoped_login", DeletionBehaviour::Cascade)]
+    ///         } else {
+    ///             Vec::new()
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    fn sibling_trees_for(&self) -> Vec<(&'static str, DeletionBehaviour)> {
+        Vec::new()
+    }
+
     /// A function that returns the list of child trees as well as the
     /// [`DeletionBehaviour`](relation/enum.DeletionBehaviour.html) to use
     /// for the child instances of this instance if it is removed
@@ -136,7 +183,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// Entity implementation
     ///
     /// ### Example
-    /// ```rust
+    /// ```rust,ignore
     /// impl Entity for MyStruct {
     ///     fn get_child_trees() -> Vec<(&'static str, DeletionBehaviour)> {
     ///         vec![
@@ -149,21 +196,227 @@ pub trait Entity: Serialize + DeserializeOwned {
         Vec::new()
     }
 
+    /// A function that returns a tag discriminating this entity type from other entity types
+    /// sharing the same store, typically several instantiations of a generic entity.
+    ///
+    /// When a tag is provided, it is stored along with each saved value, and every read operation
+    /// only considers values of the same tag : `Token::<Session>::get_all` only returns sessions
+    /// even if `Token<Session>` and `Token<OIDCBearer>` share the `"token"` store. Saving or removing
+    /// a key that holds a value of another tag fails with an `IntegrityError`.
+    ///
+    /// ⚠ Every entity type sharing a store should provide a distinct tag. Tags must be
+    /// at most 255 bytes long : `register` and `save` fail with an `IntegrityError` otherwise.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// impl<K: TokenKind> Entity for Token<K> {
+    ///     fn type_tag() -> Option<&'static str> {
+    ///         Some(K::NAME)
+    ///     }
+    /// }
+    /// ```
+    fn type_tag() -> Option<&'static str> {
+        None
+    }
+
+    /// Converts a key to the bytes it is stored under. Defaults to the key's [`AsBytes`](entity/trait.AsBytes.html) representation.
+    ///
+    /// This can be overridden to customize how keys of this entity map to tree keys, for instance to
+    /// make `String` keys case-insensitive by lowercasing them.
+    ///
+    /// ⚠ Every method of this trait taking a `Self::Key` goes through this function, so that ordering stays consistent.
+    /// Methods taking raw prefixes or bounds (such as [`get_in_range`](entity/trait.Entity.html#method.get_in_range))
+    /// do not, and must be given encoded values. Siblings and children of the entity should use the same encoding.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// impl Entity for User {
+    ///     fn encode_key(key: &String) -> Vec<u8> {
+    ///         key.to_lowercase().into_bytes()
+    ///     }
+    /// }
+    /// ```
+    fn encode_key(key: &Self::Key) -> Vec<u8> {
+        match Self::CHILD_KEY_SEPARATOR {
+            Some(separator) => key.as_separated_bytes(separator),
+            None => key.as_bytes(),
+        }
+    }
+
+    /// Converts stored bytes back to a key, as the reverse of [`encode_key`](entity/trait.Entity.html#method.encode_key).
+    /// Defaults to the key's [`FromBytes`](entity/trait.FromBytes.html) representation.
+    ///
+    /// ⚠ Implementations must repeat the `where` clause of this method.
+    fn decode_key(bytes: &[u8]) -> Result<Self::Key>
+    where
+        Self::Key: FromBytes,
+    {
+        match Self::CHILD_KEY_SEPARATOR {
+            Some(separator) => Self::Key::from_separated_bytes(bytes, separator),
+            None => Self::Key::from_bytes(bytes),
+        }
+    }
+
+    /// Parses a key from its string representation, as produced by [`key_to_str`](entity/trait.Entity.html#method.key_to_str),
+    /// for instance a URL path segment. See [`KeyStr`](entity/trait.KeyStr.html) for the representation of each type.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// // GET /users/42/posts/42:3
+    /// let user = User::get(&User::key_from_str("42")?, &db)?;
+    /// let post = Post::get(&Post::key_from_str("42:3")?, &db)?;
+    /// ```
+    fn key_from_str(s: &str) -> Result<Self::Key>
+    where
+        Self::Key: KeyStr,
+    {
+        Self::Key::from_key_str(s)
+    }
+
+    /// Formats a key to a stable string representation, suitable for URLs.
+    /// See [`KeyStr`](entity/trait.KeyStr.html) for the representation of each type.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let url = format!("/posts/{}", Post::key_to_str(post.get_key()));
+    /// ```
+    fn key_to_str(key: &Self::Key) -> String
+    where
+        Self::Key: KeyStr,
+    {
+        key.to_key_str()
+    }
+
+    /// Whether modifications made by [`update`](entity/trait.Entity.html#method.update) and
+    /// [`filter_update`](entity/trait.Entity.html#method.filter_update) are checked not to change the entity's key.
+    ///
+    /// When `true`, a modification changing the value returned by `get_key` results in an `IntegrityError`
+    /// and the stored entity is left untouched. When `false` (the default), the entity is moved to its new key.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// impl Entity for MyStruct {
+    ///     const STRICT_KEYS: bool = true;
+    /// }
+    /// ```
+    const STRICT_KEYS: bool = false;
+
+    /// The maximum size of a stored entity of this store, in bytes, or `None` (the default) for no limit.
+    ///
+    /// Saving an entity (including through [`update`](entity/trait.Entity.html#method.update), imports or
+    /// [`merge_save`](entity/trait.Mergeable.html#method.merge_save)) whose serialized value is larger results in an
+    /// `IntegrityError`, and nothing is written. A merge is checked against the entity stored when it is called :
+    /// if concurrent merges make the result too large, it is dropped instead. This keeps rogue
+    /// inputs, such as huge user-provided content, from being persisted. Unlike the database-wide
+    /// [`bincode_limit`](struct.OpenConfig.html#method.bincode_limit), it does not prevent reading existing entities.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// impl Entity for Comment {
+    ///     const MAX_VALUE_SIZE: Option<usize> = Some(64 * 1024);
+    /// }
+    /// ```
+    const MAX_VALUE_SIZE: Option<usize> = None;
+
+    /// A byte inserted between the members of tuple keys, typically between the parent key and the
+    /// child index of child entities. `None` (the default) concatenates them without separator.
+    ///
+    /// Children are told apart from the children of a parent whose key starts with their parent's key
+    /// (such as `"id1"` and `"id10"`) by the length of their keys, with or without separator. A separator
+    /// additionally keeps the children of a parent next to each other in key order, before the children of `"id10"`.
+    ///
+    /// ⚠ Every descendant store of a parent (children, grandchildren...) should use the same separator,
+    /// and parent keys should not end with it. Keys stored without separator can still be read,
+    /// and can be converted with [`migrate_child_keys`](entity/trait.Entity.html#method.migrate_child_keys).
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// impl Entity for Comment {
+    ///     type Key = (String, u32);
+    ///     const CHILD_KEY_SEPARATOR: Option<u8> = Some(0x1F);
+    /// }
+    /// ```
+    const CHILD_KEY_SEPARATOR: Option<u8> = None;
+
+    /// The version of the entity's struct, to increase each time its serialized form changes.
+    ///
+    /// Every saved entity is stored along with this version. When an entity saved with an older version
+    /// is read with [`get`](entity/trait.Entity.html#method.get), it is upgraded with
+    /// [`migrate`](entity/trait.Entity.html#method.migrate) and saved again, so that entities are
+    /// migrated lazily, as they are accessed.
+    ///
+    /// ⚠ Other reads (such as [`get_all`](entity/trait.Entity.html#method.get_all)) do not migrate entities.
+    ///
+    /// Only available with the `lazy-migration` feature. Entities saved before a version is declared are version 0.
+    #[cfg(feature = "lazy-migration")]
+    const VERSION: u32 = 0;
+
+    /// Upgrades an entity saved with an older `version` of the struct, from its serialized form.
+    ///
+    /// `bytes` are encoded with `bincode`, or JSON for JSON-backed databases, and are typically
+    /// deserialized into the previous version of the struct, which is then converted.
+    /// The default implementation fails with a `SerializationError`.
+    ///
+    /// Only available with the `lazy-migration` feature.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// impl Entity for User {
+    ///     const VERSION: u32 = 1;
+    ///
+    ///     fn migrate(version: u32, bytes: &[u8]) -> Result<Self> {
+    ///         let old: UserV0 = bincode::deserialize(bytes)?;
+    ///         Ok(User { id: old.id, name: old.name, email: String::new() })
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "lazy-migration")]
+    fn migrate(version: u32, bytes: &[u8]) -> Result<Self> {
+        let _ = bytes;
+        Err(Error::new(
+            ErrorKind::SerializationError,
+            format!(
+                "No migration from version {} for {}",
+                version,
+                Self::store_name()
+            ),
+        ))
+    }
+
     /// Call this function once the database is opened on each Entity that you want to use.
     /// This is necessary to provide safe and type-agnostic deletion mechanisms.
     ///
     /// ⚠ If this function is not called, deleting an entity of that type will result in an error.
     ///
+    /// ⚠ Store names starting with the internal tree prefix (`__$` by default, see
+    /// [`OpenConfig::internal_prefix`](config/struct.OpenConfig.html#method.internal_prefix)) are reserved,
+    /// and registering such an entity results in an `IntegrityError`.
+    ///
+    /// ⚠ The [`type_tag`](entity/trait.Entity.html#method.type_tag) of the entity type is recorded along with its store name.
+    /// Registering two entity types with the same store name and the same type tag (or without type tag) in the same process,
+    /// or mixing entity types with and without type tag in a store, results in an `IntegrityError`.
+    /// Renaming an entity type or moving it to another module is allowed.
+    ///
     /// ### Example
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// impl Entity for MyStruct { /* ... */}
     /// ```
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// MyStruct::register(&db)?;
     /// ```
     fn register(db: &Db) -> Result<()> {
+        if Self::store_name().starts_with(internal_prefix()) {
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Store name {} starts with the prefix {}, which is reserved for internal trees",
+                    Self::store_name(),
+                    internal_prefix()
+                ),
+            ));
+        }
         let desc = FamilyDescriptor {
             tree_name: String::from(Self::store_name()),
             child_trees: Self::get_child_trees()
@@ -175,23 +428,387 @@ pub trait Entity: Serialize + DeserializeOwned {
                 .map(|e| (String::from(e.0), e.1))
                 .collect(),
         };
+        Self::type_tag_len()?;
+        check_internal_prefix(db)?;
+        Self::claim_store_name(db)?;
+        Relation::save_child_key_separator(Self::store_name(), Self::CHILD_KEY_SEPARATOR, db)?;
         desc.save(db)?;
         Ok(())
     }
 
+    /// Compares the sibling and child stores recorded when this entity was last registered with the ones it
+    /// currently declares (see [`get_sibling_trees`](entity/trait.Entity.html#method.get_sibling_trees) and
+    /// [`get_child_trees`](entity/trait.Entity.html#method.get_child_trees)), without registering it.
+    ///
+    /// If the entity was never registered, every declared store is reported as added.
+    fn registration_diff(db: &Db) -> Result<RegistrationDiff> {
+        let recorded =
+            FamilyDescriptor::get(&String::from(Self::store_name()), db)?.unwrap_or_default();
+        let mut diff = RegistrationDiff::default();
+        diff.compare(
+            FamilyRelation::Sibling,
+            &recorded.sibling_trees,
+            &Self::get_sibling_trees(),
+        );
+        diff.compare(
+            FamilyRelation::Child,
+            &recorded.child_trees,
+            &Self::get_child_trees(),
+        );
+        Ok(diff)
+    }
+
+    /// Registers this entity like [`register`](entity/trait.Entity.html#method.register), and returns how its sibling
+    /// and child stores differ from the ones recorded by its previous registration (see
+    /// [`registration_diff`](entity/trait.Entity.html#method.registration_diff)).
+    ///
+    /// Since deleting an entity follows the recorded definitions, a non-empty difference means that deleting
+    /// existing entities will behave differently from now on.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let diff = MyStruct::reconcile_registration(&db)?;
+    /// for (relation, store, from, to) in &diff.changed {
+    ///     log::warn!("{:?} store {} now uses {:?} instead of {:?}", relation, store, to, from);
+    /// }
+    /// ```
+    fn reconcile_registration(db: &Db) -> Result<RegistrationDiff> {
+        let diff = Self::registration_diff(db)?;
+        Self::register(db)?;
+        Ok(diff)
+    }
+
+    /// Registers one instantiation of a generic entity type, such as `Token<Session>`, whose instantiations
+    /// share the same store and are told apart by their [`type_tag`](entity/trait.Entity.html#method.type_tag).
+    ///
+    /// Unlike [`register`](entity/trait.Entity.html#method.register), which replaces the sibling and child stores
+    /// recorded for the store, this adds the ones declared by this instantiation to those declared by the
+    /// instantiations registered before it, so that deleting any entity of the store handles all of them.
+    /// The type tags of every registered instantiation are listed by [`registered_type_tags`](entity/trait.Entity.html#method.registered_type_tags).
+    ///
+    /// Registering a type without a type tag this way results in an `IntegrityError`.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// Token::<Session>::register_generic(&db)?;
+    /// Token::<Bearer>::register_generic(&db)?;
+    /// ```
+    fn register_generic(db: &Db) -> Result<()> {
+        if Self::type_tag().is_none() {
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Cannot register {} as a generic entity : it has no type tag",
+                    std::any::type_name::<Self>()
+                ),
+            ));
+        }
+        let previous = FamilyDescriptor::get(&String::from(Self::store_name()), db)?;
+        Self::register(db)?;
+        if let Some(previous) = previous {
+            let mut desc =
+                FamilyDescriptor::get(&String::from(Self::store_name()), db)?.unwrap_or_default();
+            for (tree_name, behaviour) in previous.sibling_trees {
+                if !desc
+                    .sibling_trees
+                    .iter()
+                    .any(|(name, _)| *name == tree_name)
+                {
+                    desc.sibling_trees.push((tree_name, behaviour));
+                }
+            }
+            for (tree_name, behaviour) in previous.child_trees {
+                if !desc.child_trees.iter().any(|(name, _)| *name == tree_name) {
+                    desc.child_trees.push((tree_name, behaviour));
+                }
+            }
+            desc.save(db)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the type tags of the entity types registered with this entity's store name, in registration order.
+    /// Entity types without a type tag are not listed.
+    fn registered_type_tags(db: &Db) -> Result<Vec<String>> {
+        let claims: Vec<StoreClaim> = match db
+            .open_tree(internal_tree_name("store_types"))?
+            .get(Self::store_name())?
+        {
+            Some(vec) => bincode::deserialize(&vec)?,
+            None => Vec::new(),
+        };
+        Ok(claims.into_iter().filter_map(|claim| claim.tag).collect())
+    }
+
+    /// Records that this entity type uses its store, failing with an `IntegrityError` if another
+    /// entity type already uses it without a distinct type tag.
+    ///
+    /// Claims are identified by their type tag, which is stable. Type names are not stable across builds,
+    /// so they are only compared between entity types registered by the same process.
+    #[doc(hidden)]
+    fn claim_store_name(db: &Db) -> Result<()> {
+        let tree = db.open_tree(internal_tree_name("store_types"))?;
+        let type_name = std::any::type_name::<Self>();
+        let tag = Self::type_tag().map(String::from);
+        loop {
+            let stored = tree.get(Self::store_name())?;
+            let mut claims: Vec<StoreClaim> = match &stored {
+                Some(vec) => bincode::deserialize(vec)?,
+                None => Vec::new(),
+            };
+            let other = match claims.iter_mut().find(|claim| claim.tag == tag) {
+                Some(claim) if claim.process == process_id() && claim.type_name != type_name => {
+                    Some(claim.type_name.clone())
+                }
+                Some(claim) => {
+                    claim.process = process_id();
+                    claim.type_name = String::from(type_name);
+                    None
+                }
+                None => match claims
+                    .iter()
+                    .find(|claim| tag.is_none() || claim.tag.is_none())
+                {
+                    Some(claim) => Some(claim.type_name.clone()),
+                    None => {
+                        claims.push(StoreClaim {
+                            tag: tag.clone(),
+                            process: process_id(),
+                            type_name: String::from(type_name),
+                        });
+                        None
+                    }
+                },
+            };
+            if let Some(other) = other {
+                return Err(Error::new(
+                    ErrorKind::IntegrityError,
+                    format!(
+                        "Cannot register {} : store {} is already used by {}",
+                        type_name,
+                        Self::store_name(),
+                        other
+                    ),
+                ));
+            }
+            if tree
+                .compare_and_swap(
+                    Self::store_name(),
+                    stored,
+                    Some(bincode::serialize(&claims)?),
+                )?
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+
     #[doc(hidden)]
     fn get_tree(db: &Db) -> Result<Tree> {
         Ok(db.open_tree(Self::store_name())?)
     }
 
+    /// Opens the entity's tree to read from it, checking that the entity is registered
+    /// according to the [registration check](fn.set_registration_check.html) in effect.
+    #[doc(hidden)]
+    fn read_tree(db: &Db) -> Result<Tree> {
+        Self::check_registered(RegistrationCheck::current(), db)?;
+        Self::get_tree(db)
+    }
+
+    #[doc(hidden)]
+    fn check_registered(check: RegistrationCheck, db: &Db) -> Result<()> {
+        if check == RegistrationCheck::Lenient
+            || Self::store_name().starts_with(internal_prefix())
+            || db
+                .open_tree(FamilyDescriptor::store_name())?
+                .contains_key(Self::store_name())?
+        {
+            return Ok(());
+        }
+        if check == RegistrationCheck::Error {
+            return Err(Error::new(
+                ErrorKind::UnregisteredEntity,
+                format!("Reading unregistered entity {}", Self::store_name()),
+            ));
+        }
+        #[cfg(feature = "tracing")]
+        tracing::warn!(store = Self::store_name(), "reading unregistered entity");
+        Ok(())
+    }
+
+    #[doc(hidden)]
+    fn from_ivec(key: &[u8], vec: &[u8], format: StorageFormat) -> Result<Self> {
+        format
+            .deserialize::<Self>(Self::payload(vec))
+            .map_err(|error| {
+                Error::new(
+                    ErrorKind::SerializationError,
+                    format!(
+                        "Could not read a {} from {} bytes with key {:?} in store {} : {}",
+                        std::any::type_name::<Self>(),
+                        vec.len(),
+                        key,
+                        Self::store_name(),
+                        error
+                    ),
+                )
+            })
+    }
+
+    #[doc(hidden)]
+    fn decode_entries(
+        entries: impl Iterator<Item = sled::Result<(IVec, IVec)>>,
+        format: StorageFormat,
+    ) -> Result<Vec<Self>> {
+        let mut result = Vec::new();
+        for elem in entries {
+            let (key, vec) = elem?;
+            if Self::has_own_tag(&vec) {
+                result.push(Self::from_ivec(&key, &vec, format)?);
+            }
+        }
+        Ok(result)
+    }
+
+    #[doc(hidden)]
+    fn decode_entries_with_policy(
+        entries: impl Iterator<Item = sled::Result<(IVec, IVec)>>,
+        format: StorageFormat,
+        policy: ReadErrorPolicy,
+    ) -> Result<(Vec<Self>, ReadErrors)> {
+        if policy == ReadErrorPolicy::Abort {
+            return Ok((Self::decode_entries(entries, format)?, Vec::new()));
+        }
+        let mut result = Vec::new();
+        let mut errors = Vec::new();
+        for elem in entries {
+            let (key, vec) = elem?;
+            if !Self::has_own_tag(&vec) {
+                continue;
+            }
+            match Self::from_ivec(&key, &vec, format) {
+                Ok(entity) => result.push(entity),
+                #[cfg(feature = "tracing")]
+                Err(error) if policy == ReadErrorPolicy::Skip => tracing::warn!(
+                    store = Self::store_name(),
+                    key = ?key.as_ref(),
+                    %error,
+                    "skipping unreadable entity"
+                ),
+                #[cfg(not(feature = "tracing"))]
+                Err(_) if policy == ReadErrorPolicy::Skip => {}
+                Err(error) => errors.push((key.to_vec(), error)),
+            }
+        }
+        Ok((result, errors))
+    }
+
+    #[doc(hidden)]
+    fn payload(vec: &[u8]) -> &[u8] {
+        match Self::type_tag() {
+            Some(tag) => &vec[1 + tag.len()..],
+            None => vec,
+        }
+    }
+
+    #[doc(hidden)]
+    #[cfg_attr(not(feature = "lazy-migration"), allow(unused_variables))]
+    fn record_version(key: &[u8], db: &Db) -> Result<()> {
+        #[cfg(feature = "lazy-migration")]
+        crate::migration::record_version(Self::store_name(), key, Self::VERSION, db)?;
+        Ok(())
+    }
+
+    #[doc(hidden)]
+    fn type_tag_len() -> Result<Option<u8>> {
+        match Self::type_tag() {
+            Some(tag) => u8::try_from(tag.len()).map(Some).map_err(|_| {
+                Error::new(
+                    ErrorKind::IntegrityError,
+                    format!(
+                        "Type tag {} of {} is {} bytes long : the maximum length is 255 bytes",
+                        tag,
+                        Self::store_name(),
+                        tag.len()
+                    ),
+                )
+            }),
+            None => Ok(None),
+        }
+    }
+
+    #[doc(hidden)]
+    fn to_ivec(&self, format: StorageFormat) -> Result<IVec> {
+        let payload = format.serialize(self)?;
+        let vec = match (Self::type_tag(), Self::type_tag_len()?) {
+            (Some(tag), Some(len)) => IVec::from([&[len], tag.as_bytes(), &payload[..]].concat()),
+            _ => IVec::from(payload),
+        };
+        match Self::MAX_VALUE_SIZE {
+            Some(max) if vec.len() > max => Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Cannot save an entity of {} bytes in {} : the maximum size is {} bytes",
+                    vec.len(),
+                    Self::store_name(),
+                    max
+                ),
+            )),
+            _ => Ok(vec),
+        }
+    }
+
+    #[doc(hidden)]
+    fn has_own_tag(vec: &[u8]) -> bool {
+        match (Self::type_tag(), Self::type_tag_len()) {
+            (Some(tag), Ok(Some(len))) => {
+                vec.first() == Some(&len) && vec.get(1..1 + tag.len()) == Some(tag.as_bytes())
+            }
+            (Some(_), _) => false,
+            (None, _) => true,
+        }
+    }
+
+    #[doc(hidden)]
+    fn check_own_tag(key: &[u8], stored: Option<&[u8]>) -> Result<()> {
+        match stored {
+            Some(vec) if !Self::has_own_tag(vec) => Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Key {:?} of {} holds an entity of another type",
+                    key,
+                    Self::store_name()
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
     #[doc(hidden)]
-    fn from_ivec(vec: IVec) -> Self {
-        bincode::deserialize::<Self>(vec.as_ref()).unwrap()
+    fn insert_own(tree: &Tree, key: &[u8], value: IVec) -> Result<Option<IVec>> {
+        if Self::type_tag().is_none() {
+            return Ok(tree.insert(key, value)?);
+        }
+        loop {
+            let current = tree.get(key)?;
+            Self::check_own_tag(key, current.as_deref())?;
+            if tree
+                .compare_and_swap(key, current.clone(), Some(value.clone()))?
+                .is_ok()
+            {
+                return Ok(current);
+            }
+        }
     }
 
     #[doc(hidden)]
-    fn to_ivec(&self) -> IVec {
-        IVec::from(bincode::serialize(self).unwrap())
+    fn check_removable(key: &[u8], db: &Db) -> Result<()> {
+        if Self::type_tag().is_some() {
+            Self::check_own_tag(key, Self::get_tree(db)?.get(key)?.as_deref())?;
+        }
+        Ok(())
     }
 
     /// Retrieves an entity instance given its key.
@@ -200,13 +817,36 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// if let Some(my_struct_4) = MyStruct::get(&4,&db)? {
     ///     /* ... */
     /// }
     /// ```
     fn get(key: &Self::Key, db: &Db) -> Result<Option<Self>> {
-        Self::get_from_u8_array(&key.as_bytes(), db)
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET, Self::store_name());
+        Self::get_from_u8_array(&Self::encode_key(key), db)
+    }
+
+    /// Retrieves an entity instance given its key, or its default value if the key does not exist.
+    ///
+    /// Contrary to [`get_or_create_sibling`](entity/trait.Entity.html#method.get_or_create_sibling),
+    /// the default value is not saved to the database. Its key is set to `key`.
+    ///
+    /// ### Example
+    ///
+    /// ```rust,ignore
+    /// let settings = Settings::get_or_default(&user_id, &db)?;
+    /// ```
+    fn get_or_default(key: &Self::Key, db: &Db) -> Result<Self>
+    where
+        Self: Default,
+    {
+        Ok(Self::get(key, db)?.unwrap_or_else(|| {
+            let mut entity = Self::default();
+            entity.set_key(key);
+            entity
+        }))
     }
 
     /// Retrieves all entities of a given type.
@@ -217,127 +857,389 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// let entities = MyStruct::get_all(&db)?;
     /// ```
     fn get_all(db: &Db) -> Result<Vec<Self>> {
-        Ok(Self::get_tree(db)?
-            .iter()
-            .map(|elem| Self::from_ivec(elem.unwrap().1))
-            .collect())
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_ALL, Self::store_name());
+        Self::decode_entries(Self::read_tree(db)?.iter(), StorageFormat::of(db)?)
     }
 
-    /// Returns the number of saved instances for this entity type.
+    /// Same as [`get_all`](entity/trait.Entity.html#method.get_all), handling entities that cannot be read
+    /// (for instance because they are corrupted) according to `policy` :
+    ///  - `ReadErrorPolicy::Abort` fails on the first unreadable entity, like `get_all`
+    ///  - `ReadErrorPolicy::Skip` ignores unreadable entities, emitting a `tracing` warning with their keys
+    ///    (silently without the `tracing` feature)
+    ///  - `ReadErrorPolicy::Collect` returns the encoded key and error of each unreadable entity along with the readable ones
+    ///
+    /// The list of errors is always empty unless `policy` is `ReadErrorPolicy::Collect`.
     ///
     /// ### Example
-    /// ```rust
-    /// let count = MyStruct::get_count()?;
+    /// ```rust,ignore
+    /// let (users, errors) = User::get_all_with_policy(ReadErrorPolicy::Collect, &db)?;
+    /// for (key, error) in errors {
+    ///     println!("Could not read user {:?} : {}", key, error);
+    /// }
     /// ```
-    fn get_count(db: &Db) -> Result<usize> {
-        Ok(Self::get_tree(db)?.len())
-    }
-
-    #[doc(hidden)]
-    fn get_from_u8_array(key: &[u8], db: &Db) -> Result<Option<Self>> {
-        Ok(Self::get_tree(db)?
-            .get(key)?
-            .map(|vec| Self::from_ivec(vec)))
+    fn get_all_with_policy(policy: ReadErrorPolicy, db: &Db) -> Result<(Vec<Self>, ReadErrors)> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_ALL, Self::store_name());
+        Self::decode_entries_with_policy(
+            Self::read_tree(db)?.iter(),
+            StorageFormat::of(db)?,
+            policy,
+        )
     }
 
-    #[doc(hidden)]
-    fn get_with_prefix(key: &impl AsBytes, db: &Db) -> Result<Vec<Self>> {
-        Ok(Self::get_tree(db)?
-            .scan_prefix(key.as_bytes())
-            .map(|elem| Self::from_ivec(elem.unwrap().1))
-            .collect())
-    }
-
-    /// Gets entities in a range of keys with a min and max values
-    /// This can be especially useful when keys are integral types,
-    /// but any key will work.
+    /// Retrieves all entities of a given type as projections : smaller structs `P` holding a subset of their fields.
+    ///
+    /// ⚠ This is only possible for JSON-backed databases (see [`open_json_backed`](fn.open_json_backed.html)),
+    /// since fields are matched by name, which requires a self-describing format : `bincode` values only hold
+    /// field values, in declaration order. Calling it on a `bincode` database results in a `SerializationError`.
     ///
     /// ### Example
-    /// ```rust
-    /// let entities = MyStruct::get_in_range(10,30,&db)?;
+    ///
+    /// ```rust,ignore
+    /// #[derive(Deserialize)]
+    /// struct UserName { name: String }
+    /// let names = User::get_all_as::<UserName>(&db)?;
     /// ```
-    fn get_in_range(start: impl AsBytes, end: impl AsBytes, db: &Db) -> Result<Vec<Self>> {
-        Ok(Self::get_tree(db)?
-            .range(start.as_bytes()..end.as_bytes())
-            .map(|elem| Self::from_ivec(elem.unwrap().1))
-            .collect())
+    fn get_all_as<P: DeserializeOwned>(db: &Db) -> Result<Vec<P>> {
+        if StorageFormat::of(db)? != StorageFormat::Json {
+            return Err(Error::new(
+                ErrorKind::SerializationError,
+                format!(
+                    "Cannot read projections of {} : the database is not JSON-backed",
+                    Self::store_name()
+                ),
+            ));
+        }
+        let mut result = Vec::new();
+        for elem in Self::read_tree(db)?.iter() {
+            let (_, vec) = elem?;
+            if Self::has_own_tag(&vec) {
+                result.push(serde_json::from_slice(Self::payload(&vec))?);
+            }
+        }
+        Ok(result)
     }
 
-    /// Gets `count` entities starting at the instance at index `start` in the given store
+    /// Retrieves the keys of all entities of a given type, in key order, without deserializing the entities.
     ///
     /// ### Example
-    /// ```rust
-    /// let entities = MyStruct::get_from_start(10,20,None,&db)?;
-    /// ```
-    /// ## Child entities
     ///
-    /// A parent key can be supplied for child entities, to consider only children of a given parent.
+    /// ```rust,ignore
+    /// let ids = MyStruct::all_keys(&db)?;
+    /// ```
+    fn all_keys(db: &Db) -> Result<Vec<Self::Key>>
+    where
+        Self::Key: FromBytes,
+    {
+        Self::decode_keys(Self::read_tree(db)?.iter())
+    }
+
+    /// Retrieves the keys of all child entities of a given parent key, in key order,
+    /// without deserializing the entities.
     ///
     /// ### Example
-    /// ```rust
-    /// let entities = MyStruct::get_from_start(10,20,Some(parent.get_key().to_owned()),&db)?;
+    ///
+    /// ```rust,ignore
+    /// let comment_ids = Comment::child_keys_of(post.get_key(), &db)?;
     /// ```
-    fn get_from_start(
-        start: usize,
-        count: usize,
-        parent: Option<impl AsBytes>,
-        db: &Db,
-    ) -> Result<Vec<Self>> {
-        let mut iter = if let Some(prefix) = parent {
-            Self::get_tree(db)?.scan_prefix(prefix.as_bytes())
-        } else {
-            Self::get_tree(db)?.iter()
-        };
+    fn child_keys_of<P: AsBytes>(parent: &P, db: &Db) -> Result<Vec<Self::Key>>
+    where
+        Self::Key: FromBytes,
+    {
+        Self::decode_keys(child_entries(
+            &Self::read_tree(db)?,
+            Self::child_prefix(parent),
+        ))
+    }
+
+    #[doc(hidden)]
+    fn decode_keys(
+        entries: impl Iterator<Item = sled::Result<(IVec, IVec)>>,
+    ) -> Result<Vec<Self::Key>>
+    where
+        Self::Key: FromBytes,
+    {
         let mut result = Vec::new();
-        for i in 0..(start + count) {
-            match iter.next() {
-                Some(e) => {
-                    if i >= start {
-                        result.push(Self::from_ivec(e.unwrap().1));
-                    }
-                }
-                None => return Ok(result),
+        for elem in entries {
+            let (key, vec) = elem?;
+            if Self::has_own_tag(&vec) {
+                result.push(Self::decode_key(&key)?);
             }
         }
         Ok(result)
     }
 
-    /// Gets `count` entities starting at the instance at index `start` from the end of a given store
-    /// Same as `get_from_start`, but starting at the end of the store.
-    ///
-    /// ### Example
-    /// ```rust
-    /// let entities = MyStruct::get_from_end(10,20,None,&db)?;
-    /// ```
-    /// ## Child entities
+    /// Calls `f` on consecutive chunks of at most `chunk_size` entities of this store, in key order,
+    /// so that the whole store can be processed without loading it in memory at once.
     ///
-    /// A parent key can be supplied for child entities, to consider only children of a given parent.
+    /// If `f` returns an error, iteration stops and the error is returned.
+    /// A `chunk_size` of 0 is treated as 1.
     ///
     /// ### Example
-    /// ```rust
-    /// let entities = MyStruct::get_from_end(10,20,Some(parent.get_key().to_owned()),&db)?;
+    /// ```rust,ignore
+    /// MyStruct::for_each_chunk(500, |chunk| bulk_index(chunk), &db)?;
     /// ```
-    fn get_from_end(
-        start: usize,
+    fn for_each_chunk<F: FnMut(&[Self]) -> Result<()>>(
+        chunk_size: usize,
+        mut f: F,
+        db: &Db,
+    ) -> Result<()> {
+        let format = StorageFormat::of(db)?;
+        let chunk_size = chunk_size.max(1);
+        let mut chunk = Vec::with_capacity(chunk_size);
+        for elem in Self::read_tree(db)?.iter() {
+            let (key, vec) = elem?;
+            if !Self::has_own_tag(&vec) {
+                continue;
+            }
+            chunk.push(Self::from_ivec(&key, &vec, format)?);
+            if chunk.len() == chunk_size {
+                f(&chunk)?;
+                chunk.clear();
+            }
+        }
+        if !chunk.is_empty() {
+            f(&chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of saved instances for this entity type.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let count = MyStruct::get_count()?;
+    /// ```
+    fn get_count(db: &Db) -> Result<usize> {
+        if Self::type_tag().is_some() {
+            return Ok(Self::read_tree(db)?
+                .iter()
+                .values()
+                .filter(|vec| matches!(vec, Ok(vec) if Self::has_own_tag(vec)))
+                .count());
+        }
+        Ok(Self::read_tree(db)?.len())
+    }
+
+    /// Returns the space occupied by this entity's store, as the sum of the byte lengths of its keys and values.
+    ///
+    /// ⚠ This is an O(n) operation, since every entry of the store is read. It does not account for
+    /// `sled`'s own overhead, nor for relation descriptors.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let bytes = MyStruct::size_on_disk(&db)?;
+    /// ```
+    fn size_on_disk(db: &Db) -> Result<u64> {
+        crate::storage::tree_size(&Self::get_tree(db)?)
+    }
+
+    #[doc(hidden)]
+    fn get_from_u8_array(key: &[u8], db: &Db) -> Result<Option<Self>> {
+        let format = StorageFormat::of(db)?;
+        Self::read_tree(db)?
+            .get(key)?
+            .filter(|vec| Self::has_own_tag(vec))
+            .map(|vec| Self::decode_stored(key, &vec, format, db))
+            .transpose()
+    }
+
+    /// Decodes a stored value, migrating it first if it was saved with an older version.
+    #[doc(hidden)]
+    #[cfg_attr(not(feature = "lazy-migration"), allow(unused_variables))]
+    fn decode_stored(key: &[u8], vec: &[u8], format: StorageFormat, db: &Db) -> Result<Self> {
+        #[cfg(feature = "lazy-migration")]
+        {
+            let version = crate::migration::stored_version(Self::store_name(), key, db)?;
+            if version < Self::VERSION {
+                let migrated = Self::migrate(version, Self::payload(vec))?;
+                migrated.save(db)?;
+                return Ok(migrated);
+            }
+        }
+        Self::from_ivec(key, vec, format)
+    }
+
+    #[doc(hidden)]
+    fn get_with_prefix(key: &impl AsBytes, db: &Db) -> Result<Vec<Self>> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_WITH_PREFIX, Self::store_name());
+        Self::decode_entries(
+            child_entries(&Self::read_tree(db)?, Self::child_prefix(key)),
+            StorageFormat::of(db)?,
+        )
+    }
+
+    /// Returns the prefix of the keys of the children of `parent` in this store.
+    #[doc(hidden)]
+    fn child_prefix<P: AsBytes + ?Sized>(parent: &P) -> Vec<u8> {
+        match Self::CHILD_KEY_SEPARATOR {
+            Some(separator) => [parent.as_separated_bytes(separator), vec![separator]].concat(),
+            None => parent.as_bytes(),
+        }
+    }
+
+    /// Gets all entities whose encoded key starts with `prefix`, in key order.
+    ///
+    /// This is a single range scan, which makes it a cheap way to list the entities of a tenant
+    /// when keys start with a tenant id.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let invoices = Invoice::get_with_key_prefix(b"tenant-42/", &db)?;
+    /// ```
+    fn get_with_key_prefix(prefix: &[u8], db: &Db) -> Result<Vec<Self>> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_WITH_PREFIX, Self::store_name());
+        Self::decode_entries(
+            Self::read_tree(db)?.scan_prefix(prefix),
+            StorageFormat::of(db)?,
+        )
+    }
+
+    /// Same as [`get_with_key_prefix`](entity/trait.Entity.html#method.get_with_key_prefix), with a typed prefix
+    /// encoded with [`AsBytes`](trait.AsBytes.html), such as the first member of tuple keys.
+    ///
+    /// The prefix is followed by the [`CHILD_KEY_SEPARATOR`](entity/trait.Entity.html#associatedconstant.CHILD_KEY_SEPARATOR),
+    /// if any, like the keys of [`get_children`](entity/trait.Entity.html#method.get_children). Without separator,
+    /// the prefix `"id1"` also matches keys starting with `"id10"`.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// // keys of `Invoice` are `(String, u32)` tuples, starting with the tenant id
+    /// let invoices = Invoice::get_with_key_prefix_of(&tenant_id, &db)?;
+    /// ```
+    fn get_with_key_prefix_of<P: AsBytes + ?Sized>(prefix: &P, db: &Db) -> Result<Vec<Self>> {
+        Self::get_with_key_prefix(&Self::child_prefix(prefix), db)
+    }
+
+    /// Gets entities in a range of keys with a min and max values
+    /// This can be especially useful when keys are integral types,
+    /// but any key will work.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let entities = MyStruct::get_in_range(10,30,&db)?;
+    /// ```
+    fn get_in_range(start: impl AsBytes, end: impl AsBytes, db: &Db) -> Result<Vec<Self>> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_IN_RANGE, Self::store_name());
+        Self::decode_entries(
+            Self::read_tree(db)?.range(start.as_bytes()..end.as_bytes()),
+            StorageFormat::of(db)?,
+        )
+    }
+
+    /// Gets entities in a range of keys described by any Rust range expression,
+    /// allowing inclusive or exclusive bounds on both ends, or no bound at all.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let entities = MyStruct::get_in_range_bounds(10..=30, &db)?;
+    /// let entities = MyStruct::get_in_range_bounds(10.., &db)?;
+    /// ```
+    fn get_in_range_bounds<K: AsBytes>(bounds: impl RangeBounds<K>, db: &Db) -> Result<Vec<Self>> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_IN_RANGE, Self::store_name());
+        let bound = |bound: Bound<&K>| match bound {
+            Bound::Included(key) => Bound::Included(key.as_bytes()),
+            Bound::Excluded(key) => Bound::Excluded(key.as_bytes()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        Self::decode_entries(
+            Self::read_tree(db)?.range((bound(bounds.start_bound()), bound(bounds.end_bound()))),
+            StorageFormat::of(db)?,
+        )
+    }
+
+    /// Gets `count` entities starting at the instance at index `start` in the given store
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let entities = MyStruct::get_from_start(10,20,None,&db)?;
+    /// ```
+    /// ## Child entities
+    ///
+    /// A parent key can be supplied for child entities, to consider only children of a given parent.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let entities = MyStruct::get_from_start(10,20,Some(parent.get_key().to_owned()),&db)?;
+    /// ```
+    fn get_from_start(
+        start: usize,
+        count: usize,
+        parent: Option<impl AsBytes>,
+        db: &Db,
+    ) -> Result<Vec<Self>> {
+        let format = StorageFormat::of(db)?;
+        let tree = Self::read_tree(db)?;
+        let iter: Box<dyn DoubleEndedIterator<Item = _>> = match parent {
+            Some(prefix) => Box::new(child_entries(&tree, Self::child_prefix(&prefix))),
+            None => Box::new(tree.iter()),
+        };
+        let mut iter = iter.filter(|elem| match elem {
+            Ok((_, vec)) => Self::has_own_tag(vec),
+            Err(_) => true,
+        });
+        let mut result = Vec::new();
+        for i in 0..start.saturating_add(count) {
+            match iter.next() {
+                Some(e) => {
+                    if i >= start {
+                        let (key, vec) = e?;
+                        result.push(Self::from_ivec(&key, &vec, format)?);
+                    }
+                }
+                None => return Ok(result),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Gets `count` entities starting at the instance at index `start` from the end of a given store
+    /// Same as `get_from_start`, but starting at the end of the store.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let entities = MyStruct::get_from_end(10,20,None,&db)?;
+    /// ```
+    /// ## Child entities
+    ///
+    /// A parent key can be supplied for child entities, to consider only children of a given parent.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let entities = MyStruct::get_from_end(10,20,Some(parent.get_key().to_owned()),&db)?;
+    /// ```
+    fn get_from_end(
+        start: usize,
         offset: usize,
         prefix: Option<impl AsBytes>,
         db: &Db,
     ) -> Result<Vec<Self>> {
-        let mut iter = if let Some(prefix) = prefix {
-            Self::get_tree(db)?.scan_prefix(prefix.as_bytes())
-        } else {
-            Self::get_tree(db)?.iter()
+        let format = StorageFormat::of(db)?;
+        let tree = Self::read_tree(db)?;
+        let iter: Box<dyn DoubleEndedIterator<Item = _>> = match prefix {
+            Some(prefix) => Box::new(child_entries(&tree, Self::child_prefix(&prefix))),
+            None => Box::new(tree.iter()),
         };
+        let mut iter = iter.filter(|elem| match elem {
+            Ok((_, vec)) => Self::has_own_tag(vec),
+            Err(_) => true,
+        });
         let mut result = Vec::new();
-        for i in 0..(start + offset) {
+        for i in 0..start.saturating_add(offset) {
             match iter.next_back() {
                 Some(e) => {
                     if i >= start {
-                        result.push(Self::from_ivec(e.unwrap().1));
+                        let (key, vec) = e?;
+                        result.push(Self::from_ivec(&key, &vec, format)?);
                     }
                 }
                 None => break,
@@ -347,20 +1249,286 @@ pub trait Entity: Serialize + DeserializeOwned {
         Ok(result)
     }
 
+    /// Gets the page of index `page` (starting at 0) of a store split in pages of `per_page` entities,
+    /// along with the total number of entities and whether there are previous and next pages.
+    ///
+    /// The total is the length of the store's tree, so it is not computed by reading every entity
+    /// (unless the entity has a [type tag](entity/trait.Entity.html#method.type_tag)).
+    /// A `per_page` of 0 results in an `IntegrityError`.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let page = MyStruct::paginate(2, 20, &db)?;
+    /// println!("showing {} of {}", page.items.len(), page.total);
+    /// ```
+    fn paginate(page: usize, per_page: usize, db: &Db) -> Result<Page<Self>> {
+        check_page_size(per_page)?;
+        let items = Self::get_from_start(
+            page.saturating_mul(per_page),
+            per_page,
+            None::<Self::Key>,
+            db,
+        )?;
+        Ok(Page::new(items, Self::get_count(db)?, page, per_page))
+    }
+
+    /// Same as [`paginate`](entity/trait.Entity.html#method.paginate), only considering entities matching a condition
+    /// materialized as a function returning a boolean.
+    ///
+    /// ⚠ Computing the total requires a scan : this will effectively iterate over every entity in the store.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let page = MyStruct::paginate_with_filter(|m_struct| m_struct.prop > 20, 0, 20, &db)?;
+    /// ```
+    fn paginate_with_filter<F: Fn(&Self) -> bool>(
+        f: F,
+        page: usize,
+        per_page: usize,
+        db: &Db,
+    ) -> Result<Page<Self>> {
+        check_page_size(per_page)?;
+        let matching = Self::get_with_filter(f, db)?;
+        let total = matching.len();
+        let items = matching
+            .into_iter()
+            .skip(page.saturating_mul(per_page))
+            .take(per_page)
+            .collect();
+        Ok(Page::new(items, total, page, per_page))
+    }
+
     /// Gets all entities of a given store matching a condition materialized
     /// as a function returning a boolean
     ///
     /// ⚠ This will effectively iterate over every entity in the store.
     ///
     /// ### Example
-    /// ```rust
+    /// ```rust,ignore
     /// let entities = MyStruct::get_with_filter(|m_struct| m_struct.prop > 20,&db)?;
     /// ```
     fn get_with_filter<F: Fn(&Self) -> bool>(f: F, db: &Db) -> Result<Vec<Self>> {
-        Ok(Self::get_tree(db)?
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_WITH_FILTER, Self::store_name());
+        Ok(
+            Self::decode_entries(Self::read_tree(db)?.iter(), StorageFormat::of(db)?)?
+                .into_iter()
+                .filter(|e| f(e))
+                .collect(),
+        )
+    }
+
+    /// Same as [`get_with_filter`](entity/trait.Entity.html#method.get_with_filter), handling entities that cannot be read
+    /// according to `policy`, like [`get_all_with_policy`](entity/trait.Entity.html#method.get_all_with_policy).
+    fn get_with_filter_with_policy<F: Fn(&Self) -> bool>(
+        f: F,
+        policy: ReadErrorPolicy,
+        db: &Db,
+    ) -> Result<(Vec<Self>, ReadErrors)> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_WITH_FILTER, Self::store_name());
+        let (all, errors) = Self::decode_entries_with_policy(
+            Self::read_tree(db)?.iter(),
+            StorageFormat::of(db)?,
+            policy,
+        )?;
+        Ok((all.into_iter().filter(|e| f(e)).collect(), errors))
+    }
+
+    /// Same as [`get_with_filter`](entity/trait.Entity.html#method.get_with_filter), deserializing and filtering
+    /// entities in parallel with `rayon`. Entities are still returned in key order.
+    ///
+    /// Raw values are read from the store first, so that deserialization, usually the most expensive part,
+    /// is spread over `rayon`'s thread pool along with `f`. This is worth it for large stores or expensive conditions.
+    ///
+    /// Requires the `parallel` feature.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let entities = MyStruct::get_with_filter_par(|m_struct| m_struct.matches(&pattern),&db)?;
+    /// ```
+    #[cfg(feature = "parallel")]
+    fn get_with_filter_par<F: Fn(&Self) -> bool + Sync>(f: F, db: &Db) -> Result<Vec<Self>>
+    where
+        Self: Send,
+    {
+        use rayon::prelude::*;
+        let format = StorageFormat::of(db)?;
+        let entries = Self::read_tree(db)?
             .iter()
-            .map(|elem| Self::from_ivec(elem.unwrap().1))
-            .filter(|e| f(e))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        entries
+            .par_iter()
+            .filter(|(_, vec)| Self::has_own_tag(vec))
+            .filter_map(|(key, vec)| match Self::from_ivec(key, vec, format) {
+                Ok(entity) if !f(&entity) => None,
+                result => Some(result),
+            })
+            .collect()
+    }
+
+    /// Gets all entities of a given store, sorted by the value returned by `key_fn` for each entity.
+    /// Entities with equal values stay in key order.
+    ///
+    /// ⚠ This loads and sorts the whole store in memory.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let users = User::get_all_sorted_by(|user| user.last_login,&db)?;
+    /// ```
+    fn get_all_sorted_by<K: Ord, F: Fn(&Self) -> K>(key_fn: F, db: &Db) -> Result<Vec<Self>> {
+        let mut entities = Self::get_all(db)?;
+        entities.sort_by_key(|e| key_fn(e));
+        Ok(entities)
+    }
+
+    /// Same as [`get_all_sorted_by`](entity/trait.Entity.html#method.get_all_sorted_by), in descending order.
+    /// Entities with equal values stay in key order.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let recent_users = User::get_all_sorted_by_desc(|user| user.last_login,&db)?;
+    /// ```
+    fn get_all_sorted_by_desc<K: Ord, F: Fn(&Self) -> K>(key_fn: F, db: &Db) -> Result<Vec<Self>> {
+        let mut entities = Self::get_all(db)?;
+        entities.sort_by_key(|e| std::cmp::Reverse(key_fn(e)));
+        Ok(entities)
+    }
+
+    /// Gets the first entity of a given store matching a condition materialized
+    /// as a function returning a boolean, or `None` if no entity matches.
+    ///
+    /// Entities are tested in key order, so the result is the matching entity with the lowest key,
+    /// and the store is only read until a match is found.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let admin = User::find(|user| user.is_admin,&db)?;
+    /// ```
+    fn find<F: Fn(&Self) -> bool>(f: F, db: &Db) -> Result<Option<Self>> {
+        let format = StorageFormat::of(db)?;
+        for elem in Self::read_tree(db)?.iter() {
+            let (key, vec) = elem?;
+            if Self::has_own_tag(&vec) {
+                let entity = Self::from_ivec(&key, &vec, format)?;
+                if f(&entity) {
+                    return Ok(Some(entity));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Gets `n` entities picked at random from the store, or every entity if the store holds fewer than `n`.
+    ///
+    /// The store is read once, but at most `n` entities are kept in memory (reservoir sampling).
+    /// Use [`sample_with_seed`](entity/trait.Entity.html#method.sample_with_seed) for reproducible results.
+    ///
+    /// ⚠ This will effectively iterate over every entity in the store.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let preview = MyStruct::sample(10, &db)?;
+    /// ```
+    fn sample(n: usize, db: &Db) -> Result<Vec<Self>> {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::sample_with_seed(n, seed, db)
+    }
+
+    /// Same as [`sample`](entity/trait.Entity.html#method.sample), always picking the same entities
+    /// for a given `seed` and store content.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let preview = MyStruct::sample_with_seed(10, 42, &db)?;
+    /// ```
+    fn sample_with_seed(n: usize, seed: u64, db: &Db) -> Result<Vec<Self>> {
+        let format = StorageFormat::of(db)?;
+        let mut random = SplitMix64(seed);
+        let mut reservoir = Vec::with_capacity(n);
+        let mut seen = 0;
+        for elem in Self::read_tree(db)?.iter() {
+            let (key, vec) = elem?;
+            if !Self::has_own_tag(&vec) {
+                continue;
+            }
+            seen += 1;
+            if reservoir.len() < n {
+                reservoir.push(Self::from_ivec(&key, &vec, format)?);
+            } else {
+                let index = (random.next() % seen) as usize;
+                if index < n {
+                    reservoir[index] = Self::from_ivec(&key, &vec, format)?;
+                }
+            }
+        }
+        Ok(reservoir)
+    }
+
+    /// Gets entities in key order, stopping at the first entity for which `f` returns `false`.
+    ///
+    /// Contrary to [`get_with_filter`](entity/trait.Entity.html#method.get_with_filter), the rest of the store is not read,
+    /// which makes it an efficient bounded scan.
+    ///
+    /// ⚠ The result is only meaningful if `f` is monotonic over key order,
+    /// that is if it never returns `true` again once it has returned `false`.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let before_noon = Event::scan_while(|event| event.timestamp < noon, &db)?;
+    /// ```
+    fn scan_while<F: Fn(&Self) -> bool>(f: F, db: &Db) -> Result<Vec<Self>> {
+        let format = StorageFormat::of(db)?;
+        let mut result = Vec::new();
+        for elem in Self::read_tree(db)?.iter() {
+            let (key, vec) = elem?;
+            if !Self::has_own_tag(&vec) {
+                continue;
+            }
+            let entity = Self::from_ivec(&key, &vec, format)?;
+            if !f(&entity) {
+                break;
+            }
+            result.push(entity);
+        }
+        Ok(result)
+    }
+
+    /// Gets all entities of this store, each paired with the entity of another store whose key
+    /// is given by `key_of`, or `None` if that entity does not exist.
+    ///
+    /// Each entity of the other store is fetched only once, however many entities of this store point to it.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// for (order, customer) in Order::join::<Customer, _>(|order| order.customer_id, &db)? {
+    ///     /* ... */
+    /// }
+    /// ```
+    fn join<E: Entity + Clone, F: Fn(&Self) -> E::Key>(
+        key_of: F,
+        db: &Db,
+    ) -> Result<Vec<(Self, Option<E>)>> {
+        let all = Self::get_all(db)?;
+        let mut keys = all
+            .iter()
+            .map(|e| E::encode_key(&key_of(e)))
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        let others = E::get_each_u8(&keys, db)?
+            .into_iter()
+            .map(|other| (E::encode_key(other.get_key()), other))
+            .collect::<HashMap<_, _>>();
+        Ok(all
+            .into_iter()
+            .map(|e| {
+                let other = others.get(&E::encode_key(&key_of(&e))).cloned();
+                (e, other)
+            })
             .collect())
     }
 
@@ -369,7 +1537,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// ⚠ This will call `get` as many times as the number of keys provided.
     ///
     /// ### Example
-    /// ```rust
+    /// ```rust,ignore
     /// let entities = MyStruct::get_each(vec![4,8,9],&db)?;
     /// ```
     fn get_each(keys: &[Self::Key], db: &Db) -> Vec<Self> {
@@ -383,56 +1551,391 @@ pub trait Entity: Serialize + DeserializeOwned {
     }
 
     #[doc(hidden)]
-    fn get_each_u8(keys: &[Vec<u8>], db: &Db) -> Vec<Self> {
-        keys.iter()
-            .map(|key| Self::get_from_u8_array(key, db))
-            .filter_map(|res| match res {
-                Ok(opt) => opt,
-                Err(_) => None,
-            })
-            .collect()
+    fn get_each_u8(keys: &[Vec<u8>], db: &Db) -> Result<Vec<Self>> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_EACH, Self::store_name());
+        if let Some(mut found) = Self::get_each_in_range(keys, db)? {
+            return Ok(keys.iter().filter_map(|key| found.remove(key)).collect());
+        }
+        let mut result = Vec::new();
+        for key in keys {
+            if let Some(entity) = Self::get_from_u8_array(key, db)? {
+                result.push(entity);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Reads `keys` with a single range scan between the smallest and the greatest key.
+    /// Returns `None` when the keys are too sparse, that is when the scan reaches more
+    /// entries than allowed by the [range scan ratio](fn.set_range_scan_ratio.html).
+    #[doc(hidden)]
+    fn get_each_in_range(keys: &[Vec<u8>], db: &Db) -> Result<Option<HashMap<Vec<u8>, Self>>> {
+        let ratio = RANGE_SCAN_RATIO.load(Ordering::Relaxed);
+        let wanted = keys
+            .iter()
+            .map(|key| key.as_slice())
+            .collect::<HashSet<_>>();
+        if ratio == 0 || wanted.len() < 2 {
+            return Ok(None);
+        }
+        let (Some(&first), Some(&last)) = (wanted.iter().min(), wanted.iter().max()) else {
+            return Ok(None);
+        };
+        let format = StorageFormat::of(db)?;
+        let max_scanned = wanted.len().saturating_mul(ratio);
+        let mut found = HashMap::new();
+        for (scanned, elem) in Self::get_tree(db)?.range(first..=last).enumerate() {
+            if scanned >= max_scanned {
+                return Ok(None);
+            }
+            let (key, vec) = elem?;
+            if !wanted.contains(key.as_ref()) || !Self::has_own_tag(&vec) {
+                continue;
+            }
+            found.insert(key.to_vec(), Self::decode_stored(&key, &vec, format, db)?);
+        }
+        Ok(Some(found))
     }
 
     /// Saves an entity to the database, using its key provided by the`get_key` method.
     ///
+    /// ⚠ Entities with an empty key (such as an empty `String`) are rejected with an `IntegrityError`,
+    /// since an empty key is a prefix of every other key.
+    ///
+    /// ⚠ This does not flush the database : the entity is written to disk by `sled`'s background
+    /// flush, which happens every 500ms by default. Use [`save_durable`](entity/trait.Entity.html#method.save_durable)
+    /// if the entity must be on disk when this returns.
+    ///
     /// ### Example
     ///
-    /// ```rust
+    /// ```rust,ignore
     /// let my_struct = MyStruct { key : 0, prop1 : String::from("Hello"), prop2 : 554};
     /// my_struct.save(&db)?;
     /// ```
     fn save(&self, db: &Db) -> Result<()> {
-        Self::get_tree(db)?.insert(
-            &self.get_key().as_bytes(),
-            bincode::serialize(self).unwrap(),
-        )?;
+        self.save_with_receipt(db)?;
+        Ok(())
+    }
+
+    /// Saves an entity like [`save`](entity/trait.Entity.html#method.save), and returns a [`SaveReceipt`](struct.SaveReceipt.html)
+    /// giving the size of the stored value and whether it replaced an existing record.
+    ///
+    /// ### Example
+    ///
+    /// ```rust,ignore
+    /// let receipt = my_struct.save_with_receipt(&db)?;
+    /// if !receipt.was_update {
+    ///     count += 1;
+    /// }
+    /// quota.consume(receipt.bytes_written);
+    /// ```
+    fn save_with_receipt(&self, db: &Db) -> Result<SaveReceipt<Self::Key>> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::SAVE, Self::store_name());
+        let key = Self::encode_key(self.get_key());
+        check_key::<Self>(&key)?;
+        let value = self.to_ivec(StorageFormat::of(db)?)?;
+        let bytes_written = value.len();
+        let previous = Self::insert_own(&Self::get_tree(db)?, &key, value)?;
+        self.record_save(&key, db)?;
+        Ok(SaveReceipt {
+            key: self.get_key().clone(),
+            bytes_written,
+            was_update: previous.is_some(),
+        })
+    }
+
+    /// Saves this entity like [`save`](entity/trait.Entity.html#method.save), unless an entity already
+    /// exists with the same key, and returns whether it was saved.
+    #[doc(hidden)]
+    fn save_if_vacant(&self, db: &Db) -> Result<bool> {
+        let key = Self::encode_key(self.get_key());
+        check_key::<Self>(&key)?;
+        let value = self.to_ivec(StorageFormat::of(db)?)?;
+        if Self::get_tree(db)?
+            .compare_and_swap(&key, None as Option<&[u8]>, Some(value))?
+            .is_err()
+        {
+            return Ok(false);
+        }
+        self.record_save(&key, db)?;
+        Ok(true)
+    }
+
+    /// Writes the version, instance siblings and changelog record of an entity that was just saved.
+    #[doc(hidden)]
+    fn record_save(&self, key: &[u8], db: &Db) -> Result<()> {
+        Self::record_version(key, db)?;
+        self.save_instance_siblings(db)?;
+        #[cfg(feature = "changelog")]
+        crate::changelog::record(Self::store_name(), key, ChangeOp::Save, db)?;
+        Ok(())
+    }
+
+    /// Saves an entity to the database without flushing it to disk.
+    ///
+    /// This is the same as [`save`](entity/trait.Entity.html#method.save), and makes the intent explicit
+    /// for high-throughput writes : importers can save many entities this way and
+    /// call `db.flush()` once at the end.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// for my_struct in &my_structs {
+    ///     my_struct.save_async(&db)?;
+    /// }
+    /// db.flush()?;
+    /// ```
+    fn save_async(&self, db: &Db) -> Result<()> {
+        self.save(db)
+    }
+
+    /// Saves an entity to the database and flushes the database, so that the entity
+    /// is durably written to disk when this returns.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// my_struct.save_durable(&db)?;
+    /// ```
+    fn save_durable(&self, db: &Db) -> Result<()> {
+        self.save(db)?;
+        db.flush()?;
         Ok(())
     }
 
     /// Updates an entity entry using the provided function
     ///
+    /// If the function changes the entity's key, the entity is moved to its new key, along with its
+    /// relations, siblings and children, as with [`rekey`](entity/trait.Entity.html#method.rekey).
+    /// If an entity already exists with the new key, or if the entity has [`STRICT_KEYS`](entity/trait.Entity.html#associatedconstant.STRICT_KEYS),
+    /// it results in an `IntegrityError` and nothing is changed.
+    ///
     /// ### Example
     /// This will get the `MyStruct` instance with key 3  and increment its `prop1` member
-    /// ```rust
+    /// ```rust,ignore
     /// MyStruct::update(&3,|my_struct| my_struct.prop1++,&db)?;
     /// ```
     fn update<F: Fn(&mut Self)>(key: &Self::Key, f: F, db: &Db) -> Result<()> {
-        Self::get_tree(db)?.fetch_and_update(&key.as_bytes(), |e| {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::UPDATE, Self::store_name());
+        #[cfg(feature = "lazy-migration")]
+        Self::get(key, db)?;
+        let format = StorageFormat::of(db)?;
+        let key_bytes = Self::encode_key(key);
+        let mut error = None;
+        let mut rekeyed = None;
+        #[cfg_attr(not(feature = "changelog"), allow(unused_variables))]
+        let previous = Self::get_tree(db)?.fetch_and_update(&key_bytes, |e| {
+            error = None;
+            rekeyed = None;
             e.map(|u8_arr| {
-                let mut value: Self = Self::from_ivec(IVec::from(u8_arr));
-                f(&mut value);
-                value.to_ivec()
+                if !Self::has_own_tag(u8_arr) {
+                    return IVec::from(u8_arr);
+                }
+                let updated = Self::from_ivec(&key_bytes, u8_arr, format).and_then(|mut value| {
+                    f(&mut value);
+                    check_same_key(&key_bytes, &value)?;
+                    if Self::encode_key(value.get_key()) != key_bytes {
+                        // moving the entity needs more than this tree : it is done afterwards
+                        rekeyed = Some(value);
+                        return Ok(IVec::from(u8_arr));
+                    }
+                    value.to_ivec(format)
+                });
+                updated.unwrap_or_else(|e| {
+                    error = Some(e);
+                    IVec::from(u8_arr)
+                })
             })
         })?;
+        if let Some(error) = error {
+            return Err(error);
+        }
+        if let Some(value) = rekeyed {
+            return value.rekey(key, db);
+        }
+        #[cfg(feature = "changelog")]
+        if previous.is_some() {
+            crate::changelog::record(
+                Self::store_name(),
+                &Self::encode_key(key),
+                ChangeOp::Save,
+                db,
+            )?;
+        }
         Ok(())
     }
 
+    /// Modifies an entity entry using the provided function, and returns the value returned by the function,
+    /// or `None` if there is no entity with key `key`.
+    ///
+    /// Key changes are handled as with [`update`](entity/trait.Entity.html#method.update).
+    ///
+    /// ⚠ Contrary to `update`, the function is called exactly once, so the entity is read and saved in two steps :
+    /// a concurrent modification of the same entity between these steps is overwritten.
+    ///
+    /// ### Example
+    /// This will set the `prop1` member of the `MyStruct` instance with key 3 to 0, and return its previous value
+    /// ```rust,ignore
+    /// let previous = MyStruct::modify(&3,|my_struct| std::mem::take(&mut my_struct.prop1),&db)?;
+    /// ```
+    fn modify<R, F: FnOnce(&mut Self) -> R>(key: &Self::Key, f: F, db: &Db) -> Result<Option<R>> {
+        let mut value = match Self::get(key, db)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let result = f(&mut value);
+        check_same_key(&Self::encode_key(key), &value)?;
+        if Self::encode_key(value.get_key()) != Self::encode_key(key) {
+            value.rekey(key, db)?;
+        } else {
+            value.save(db)?;
+        }
+        Ok(Some(result))
+    }
+
+    /// Exchanges the stored values of the entities with keys `key_a` and `key_b`, in a single transaction.
+    ///
+    /// Relations, siblings and children stay attached to the keys : only the values move.
+    /// If either entity does not exist, it results in a `NotFound` error and nothing is changed.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// Slide::swap(&2, &3, &db)?;
+    /// ```
+    fn swap(key_a: &Self::Key, key_b: &Self::Key, db: &Db) -> Result<()> {
+        let format = StorageFormat::of(db)?;
+        let key_a_bytes = Self::encode_key(key_a);
+        let key_b_bytes = Self::encode_key(key_b);
+        let decode = |new_key: &Self::Key, key_bytes: &[u8], vec: Option<IVec>| -> Result<IVec> {
+            match vec {
+                Some(vec) if Self::has_own_tag(&vec) => {
+                    let mut value = Self::from_ivec(key_bytes, &vec, format)?;
+                    value.set_key(new_key);
+                    value.to_ivec(format)
+                }
+                _ => Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!(
+                        "Cannot swap missing entity {:?} of {}",
+                        key_bytes,
+                        Self::store_name()
+                    ),
+                )),
+            }
+        };
+        Self::get_tree(db)?.transaction(|tx| {
+            // each value is re-keyed, since entities hold their own key
+            let vec_a = decode(key_b, &key_a_bytes, tx.get(&key_a_bytes)?)
+                .map_err(ConflictableTransactionError::Abort)?;
+            let vec_b = decode(key_a, &key_b_bytes, tx.get(&key_b_bytes)?)
+                .map_err(ConflictableTransactionError::Abort)?;
+            tx.insert(key_b_bytes.as_slice(), vec_a)?;
+            tx.insert(key_a_bytes.as_slice(), vec_b)?;
+            Ok(())
+        })?;
+        Self::record_version(&key_a_bytes, db)?;
+        Self::record_version(&key_b_bytes, db)?;
+        #[cfg(feature = "changelog")]
+        for key in [&key_a_bytes, &key_b_bytes] {
+            crate::changelog::record(Self::store_name(), key, ChangeOp::Save, db)?;
+        }
+        Ok(())
+    }
+
+    /// Saves this entity, which used to be stored under `old_key`, under its current key,
+    /// and removes the entity stored under `old_key`.
+    ///
+    /// Relations of the old entity are transferred to the new one, and its siblings and children are moved
+    /// to the new key as well. If an entity already exists with the new key, it results in an `IntegrityError`
+    /// and nothing is changed.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let mut my_struct = MyStruct::get(&3,&db)?.unwrap();
+    /// my_struct.set_key(&4);
+    /// my_struct.rekey(&3,&db)?;
+    /// ```
+    fn rekey(&self, old_key: &Self::Key, db: &Db) -> Result<()> {
+        let old_key = Self::encode_key(old_key);
+        let new_key = Self::encode_key(self.get_key());
+        if old_key == new_key {
+            return self.save(db);
+        }
+        // the new key is only written if it is vacant, so that a concurrent writer is never overwritten
+        if !self.save_if_vacant(db)? {
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Cannot move entity {:?} of {} : key {:?} is already taken",
+                    old_key,
+                    Self::store_name(),
+                    new_key
+                ),
+            ));
+        }
+        // the old entry is removed first, so that siblings moving their own siblings do not move it back
+        Self::get_tree(db)?.remove(&old_key)?;
+        Relation::change_entity_id(Self::store_name(), &old_key, &new_key, db)?;
+        Relation::remove_entity_entry::<Self>(&old_key, db)?;
+        #[cfg(feature = "changelog")]
+        crate::changelog::record(Self::store_name(), &old_key, ChangeOp::Remove, db)?;
+        Ok(())
+    }
+
+    /// Re-encodes the keys of every entity of this store that were not stored with the current
+    /// [`CHILD_KEY_SEPARATOR`](entity/trait.Entity.html#associatedconstant.CHILD_KEY_SEPARATOR),
+    /// moving their relations, siblings and children along, and returns the number of moved entities.
+    ///
+    /// ⚠ Parent stores must be migrated before their child stores.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// Comment::migrate_child_keys(&db)?;
+    /// Reply::migrate_child_keys(&db)?;
+    /// ```
+    fn migrate_child_keys(db: &Db) -> Result<usize>
+    where
+        Self::Key: FromBytes,
+    {
+        let tree = Self::get_tree(db)?;
+        let keys = tree
+            .iter()
+            .keys()
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let mut count = 0;
+        for old_key in keys {
+            let new_key = Self::encode_key(&Self::decode_key(&old_key)?);
+            if new_key == old_key.as_ref() {
+                continue;
+            }
+            if tree.contains_key(&new_key)? {
+                return Err(Error::new(
+                    ErrorKind::IntegrityError,
+                    format!(
+                        "Cannot migrate entity {:?} of {} : key {:?} is already taken",
+                        old_key,
+                        Self::store_name(),
+                        new_key
+                    ),
+                ));
+            }
+            if let Some(value) = tree.remove(&old_key)? {
+                tree.insert(&new_key, value)?;
+            }
+            Relation::change_entity_id(Self::store_name(), &old_key, &new_key, db)?;
+            Relation::remove_entity_entry::<Self>(&old_key, db)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
     /// Updates all entities that match a condition provided as a function
     ///
     /// ### Example
     /// This will get all the `MyStruct` instances with prop1 greater than 100
     /// and change it to be 0 instead;
-    /// ```rust
+    /// ```rust,ignore
     /// MyStruct::filter_update(|my_struct| mu_struct.prop1 > 100,|my_struct| {my_struct.prop1 = 0;},&db)?;
     /// ```
     fn filter_update<F: Fn(&Self) -> bool, M: Fn(&mut Self)>(
@@ -442,133 +1945,720 @@ pub trait Entity: Serialize + DeserializeOwned {
     ) -> Result<()> {
         let mut res = Self::get_with_filter(filter, db)?;
         for entity in &mut res {
+            let key = entity.get_key().clone();
             modifier(entity);
-            entity.save(db)?;
+            check_same_key(&Self::encode_key(&key), entity)?;
+            if Self::encode_key(entity.get_key()) != Self::encode_key(&key) {
+                entity.rekey(&key, db)?;
+            } else {
+                entity.save(db)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[doc(hidden)]
+    fn pre_remove(key: &[u8], overrides: &[(&str, DeletionBehaviour)], db: &Db) -> Result<()> {
+        let plan = Relation::deletion_plan(Self::store_name(), key, overrides, db)?;
+        Self::apply_deletion_plan(key, plan, db)
+    }
+
+    #[doc(hidden)]
+    fn apply_deletion_plan(key: &[u8], mut plan: Vec<(String, Vec<u8>)>, db: &Db) -> Result<()> {
+        plan.pop();
+        for (tree_name, other_key) in &plan {
+            Relation::remove_entity_entry_with_tree_name(tree_name, other_key, db)?;
+            db.open_tree(tree_name)?.remove(other_key.as_slice())?;
+            #[cfg(feature = "changelog")]
+            crate::changelog::record(tree_name, other_key, ChangeOp::Remove, db)?;
+        }
+        Relation::remove_entity_entry::<Self>(key, db)?;
+        Ok(())
+    }
+
+    #[doc(hidden)]
+    fn save_instance_siblings(&self, db: &Db) -> Result<()> {
+        let siblings = self
+            .sibling_trees_for()
+            .into_iter()
+            .map(|(name, behaviour)| (String::from(name), behaviour))
+            .collect::<Vec<_>>();
+        Relation::save_instance_siblings(
+            Self::store_name(),
+            &Self::encode_key(self.get_key()),
+            &siblings,
+            db,
+        )
+    }
+
+    #[doc(hidden)]
+    fn can_be_removed(key: &[u8], db: &Db) -> Result<()> {
+        Relation::deletion_plan(Self::store_name(), key, &[], db)?;
+        Ok(())
+    }
+
+    /// Returns every entity preventing the removal of the entity with key `key`, as a list of `(store_name, key)` pairs,
+    /// where `key` is the binary representation of the blocking entity's key. The list is empty if the entity can be removed.
+    ///
+    /// Contrary to [`remove`](entity/trait.Entity.html#method.remove), which fails on the first blocker, the whole
+    /// relation hierarchy is traversed, so that every dependent entity can be listed at once.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// for (store_name, key) in MyStruct::removal_blockers(&3, &db)? {
+    ///     println!("{} {:?} must be removed first", store_name, key);
+    /// }
+    /// ```
+    fn removal_blockers(key: &Self::Key, db: &Db) -> Result<Vec<(String, Vec<u8>)>> {
+        Relation::removal_blockers(Self::store_name(), &Self::encode_key(key), db)
+    }
+
+    /// Removes an entity given its key.
+    /// ⚠ If removal is impossible due to integrity checks
+    /// (`DeletionBehaviour::Error` found in the relation hierarchy), this will result in an error.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// MyStruct::remove(&3, &db);
+    /// ```
+    fn remove(key: &Self::Key, db: &Db) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::REMOVE, Self::store_name());
+        Self::remove_from_u8_array(&Self::encode_key(key), db)
+    }
+
+    /// Removes an entity given its key, substituting the declared
+    /// [`DeletionBehaviour`](relation/enum.DeletionBehaviour.html) of some related stores
+    /// for this deletion only.
+    ///
+    /// `overrides` is a list of `(store_name, DeletionBehaviour)` pairs. Whenever the deletion
+    /// traversal meets a relation (free, sibling or child) pointing to one of these stores, the
+    /// provided behaviour is used instead of the registered one. Nothing is persisted: subsequent
+    /// deletions use the registered behaviours again.
+    ///
+    /// This is meant as an escape hatch for maintenance purposes.
+    ///
+    /// ### Example
+    /// This will remove `MyStruct` with key 3 along with its children, even if `child_struct`
+    /// was registered with `DeletionBehaviour::Error`
+    /// ```rust,ignore
+    /// MyStruct::remove_with_override(&3, &[("child_struct", DeletionBehaviour::Cascade)], &db)?;
+    /// ```
+    fn remove_with_override(
+        key: &Self::Key,
+        overrides: &[(&str, DeletionBehaviour)],
+        db: &Db,
+    ) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::REMOVE, Self::store_name());
+        Self::check_removable(&Self::encode_key(key), db)?;
+        Self::pre_remove(&Self::encode_key(key), overrides, db)?;
+        Self::get_tree(db)?.remove(Self::encode_key(key))?;
+        #[cfg(feature = "changelog")]
+        crate::changelog::record(
+            Self::store_name(),
+            &Self::encode_key(key),
+            ChangeOp::Remove,
+            db,
+        )?;
+        Ok(())
+    }
+
+    /// Removes an entity given its key, failing with an `IntegrityError` if `Cascade` relations
+    /// would remove entities further than `max_depth` hops away from it.
+    ///
+    /// Nothing is removed if the limit is exceeded. See also
+    /// [`set_max_cascade_depth`](fn.set_max_cascade_depth.html) to set a limit for every deletion.
+    ///
+    /// ### Example
+    /// This will remove `MyStruct` with key 3 and its children, but fail if its children have children of their own
+    /// ```rust,ignore
+    /// MyStruct::remove_with_limit(&3, 1, &db)?;
+    /// ```
+    fn remove_with_limit(key: &Self::Key, max_depth: usize, db: &Db) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::REMOVE, Self::store_name());
+        let key = Self::encode_key(key);
+        Self::check_removable(&key, db)?;
+        let plan =
+            Relation::deletion_plan_with_limit(Self::store_name(), &key, &[], max_depth, db)?;
+        Self::apply_deletion_plan(&key, plan, db)?;
+        Self::get_tree(db)?.remove(&key)?;
+        #[cfg(feature = "changelog")]
+        crate::changelog::record(Self::store_name(), &key, ChangeOp::Remove, db)?;
+        Ok(())
+    }
+
+    #[doc(hidden)]
+    fn remove_from_u8_array(key: &[u8], db: &Db) -> Result<()> {
+        Self::check_removable(key, db)?;
+        Self::pre_remove(key, &[], db)?;
+        Self::get_tree(db)?.remove(key)?;
+        #[cfg(feature = "changelog")]
+        crate::changelog::record(Self::store_name(), key, ChangeOp::Remove, db)?;
+        Ok(())
+    }
+
+    #[doc(hidden)]
+    fn remove_prefixed(prefix: impl AsBytes, db: &Db) -> Result<()> {
+        Self::remove_prefixed_in_tree(Self::store_name(), &prefix.as_bytes(), db)
+    }
+
+    #[doc(hidden)]
+    fn remove_prefixed_in_tree(tree_name: &str, prefix: &[u8], db: &Db) -> Result<()> {
+        let tree = db.open_tree(tree_name)?;
+        let mut batch = Batch::default();
+        #[cfg(feature = "changelog")]
+        let mut removed = Vec::new();
+        tree.scan_prefix(prefix).for_each(|elem| {
+            if let Ok((key, _)) = elem {
+                if Self::pre_remove(&key, &[], db).is_ok() {
+                    #[cfg(feature = "changelog")]
+                    removed.push(key.clone());
+                    batch.remove(key)
+                }
+            }
+        });
+        tree.apply_batch(batch)?;
+        #[cfg(feature = "changelog")]
+        for key in &removed {
+            crate::changelog::record(tree_name, key, ChangeOp::Remove, db)?;
+        }
+        Ok(())
+    }
+
+    /// Removes every entry of a store, given a condition in the form of a function returning a boolean
+    /// and returns the array of removed elements.
+    /// ⚠ If removal is impossible due to integrity checks
+    /// (`DeletionBehaviour::Error` found in the relation hierarchy), they won't be deleted and won't be
+    /// included in results. Use [`filter_remove_report`](entity/trait.Entity.html#method.filter_remove_report)
+    /// to know which ones could not be removed.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// MyStruct::remove(&3, &db);
+    /// ```
+    fn filter_remove<F: Fn(&Self) -> bool>(f: F, db: &Db) -> Result<Vec<Self>> {
+        Ok(Self::filter_remove_report(f, db)?.removed)
+    }
+
+    /// Removes every entry of a store matching a condition, like [`filter_remove`](entity/trait.Entity.html#method.filter_remove),
+    /// and returns a [`RemoveReport`](struct.RemoveReport.html) listing both the removed entities and the ones
+    /// that could not be removed, along with the reason why.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let report = MyStruct::filter_remove_report(|m_struct| m_struct.archived, &db)?;
+    /// println!("{} deleted, {} blocked", report.removed.len(), report.blocked.len());
+    /// ```
+    fn filter_remove_report<F: Fn(&Self) -> bool>(f: F, db: &Db) -> Result<RemoveReport<Self>> {
+        let mut report = RemoveReport {
+            removed: Vec::new(),
+            blocked: Vec::new(),
+        };
+        for entity in Self::get_with_filter(f, db)? {
+            match Self::remove(entity.get_key(), db) {
+                Ok(()) => report.removed.push(entity),
+                Err(error) => report.blocked.push((entity, error)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Atomically replaces every entity of this store by `items` : readers either see the previous
+    /// content of the store or the new one, never an empty or partially replaced store.
+    ///
+    /// `behaviour` defines what happens to the entities that are not part of `items` anymore :
+    ///  - `DeletionBehaviour::Error` : nothing is replaced if any of them has free relations
+    ///  - `DeletionBehaviour::BreakLink` : their free relations are removed, their siblings and children are left untouched
+    ///  - `DeletionBehaviour::Cascade` : they are removed as with [`remove`](entity/trait.Entity.html#method.remove),
+    ///    and nothing is replaced if one of them cannot be removed
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// CurrencyRate::replace_all(&rates, DeletionBehaviour::BreakLink, &db)?;
+    /// ```
+    fn replace_all(items: &[Self], behaviour: DeletionBehaviour, db: &Db) -> Result<()> {
+        let tree = Self::get_tree(db)?;
+        let kept = items
+            .iter()
+            .map(|item| Self::encode_key(item.get_key()))
+            .collect::<HashSet<_>>();
+        let mut removed = Vec::new();
+        for elem in tree.iter() {
+            let (key, value) = elem?;
+            if Self::has_own_tag(&value) && !kept.contains(key.as_ref()) {
+                removed.push(key.to_vec());
+            }
+        }
+        let mut plans = Vec::new();
+        for key in &removed {
+            match behaviour {
+                DeletionBehaviour::Error => {
+                    let relations = Relation::get_descriptor_with_key_and_tree_name(
+                        Self::store_name(),
+                        key,
+                        db,
+                    )?;
+                    if relations.related_entities.values().any(|e| !e.is_empty()) {
+                        return Err(Error::new(
+                            ErrorKind::IntegrityError,
+                            format!(
+                                "Replaced entity in {} still has related entities",
+                                Self::store_name()
+                            ),
+                        ));
+                    }
+                }
+                DeletionBehaviour::Cascade => {
+                    plans.push(Relation::deletion_plan(Self::store_name(), key, &[], db)?)
+                }
+                DeletionBehaviour::BreakLink => {}
+            }
+        }
+        let mut batches = TreeBatches::default();
+        for key in &removed {
+            batches.tree(Self::store_name()).remove(key.as_slice());
+            #[cfg(feature = "changelog")]
+            crate::changelog::record_in(
+                Self::store_name(),
+                key,
+                ChangeOp::Remove,
+                &mut batches,
+                db,
+            )?;
+        }
+        save_in(items, &mut batches, db)?;
+        // Relations of the removed entities are forgotten in the same transaction
+        if behaviour != DeletionBehaviour::Cascade {
+            Relation::forget_entities_in(Self::store_name(), &removed, &mut batches, db)?;
+            #[cfg(feature = "lazy-migration")]
+            for key in &removed {
+                crate::migration::forget_version_in(Self::store_name(), key, &mut batches);
+            }
+        }
+        batches.apply(db)?;
+        if behaviour == DeletionBehaviour::Cascade {
+            for (key, plan) in removed.iter().zip(plans) {
+                Self::apply_deletion_plan(key, plan, db)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if an entity exists in a given store, without fetching it.
+    /// ### Example
+    /// ```rust,ignore
+    /// if MyStruct::exists(&3, &db)? {
+    ///     /* */
+    /// }
+    /// ```
+    fn exists(key: &Self::Key, db: &Db) -> Result<bool> {
+        Self::contains_own_key(&Self::read_tree(db)?, key)
+    }
+
+    /// Whether `tree` holds an entity of this type at `key`, ignoring the entities of other types sharing the store.
+    #[doc(hidden)]
+    fn contains_own_key(tree: &Tree, key: &Self::Key) -> Result<bool> {
+        if Self::type_tag().is_some() {
+            return Ok(tree
+                .get(Self::encode_key(key))?
+                .filter(|vec| Self::has_own_tag(vec))
+                .is_some());
+        }
+        Ok(tree.contains_key(Self::encode_key(key))?)
+    }
+
+    /// Checks if every key of a collection exists in a given store, without fetching the entities.
+    /// ### Example
+    /// ```rust,ignore
+    /// if MyStruct::exists_all(&[3, 4, 9], &db)? {
+    ///     /* */
+    /// }
+    /// ```
+    fn exists_all(keys: &[Self::Key], db: &Db) -> Result<bool> {
+        let tree = Self::read_tree(db)?;
+        for key in keys {
+            if !Self::contains_own_key(&tree, key)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns the keys of a collection that do not exist in a given store, without fetching the entities.
+    /// ### Example
+    /// ```rust,ignore
+    /// let missing = MyStruct::missing_keys(&[3, 4, 9], &db)?;
+    /// ```
+    fn missing_keys(keys: &[Self::Key], db: &Db) -> Result<Vec<Self::Key>> {
+        let tree = Self::read_tree(db)?;
+        let mut missing = Vec::new();
+        for key in keys {
+            if !Self::contains_own_key(&tree, key)? {
+                missing.push(key.clone());
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Exports the entire store for this entity as a JSON file.
+    /// This can be used for saving purposes.
+    fn export_json(f: File, db: &Db) -> Result<()> {
+        let all = Self::get_all(db)?;
+        serde_json::to_writer(f, &all)?;
+        Ok(())
+    }
+
+    /// Exports the entire store for this entity as a pretty-printed JSON file.
+    /// Entities are exported in key order, which makes the output stable and suitable
+    /// for human review or version control.
+    fn export_json_pretty(f: File, db: &Db) -> Result<()> {
+        let all = Self::get_all(db)?;
+        serde_json::to_writer_pretty(f, &all)?;
+        Ok(())
+    }
+
+    /// Imports the entire store for this entity as a JSON file.
+    /// Any existing entities with matching keys will be overridden.
+    ///
+    /// This can be used for restoring purposes.
+    ///
+    /// When `validate` is `true`, every record is checked before anything is saved : the store must be registered,
+    /// and each record must have a non-empty key that no other record uses, and fit the database's storage options
+    /// (such as its [size limit](struct.OpenConfig.html#method.bincode_limit)) as well as the store's
+    /// [`MAX_VALUE_SIZE`](entity/trait.Entity.html#associatedconstant.MAX_VALUE_SIZE). The import aborts on the first invalid record,
+    /// with an error giving its position in the file. Use it for untrusted data, and skip it to quickly restore trusted backups.
+    ///
+    /// ⚠ If the structure of the JSON file does not match the Structs used in the app, this will fail with an error.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// MyStruct::import_json(File::open("./upload.json")?, true, &db)?;
+    /// ```
+    fn import_json(f: File, validate: bool, db: &Db) -> Result<()> {
+        let all: Vec<Self> = serde_json::from_reader(f)?;
+        if validate {
+            Self::validate_import(&all, db)?;
+        }
+        for each in all {
+            each.save(db)?;
         }
         Ok(())
     }
 
     #[doc(hidden)]
-    fn pre_remove(key: &[u8], db: &Db) -> Result<()> {
-        let mut to_be_removed = EntityRelations::default();
-        Relation::can_be_deleted(Self::store_name(), key, &Vec::new(), &mut to_be_removed, db)?;
-        for (tree, keys) in &to_be_removed.related_entities {
-            let tree = db.open_tree(tree)?;
-            let mut batch = Batch::default();
-            keys.iter().for_each(|rd| batch.remove(rd.key.as_slice()));
-            tree.apply_batch(batch)?;
+    fn validate_import(all: &[Self], db: &Db) -> Result<()> {
+        Self::check_registered(RegistrationCheck::Error, db)?;
+        let format = StorageFormat::of(db)?;
+        let mut keys = HashSet::new();
+        for (index, each) in all.iter().enumerate() {
+            let key = Self::encode_key(each.get_key());
+            let invalid = |error: Error| {
+                Error::new(
+                    error.kind(),
+                    format!(
+                        "Invalid record {} for {} ({})",
+                        index,
+                        Self::store_name(),
+                        error
+                    ),
+                )
+            };
+            check_key::<Self>(&key).map_err(invalid)?;
+            each.to_ivec(format).map_err(invalid)?;
+            if !keys.insert(key.clone()) {
+                return Err(Error::new(
+                    ErrorKind::IntegrityError,
+                    format!(
+                        "Invalid record {} for {} : key {:?} is used by another record",
+                        index,
+                        Self::store_name(),
+                        key
+                    ),
+                ));
+            }
         }
-        Relation::remove_entity_entry::<Self>(key, db)?;
-        Relation::remove_descriptor(Self::store_name(), key, db)?;
         Ok(())
     }
 
-    #[doc(hidden)]
-    fn can_be_removed(key: &[u8], db: &Db) -> Result<()> {
-        Relation::can_be_deleted(
-            Self::store_name(),
-            key,
-            &Vec::new(),
-            &mut EntityRelations::default(),
-            db,
-        )?;
+    /// Exports the entire store for this entity as a JSON file, along with the free relations
+    /// of each entity (in both directions).
+    ///
+    /// Use [`import_json_with_relations`](entity/trait.Entity.html#method.import_json_with_relations)
+    /// to restore both the entities and their relations.
+    fn export_json_with_relations(f: File, db: &Db) -> Result<()> {
+        let mut all = Vec::new();
+        for entity in Self::get_all(db)? {
+            let key = Self::encode_key(entity.get_key());
+            let mut relations = Vec::new();
+            for (store, descriptors) in Relation::relations(&entity, db)?.related_entities {
+                for rd in descriptors {
+                    let reverse_deletion_behaviour =
+                        Relation::get_descriptor_with_key_and_tree_name(&store, &rd.key, db)?
+                            .related_entities
+                            .get(Self::store_name())
+                            .and_then(|reverse| {
+                                reverse.iter().find(|r| r.key == key && r.name == rd.name)
+                            })
+                            .map(|r| r.deletion_behaviour);
+                    relations.push(ExportedRelation {
+                        store: store.clone(),
+                        key: rd.key,
+                        deletion_behaviour: rd.deletion_behaviour,
+                        reverse_deletion_behaviour,
+                        name: rd.name,
+                    });
+                }
+            }
+            all.push(ExportedEntity { entity, relations });
+        }
+        serde_json::to_writer(f, &all)?;
         Ok(())
     }
 
-    /// Removes an entity given its key.
-    /// ⚠ If removal is impossible due to integrity checks
-    /// (`DeletionBehaviour::Error` found in the relation hierarchy), this will result in an error.
+    /// Imports the entire store for this entity, along with the free relations of each entity,
+    /// from a JSON file created with [`export_json_with_relations`](entity/trait.Entity.html#method.export_json_with_relations).
+    /// Any existing entities with matching keys will be overridden.
+    ///
+    /// Relations are recreated on both ends, so that related entities are linked back to the
+    /// imported ones.
+    ///
+    /// Relations pointing to entities that exist neither in the database nor in the imported file
+    /// are handled according to `dangling` :
+    ///  - `DanglingRelations::Drop` silently ignores them
+    ///  - `DanglingRelations::Error` aborts the import, without saving anything
     ///
     /// ### Example
-    /// ```rust
-    /// MyStruct::remove(&3, &db);
+    /// ```rust,ignore
+    /// MyStruct::import_json_with_relations(File::open("./backup.json")?, DanglingRelations::Error, &db)?;
     /// ```
-    fn remove(key: &Self::Key, db: &Db) -> Result<()> {
-        Self::remove_from_u8_array(&key.as_bytes(), db)
-    }
-
-    #[doc(hidden)]
-    fn remove_from_u8_array(key: &[u8], db: &Db) -> Result<()> {
-        Self::pre_remove(key, db)?;
-        Self::get_tree(db)?.remove(key)?;
+    fn import_json_with_relations(f: File, dangling: DanglingRelations, db: &Db) -> Result<()> {
+        let mut all: Vec<ExportedEntity<Self>> = serde_json::from_reader(f)?;
+        let imported_keys = all
+            .iter()
+            .map(|e| Self::encode_key(e.entity.get_key()))
+            .collect::<Vec<Vec<u8>>>();
+        let tree_names = db.tree_names();
+        for exported in &mut all {
+            let mut valid = Vec::new();
+            for relation in exported.relations.drain(..) {
+                let exists = if relation.store == Self::store_name()
+                    && imported_keys.contains(&relation.key)
+                {
+                    true
+                } else {
+                    tree_names.contains(&IVec::from(relation.store.as_str()))
+                        && db.open_tree(&relation.store)?.contains_key(&relation.key)?
+                };
+                if exists {
+                    valid.push(relation);
+                } else if dangling == DanglingRelations::Error {
+                    return Err(Error::new(
+                        ErrorKind::NotFound,
+                        format!(
+                            "Related entity with key {:?} does not exist in {}",
+                            relation.key, relation.store
+                        ),
+                    ));
+                }
+            }
+            exported.relations = valid;
+        }
+        for exported in all {
+            exported.entity.save(db)?;
+            let mut descriptor = Relation::relations(&exported.entity, db)?;
+            for relation in &exported.relations {
+                descriptor.add_related_by_key(
+                    &relation.store,
+                    &relation.key,
+                    relation.deletion_behaviour,
+                    relation.name.as_deref(),
+                );
+            }
+            Relation::save_descriptor(&exported.entity, &descriptor, db)?;
+            for relation in &exported.relations {
+                if let Some(reverse_deletion_behaviour) = relation.reverse_deletion_behaviour {
+                    Relation::create_link_with_keys_and_tree_names(
+                        &relation.store,
+                        &relation.key,
+                        Self::store_name(),
+                        &Self::encode_key(exported.entity.get_key()),
+                        reverse_deletion_behaviour,
+                        relation.name.as_deref(),
+                        db,
+                    )?;
+                }
+            }
+        }
         Ok(())
     }
 
-    #[doc(hidden)]
-    fn remove_prefixed(prefix: impl AsBytes, db: &Db) -> Result<()> {
-        Self::remove_prefixed_in_tree(Self::store_name(), &prefix.as_bytes(), db)
-    }
-
-    #[doc(hidden)]
-    fn remove_prefixed_in_tree(tree_name: &str, prefix: &[u8], db: &Db) -> Result<()> {
-        let tree = db.open_tree(tree_name)?;
-        let mut batch = Batch::default();
-        tree.scan_prefix(prefix).for_each(|elem| {
-            if let Ok((key, _)) = elem {
-                if Self::pre_remove(&key, db).is_ok() {
-                    batch.remove(key)
+    /// Imports the entire store for this entity as a JSON file, using `strategy` to decide
+    /// what happens when an imported entity has the same key as an existing one :
+    ///  - `ImportStrategy::Overwrite` replaces the existing entity (same as [`import_json`](entity/trait.Entity.html#method.import_json))
+    ///  - `ImportStrategy::Skip` keeps the existing entity and ignores the imported one
+    ///  - `ImportStrategy::Error` aborts the import, without saving anything
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// MyStruct::import_json_with(File::open("./seed.json")?, ImportStrategy::Skip, &db)?;
+    /// ```
+    fn import_json_with(f: File, strategy: ImportStrategy, db: &Db) -> Result<()> {
+        let all: Vec<Self> = serde_json::from_reader(f)?;
+        if strategy == ImportStrategy::Error {
+            for each in &all {
+                if Self::exists(each.get_key(), db)? {
+                    return Err(Error::new(
+                        ErrorKind::IntegrityError,
+                        format!(
+                            "Entity with key {:?} already exists in {}",
+                            Self::encode_key(each.get_key()),
+                            Self::store_name()
+                        ),
+                    ));
                 }
             }
-        });
-        tree.apply_batch(batch)?;
+        }
+        for each in all {
+            if strategy == ImportStrategy::Skip && Self::exists(each.get_key(), db)? {
+                continue;
+            }
+            each.save(db)?;
+        }
         Ok(())
     }
 
-    /// Removes every entry of a store, given a condition in the form of a function returning a boolean
-    /// and returns the array of removed elements.
-    /// ⚠ If removal is impossible due to integrity checks
-    /// (`DeletionBehaviour::Error` found in the relation hierarchy), they won't be deleted and won't be
-    /// included in results.
+    /// Saves every entity yielded by `items`, writing them in batches of `batch_size` entities,
+    /// and returns the number of saved entities.
+    ///
+    /// `progress` is called with the running count of saved entities after each batch.
+    /// The database is flushed once every entity is saved.
+    /// A `batch_size` of 0 is treated as 1.
     ///
     /// ### Example
-    /// ```rust
-    /// MyStruct::remove(&3, &db);
+    /// ```rust,ignore
+    /// let count = MyStruct::import_iter(records.map(MyStruct::from), 10_000, |count| bar.set_position(count as u64), &db)?;
     /// ```
-    fn filter_remove<F: Fn(&Self) -> bool>(f: F, db: &Db) -> Result<Vec<Self>> {
-        let mut res = Self::get_with_filter(f, db)?;
-        let mut to_remove_from_result = Vec::new();
-        for (index, entity) in res.iter().enumerate() {
-            if Self::remove(entity.get_key(), db).is_err() {
-                to_remove_from_result.push(index)
-            };
+    fn import_iter<I: Iterator<Item = Self>, F: FnMut(usize)>(
+        items: I,
+        batch_size: usize,
+        mut progress: F,
+        db: &Db,
+    ) -> Result<usize> {
+        let batch_size = batch_size.max(1);
+        let mut count = 0;
+        let mut chunk = Vec::with_capacity(batch_size);
+        for item in items {
+            chunk.push(item);
+            if chunk.len() == batch_size {
+                Self::save_batch(&chunk, db)?;
+                count += chunk.len();
+                chunk.clear();
+                progress(count);
+            }
         }
-        for index in to_remove_from_result {
-            res.remove(index);
+        if !chunk.is_empty() {
+            Self::save_batch(&chunk, db)?;
+            count += chunk.len();
+            progress(count);
         }
-        Ok(res)
+        db.flush()?;
+        Ok(count)
     }
 
-    /// Checks if an entity exists in a given store, without fetching it.
+    /// Saves every entity of `items`, inserting the ones whose key does not exist yet, and merging the other ones
+    /// into the stored entity with `merge(incoming, existing)` before saving it. Returns the number of inserted
+    /// and updated entities.
+    ///
+    /// Items with the same key are merged into each other in the same way, and every entity is written in a single batch.
+    /// `merge` must not change the key of the existing entity, which results in an `IntegrityError` before anything is saved.
+    ///
     /// ### Example
-    /// ```rust
-    /// if MyStruct::exists(&3, &db)? {
-    ///     /* */
-    /// }
+    /// ```rust,ignore
+    /// let stats = Product::upsert_many(fetched, |incoming, existing| existing.price = incoming.price, &db)?;
+    /// println!("{} new products, {} updated", stats.inserted, stats.updated);
     /// ```
-    fn exists(key: &Self::Key, db: &Db) -> Result<bool> {
-        Ok(Self::get_tree(db)?.contains_key(&key.as_bytes())?)
-    }
-
-    /// Exports the entire store for this entity as a JSON file.
-    /// This can be used for saving purposes.
-    fn export_json(f: File, db: &Db) -> Result<()> {
-        let all = Self::get_all(db)?;
-        serde_json::to_writer(f, &all)?;
-        Ok(())
+    fn upsert_many<F: Fn(&Self, &mut Self)>(
+        items: Vec<Self>,
+        merge: F,
+        db: &Db,
+    ) -> Result<UpsertStats> {
+        let mut stats = UpsertStats::default();
+        let mut pending: Vec<Self> = Vec::with_capacity(items.len());
+        let mut indices = HashMap::new();
+        for item in items {
+            let key = Self::encode_key(item.get_key());
+            let existing = match indices.get(&key) {
+                Some(&index) => Some(&mut pending[index]),
+                None => match Self::get(item.get_key(), db)? {
+                    Some(stored) => {
+                        indices.insert(key.clone(), pending.len());
+                        pending.push(stored);
+                        stats.updated += 1;
+                        pending.last_mut()
+                    }
+                    None => None,
+                },
+            };
+            match existing {
+                Some(existing) => {
+                    merge(&item, existing);
+                    if Self::encode_key(existing.get_key()) != key {
+                        return Err(Error::new(
+                            ErrorKind::IntegrityError,
+                            format!(
+                                "Merge changed the key {:?} of an entity of {} to {:?}",
+                                key,
+                                Self::store_name(),
+                                Self::encode_key(existing.get_key())
+                            ),
+                        ));
+                    }
+                }
+                None => {
+                    indices.insert(key, pending.len());
+                    pending.push(item);
+                    stats.inserted += 1;
+                }
+            }
+        }
+        Self::save_batch(&pending, db)?;
+        Ok(stats)
     }
 
-    /// Imports the entire store for this entity as a JSON file.
-    /// Any existing entities with matching keys will be overridden.
+    /// Copies every entity of this store from `src` to `dest`, along with their relation descriptors,
+    /// and returns the number of copied entities. The entity type is registered in `dest`.
     ///
-    /// This can be used for restoring purposes.
+    /// Entities are read and written with the storage format of each database, so both databases do not
+    /// need to share the same format. Entities and relation descriptors with matching keys in `dest` are overridden.
     ///
-    /// ⚠ If the structure of the JSON file does not match the Structs used in the app, this will fail with an error.
-    fn import_json(f: File, db: &Db) -> Result<()> {
-        let all: Vec<Self> = serde_json::from_reader(f)?;
-        for each in all {
-            each.save(db)?;
+    /// ⚠ Relations are copied as-is : the related entities are not copied, and should be copied
+    /// with their own store for both ends of the relations to exist in `dest`.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// let copied = User::copy_store_to(&archive, &db)?;
+    /// Post::copy_store_to(&archive, &db)?;
+    /// ```
+    fn copy_store_to(dest: &Db, src: &Db) -> Result<usize> {
+        Self::register(dest)?;
+        let entities = Self::get_all(src)?;
+        Self::save_batch(&entities, dest)?;
+        for entity in &entities {
+            let descriptor = Relation::relations(entity, src)?;
+            if !descriptor.related_entities.is_empty() {
+                Relation::save_descriptor(entity, &descriptor, dest)?;
+            }
         }
-        Ok(())
+        Ok(entities.len())
+    }
+
+    #[doc(hidden)]
+    fn save_batch(items: &[Self], db: &Db) -> Result<()> {
+        let mut batches = TreeBatches::default();
+        save_in(items, &mut batches, db)?;
+        batches.apply(db)
     }
 
     /// Creates a free relation between this entity and another one.
@@ -588,6 +2678,159 @@ pub trait Entity: Serialize + DeserializeOwned {
         Relation::create(self, other, self_to_other, other_to_self, name, db)
     }
 
+    /// Creates a free relation between this entity and the entity of store `E` with key `other_key`,
+    /// like [`create_relation`](entity/trait.Entity.html#method.create_relation), without fetching it.
+    ///
+    /// Results in a `NotFound` error if either entity does not exist in the database.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// user.create_relation_to_key::<Organization>(&org_id, DeletionBehaviour::BreakLink, DeletionBehaviour::Cascade, Some("member"), &db)?;
+    /// ```
+    fn create_relation_to_key<E: Entity>(
+        &self,
+        other_key: &E::Key,
+        self_to_other: DeletionBehaviour,
+        other_to_self: DeletionBehaviour,
+        name: Option<&str>,
+        db: &Db,
+    ) -> Result<()> {
+        Relation::create_by_keys(
+            Self::store_name(),
+            &Self::encode_key(self.get_key()),
+            E::store_name(),
+            &E::encode_key(other_key),
+            self_to_other,
+            other_to_self,
+            name,
+            db,
+        )
+    }
+
+    /// Creates a free relation between this entity and another one, like [`create_relation`](entity/trait.Entity.html#method.create_relation),
+    /// giving it an `order` among the entities related to this one.
+    ///
+    /// [`get_related`](entity/trait.Entity.html#method.get_related) and [`get_related_with_name`](entity/trait.Entity.html#method.get_related_with_name)
+    /// return entities sorted by increasing order, after which come the entities related without an order, sorted by key.
+    /// The order only applies from this entity to `other` : entities related to `other` are not affected.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// playlist.create_relation_ordered(&song_1, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, Some("tracks"), 0, &db)?;
+    /// playlist.create_relation_ordered(&song_2, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, Some("tracks"), 1, &db)?;
+    /// let tracks = playlist.get_related_with_name::<Song>("tracks", &db)?;
+    /// ```
+    fn create_relation_ordered<E: Entity>(
+        &self,
+        other: &E,
+        self_to_other: DeletionBehaviour,
+        other_to_self: DeletionBehaviour,
+        name: Option<&str>,
+        order: i64,
+        db: &Db,
+    ) -> Result<()> {
+        Relation::create_ordered(self, other, self_to_other, other_to_self, name, order, db)
+    }
+
+    /// Changes the order of the relations from this entity to `other`.
+    /// See [`create_relation_ordered`](entity/trait.Entity.html#method.create_relation_ordered).
+    ///
+    /// Results in a `NotFound` error if both entities are not related.
+    fn reorder_relation<E: Entity>(&self, other: &E, order: i64, db: &Db) -> Result<()> {
+        Relation::reorder(self, other, order, db)
+    }
+
+    /// Starts building a free relation between this entity and another one,
+    /// naming each deletion behaviour explicitly instead of passing them positionally.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// user.relate_to(&document)
+    ///     .on_self_delete(DeletionBehaviour::Cascade)
+    ///     .on_other_delete(DeletionBehaviour::Error)
+    ///     .named("owner")
+    ///     .commit(&db)?;
+    /// ```
+    fn relate_to<'a, E: Entity>(&'a self, other: &'a E) -> RelationBuilder<'a, Self, E> {
+        RelationBuilder::new(self, other)
+    }
+
+    /// Makes `desired` the exact set of entities of their store linked to this one with the relation `name`.
+    ///
+    /// Existing relations are compared to `desired` : only missing relations are created
+    /// (using `self_to_other` and `other_to_self`), and only relations to entities absent from `desired` are removed.
+    /// Relations with other names are left untouched.
+    ///
+    /// Returns the number of added and removed relations.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let (added, removed) = user.set_relations(
+    ///     &roles,
+    ///     DeletionBehaviour::BreakLink,
+    ///     DeletionBehaviour::BreakLink,
+    ///     Some("roles"),
+    ///     &db,
+    /// )?;
+    /// ```
+    fn set_relations<E: Entity>(
+        &self,
+        desired: &[E],
+        self_to_other: DeletionBehaviour,
+        other_to_self: DeletionBehaviour,
+        name: Option<&str>,
+        db: &Db,
+    ) -> Result<(usize, usize)> {
+        Relation::set(self, desired, self_to_other, other_to_self, name, db)
+    }
+
+    /// Changes the deletion behaviours and the name of an existing relation between two entities,
+    /// without removing it in between.
+    ///
+    /// If several relations with different names exist between the two entities, they are replaced by a single one.
+    /// ⚠ If no relation exists between the two entities, this will result in a `NotFound` error.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// m_struct_1.update_relation(&m_struct_2, DeletionBehaviour::Cascade, DeletionBehaviour::BreakLink, None, &db)?;
+    /// ```
+    fn update_relation<E: Entity>(
+        &self,
+        other: &E,
+        new_self_to_other: DeletionBehaviour,
+        new_other_to_self: DeletionBehaviour,
+        new_name: Option<&str>,
+        db: &Db,
+    ) -> Result<()> {
+        Relation::update(
+            self,
+            other,
+            new_self_to_other,
+            new_other_to_self,
+            new_name,
+            db,
+        )
+    }
+
+    /// Renames every relation of this entity named `old` to `new`, on both ends of each relation,
+    /// and returns the number of renamed relations.
+    ///
+    /// See also [`rename_relation_name_global`](fn.rename_relation_name_global.html) to rename relations for a whole store.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// user.rename_relation_name("owner", "admin", &db)?;
+    /// ```
+    fn rename_relation_name(&self, old: &str, new: &str, db: &Db) -> Result<usize> {
+        Relation::rename_with_tree_name(
+            Self::store_name(),
+            &Self::encode_key(self.get_key()),
+            old,
+            new,
+            db,
+        )
+    }
+
     /// Breaks an existing link between two entities.
     ///
     /// This will remove the relation in both ways.
@@ -604,13 +2847,16 @@ pub trait Entity: Serialize + DeserializeOwned {
 
     #[doc(hidden)]
     fn remove_relation_with_key<E: Entity>(&self, other: &[u8], db: &Db) -> Result<()> {
-        Relation::remove_by_keys::<Self, E>(&self.get_key().as_bytes(), other, db)
+        Relation::remove_by_keys::<Self, E>(&Self::encode_key(self.get_key()), other, db)
     }
 
     /// Gets all entities related to this one in another store.
     ///
+    /// Entities related with an [order](entity/trait.Entity.html#method.create_relation_ordered) come first,
+    /// sorted by order, followed by the other ones sorted by key.
+    ///
     /// ### Exemple
-    /// ```rust
+    /// ```rust,ignore
     /// let m_struct_1 = MyStruct1::get(&9,&db)?;
     /// let related_struct2s = m_struct_1.get_related::<MyStruct2>(&db)?;
     /// ```
@@ -618,10 +2864,270 @@ pub trait Entity: Serialize + DeserializeOwned {
         Relation::get::<Self, E>(self, db)
     }
 
-    /// Gets all the entities related to this one in another store with a given relation name
+    /// Gets the keys of all entities related to this one in another store, without fetching them.
+    ///
+    /// Every stored key is checked to be a valid `E::Key` : a malformed relation results in an `IntegrityError`.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// let related_keys = m_struct_1.get_related_keys::<MyStruct2>(&db)?;
+    /// ```
+    fn get_related_keys<E: Entity>(&self, db: &Db) -> Result<Vec<E::Key>>
+    where
+        E::Key: FromBytes,
+    {
+        Relation::get_keys::<Self, E>(self, db)
+    }
+
+    /// Counts the entities of store `E` related to each entity of `sources`, reading the relations
+    /// of each source once, without fetching any related entity.
+    ///
+    /// If `name` is provided, only relations with this name are counted. Otherwise, every related entity
+    /// is counted once, whatever the names of its relations. Counts are returned in the order of `sources`.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let organizations = Organization::get_all(&db)?;
+    /// let member_counts = Organization::related_counts_batch::<User>(&organizations, Some("member"), &db)?;
+    /// ```
+    fn related_counts_batch<E: Entity>(
+        sources: &[Self],
+        name: Option<&str>,
+        db: &Db,
+    ) -> Result<Vec<usize>> {
+        sources
+            .iter()
+            .map(|source| {
+                let relations = Relation::relations(source, db)?;
+                let keys = relations
+                    .related_entities
+                    .get(E::store_name())
+                    .into_iter()
+                    .flatten()
+                    .filter(|rd| name.is_none() || rd.name.as_deref() == name)
+                    .map(|rd| &rd.key)
+                    .collect::<HashSet<_>>();
+                Ok(keys.len())
+            })
+            .collect()
+    }
+
+    /// Gets all entities related to this one in another store that match a condition
+    /// materialized as a function returning a boolean.
+    ///
+    /// Related entities are fetched one at a time, and rejected ones are dropped right away
+    /// instead of being collected first.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let team = Team::get(&9,&db)?.unwrap();
+    /// let active_members = team.get_related_filtered::<Member, _>(|member| member.active, &db)?;
+    /// ```
+    fn get_related_filtered<E: Entity, F: Fn(&E) -> bool>(&self, f: F, db: &Db) -> Result<Vec<E>> {
+        self.related_iter::<E>(db)?
+            .filter(|related| match related {
+                Ok(entity) => f(entity),
+                Err(_) => true,
+            })
+            .collect()
+    }
+
+    /// Serializes this entity along with its related entities of another store into a JSON value
+    /// of the form `{ "self": {...}, "related": [{...}, ...] }`.
+    ///
+    /// If `relation_name` is provided, only entities related with this relation name are embedded.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let author = Author::get(&9,&db)?.unwrap();
+    /// let response = author.to_json_with_relations::<Book>(Some("author_of"),&db)?;
+    /// ```
+    fn to_json_with_relations<E: Entity>(
+        &self,
+        relation_name: Option<&str>,
+        db: &Db,
+    ) -> Result<serde_json::Value> {
+        let related = match relation_name {
+            Some(name) => self.get_related_with_name::<E>(name, db)?,
+            None => self.get_related::<E>(db)?,
+        };
+        Ok(serde_json::json!({
+            "self": serde_json::to_value(self)?,
+            "related": serde_json::to_value(related)?,
+        }))
+    }
+
+    /// Computes the fields that differ between this entity and `other`, as a JSON object of the form
+    /// `{ "field": { "from": x, "to": y } }`, where `x` is the value of `self` and `y` the value of `other`.
+    /// Fields that only exist in one of them (such as skipped `None` options) are compared with `null`.
+    ///
+    /// Fields are compared as a whole : a change in a nested struct reports the entire field.
+    /// Entities that are not serialized as JSON objects are compared as a whole, under the empty field name.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let stored = User::get(&9,&db)?.unwrap();
+    /// let changes = stored.diff(&proposed)?;
+    /// proposed.save(&db)?;
+    /// audit_log.push(changes);
+    /// ```
+    fn diff(&self, other: &Self) -> Result<serde_json::Value> {
+        let mut changes = serde_json::Map::new();
+        match (serde_json::to_value(self)?, serde_json::to_value(other)?) {
+            (serde_json::Value::Object(mut from), serde_json::Value::Object(mut to)) => {
+                let fields = from
+                    .keys()
+                    .chain(to.keys())
+                    .cloned()
+                    .collect::<BTreeSet<_>>();
+                for field in fields {
+                    let from = from.remove(&field).unwrap_or_default();
+                    let to = to.remove(&field).unwrap_or_default();
+                    if from != to {
+                        changes.insert(field, serde_json::json!({ "from": from, "to": to }));
+                    }
+                }
+            }
+            (from, to) => {
+                if from != to {
+                    changes.insert(String::new(), serde_json::json!({ "from": from, "to": to }));
+                }
+            }
+        }
+        Ok(serde_json::Value::Object(changes))
+    }
+
+    /// Gets an iterator over the entities related to this one in another store.
+    ///
+    /// Related keys are read upfront, but each entity is only fetched from the database
+    /// when the iterator reaches it, so that only a few of many related entities can be loaded.
+    /// Keys of entities that do not exist anymore are skipped.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// let first_ten = m_struct_1
+    ///     .related_iter::<MyStruct2>(&db)?
+    ///     .take(10)
+    ///     .collect::<Result<Vec<_>>>()?;
+    /// ```
+    fn related_iter<E: Entity>(&self, db: &Db) -> Result<impl Iterator<Item = Result<E>>> {
+        let keys = Relation::relations(self, db)?.take_ordered(E::store_name());
+        let tree = E::get_tree(db)?;
+        let format = StorageFormat::of(db)?;
+        Ok(keys
+            .into_iter()
+            .filter_map(move |rd| match tree.get(&rd.key) {
+                Ok(Some(vec)) if E::has_own_tag(&vec) => Some(E::from_ivec(&rd.key, &vec, format)),
+                Ok(_) => None,
+                Err(error) => Some(Err(error.into())),
+            }))
+    }
+
+    /// Gets the keys of all entities related to this one, grouped by store name.
+    ///
+    /// Keys are returned in their binary representation, so that related entities can be listed
+    /// without knowing their types at compile time. Stores without any related entity are omitted.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// for (store_name, keys) in m_struct_1.related_summary(&db)? {
+    ///     println!("{} related entities in {}", keys.len(), store_name);
+    /// }
+    /// ```
+    fn related_summary(&self, db: &Db) -> Result<HashMap<String, Vec<Vec<u8>>>> {
+        Relation::summary(self, db)
+    }
+
+    /// Gets all entities of another store that can be reached from this one by following
+    /// free relations, up to `depth` hops away.
+    ///
+    /// Each reachable entity is returned only once, and cycles in the relation graph are
+    /// safely ignored. This entity itself is never part of the result.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let user = User::get(&9,&db)?;
+    /// let friends_of_friends = user.get_related_depth::<User>(2,&db)?;
+    /// ```
+    fn get_related_depth<E: Entity>(&self, depth: usize, db: &Db) -> Result<Vec<E>> {
+        Relation::get_in_depth::<Self, E>(self, depth, db)
+    }
+
+    /// Loads the entity `root` along with its descendants, up to `max_depth` levels below it,
+    /// as a nested [`TreeNode`](struct.TreeNode.html). `children_of` gives the keys of the children of an entity,
+    /// for instance by reading a list of keys it holds, or the keys of its related entities of the same store.
+    ///
+    /// Entities are loaded one level at a time, each level with a single batch read.
+    /// Each entity appears only once in the tree, so that cycles are safely ignored, and
+    /// children that do not exist are skipped. Results in a `NotFound` error if `root` does not exist.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let thread = Comment::fetch_tree(&comment_id, |comment| comment.replies.clone(), 10, &db)?;
+    /// ```
+    fn fetch_tree<F: Fn(&Self) -> Vec<Self::Key>>(
+        root: &Self::Key,
+        children_of: F,
+        max_depth: usize,
+        db: &Db,
+    ) -> Result<TreeNode<Self>> {
+        let root = Self::get(root, db)?.ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "Entity with key {:?} does not exist in {}",
+                    Self::encode_key(root),
+                    Self::store_name()
+                ),
+            )
+        })?;
+        let mut visited = HashSet::from([Self::encode_key(root.get_key())]);
+        let mut children: Vec<Vec<usize>> = vec![Vec::new()];
+        let mut values = vec![Some(root)];
+        let mut level = vec![0];
+        for _ in 0..max_depth {
+            let mut wanted = Vec::new();
+            for &parent in &level {
+                let value = values[parent].as_ref().unwrap();
+                for key in children_of(value) {
+                    let key = Self::encode_key(&key);
+                    if visited.insert(key.clone()) {
+                        wanted.push((parent, key));
+                    }
+                }
+            }
+            if wanted.is_empty() {
+                break;
+            }
+            let keys = wanted
+                .iter()
+                .map(|(_, key)| key.clone())
+                .collect::<Vec<_>>();
+            let mut found = Self::get_each_u8(&keys, db)?
+                .into_iter()
+                .map(|value| (Self::encode_key(value.get_key()), value))
+                .collect::<HashMap<_, _>>();
+            level.clear();
+            for (parent, key) in wanted {
+                if let Some(value) = found.remove(&key) {
+                    children[parent].push(values.len());
+                    level.push(values.len());
+                    children.push(Vec::new());
+                    values.push(Some(value));
+                }
+            }
+        }
+        Ok(TreeNode::assemble(0, &mut values, &children))
+    }
+
+    /// Gets all the entities related to this one in another store with a given relation name,
+    /// sorted like [`get_related`](entity/trait.Entity.html#method.get_related).
     ///
     /// ### Exemple
-    /// ```rust
+    /// ```rust,ignore
     /// let m_struct_1 = MyStruct1::get(&9,&db)?;
     /// let related_struct2s = m_struct_1.get_related_with_name::<MyStruct2>("collection",&db)?;
     ///
@@ -632,7 +3138,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// Gets the first entity related to this one in another store.
     ///
     /// ### Exemple
-    /// ```rust
+    /// ```rust,ignore
     /// let m_struct_1 = MyStruct1::get(&9,&db)?;
     /// let m_struct_2 = m_struct_1.get_single_related::<MyStruct2>(&db)?;
     /// ```
@@ -643,7 +3149,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// Gets the first entity related to this one in another store with a given relation name
     ///
     /// ### Exemple
-    /// ```rust
+    /// ```rust,ignore
     /// let m_struct_1 = MyStruct1::get(&9,&db)?;
     /// let m_struct_2 = m_struct_1.get_single_related_with_name::<MyStruct2>("main_book",&db)?;
     /// ```
@@ -661,6 +3167,17 @@ pub trait Entity: Serialize + DeserializeOwned {
         Relation::are_related_with_name(self, other, name, db)
     }
 
+    /// Checks if this entity has a relation named `name` to any entity of store `E`, without fetching it.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let user = User::get(&9,&db)?;
+    /// let is_admin_somewhere = user.has_related_with_name::<Organization>("admin",&db)?;
+    /// ```
+    fn has_related_with_name<E: Entity>(&self, name: &str, db: &Db) -> Result<bool> {
+        Relation::has_related_with_name::<Self, E>(self, name, db)
+    }
+
     /// Checks if a named relation exists between this entity and another, with one of the provided names
     fn is_related_to_with_any_name<E: Entity>(
         &self,
@@ -678,7 +3195,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// overriden
     ///
     /// ### Exemple
-    /// ```rust
+    /// ```rust,ignore
     /// let m_struct_1 = MyStruct1::get(&9,&db)?;
     /// let m_struct_2 = MyStruct2 { key : 0, prop9 : 32};
     /// m_struct_1.save_sibling(m_struct_2,&db)?;
@@ -691,7 +3208,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// Gets an Entity in another store with the same key as `self`
     ///
     /// ### Exemple
-    /// ```rust
+    /// ```rust,ignore
     /// let m_struct_1 = MyStruct1::get(&9,&db)?;
     /// let m_struct_2 = m_struct_1.get_sibling::<MyStruct2>(&db)?;
     /// ```
@@ -699,6 +3216,75 @@ pub trait Entity: Serialize + DeserializeOwned {
         E::get(self.get_key(), db)
     }
 
+    /// Checks if an Entity exists in another store with the same key as `self`
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// let has_sibling = m_struct_1.exists_sibling::<MyStruct2>(&db)?;
+    /// ```
+    fn exists_sibling<E: Entity<Key = Self::Key>>(&self, db: &Db) -> Result<bool> {
+        E::exists(self.get_key(), db)
+    }
+
+    /// Removes the Entity in another store with the same key as `self`.
+    /// ⚠ The sibling is removed with [`remove`](entity/trait.Entity.html#method.remove), honoring its own
+    /// declared deletion behaviours : if `self` is a sibling declared with `DeletionBehaviour::Error`,
+    /// this results in an error, and if it is declared with `DeletionBehaviour::Cascade`, `self` is removed as well.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// m_struct_1.remove_sibling::<MyStruct2>(&db)?;
+    /// ```
+    fn remove_sibling<E: Entity<Key = Self::Key>>(&self, db: &Db) -> Result<()> {
+        E::remove(self.get_key(), db)
+    }
+
+    /// Gets an Entity in another store with the same key as `self`, or creates it using `default`
+    /// if it does not exist yet.
+    ///
+    /// The created sibling's key is changed to match `self` before saving it. Creation is atomic :
+    /// if the sibling is concurrently created elsewhere, the existing sibling is returned instead.
+    /// As with [`save`](entity/trait.Entity.html#method.save), an `IntegrityError` is returned if the key of `self`
+    /// is empty or holds an entity of another type in the sibling's store.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let user = User::get(&9,&db)?;
+    /// let profile = user.get_or_create_sibling(|| Profile::default(),&db)?;
+    /// ```
+    fn get_or_create_sibling<E: Entity<Key = Self::Key>, F: FnOnce() -> E>(
+        &self,
+        default: F,
+        db: &Db,
+    ) -> Result<E> {
+        let format = StorageFormat::of(db)?;
+        let key = Self::encode_key(self.get_key());
+        check_key::<E>(&key)?;
+        let tree = E::read_tree(db)?;
+        if let Some(existing) = tree.get(&key)? {
+            E::check_own_tag(&key, Some(&existing))?;
+            return E::from_ivec(&key, &existing, format);
+        }
+        let mut sibling = default();
+        sibling.set_key(self.get_key());
+        match tree.compare_and_swap(&key, None as Option<&[u8]>, Some(sibling.to_ivec(format)?))? {
+            Ok(()) => {
+                E::record_version(&key, db)?;
+                sibling.save_instance_siblings(db)?;
+                #[cfg(feature = "changelog")]
+                crate::changelog::record(E::store_name(), &key, ChangeOp::Save, db)?;
+                Ok(sibling)
+            }
+            Err(error) => {
+                let existing = error.current.unwrap();
+                E::check_own_tag(&key, Some(&existing))?;
+                E::from_ivec(&key, &existing, format)
+            }
+        }
+    }
+
     /// Saves `child` in its own store after having changed its key to make it effectively a child of `self`
     /// `child` must be an Entity with a Key being the tuple `(Self::Key,_)` (`Self::Key` being the key type of the parent entity)
     ///
@@ -706,7 +3292,7 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// overriden
     ///
     /// ### Exemple
-    /// ```rust
+    /// ```rust,ignore
     /// let m_struct_1 = MyStruct1::get(&9,&db)?;
     /// let m_struct_2 = MyStruct2 { key : (0,44), prop9 : 44};
     /// m_struct1.save_child(m_struct2,&db)?;
@@ -716,6 +3302,7 @@ pub trait Entity: Serialize + DeserializeOwned {
         child: &mut E,
         db: &Db,
     ) -> Result<E::Key> {
+        Self::check_child_store::<E>(db)?;
         let key = (self.get_key().clone(), child.get_key().1.clone());
         child.set_key(&key);
         child.save(db)?;
@@ -725,11 +3312,15 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// Saves `child` in its own store after having changed its key to make it effectively a child of `self`
     /// `child` must be an Entity with a Key being the tuple `(Self::Key,u32)` (`Self::Key` being the key type of the parent entity)
     ///
+    /// Child indices are numbered across the whole child store : the index is one more than the highest index
+    /// given in the store, whichever its parent, so that the indices of removed children are not reused.
+    /// The first child of a store gets index 0, and an `IntegrityError` is returned once index `u32::MAX` is taken.
+    ///
     /// ⚠ Note that for child relations to be fully functionnal, [`get_child_trees`](entity/trait.Entity.html#method.get_child_trees) must be
     /// overriden
     ///
     /// ### Exemple
-    /// ```rust
+    /// ```rust,ignore
     /// let m_struct_1 = MyStruct1::get(&9,&db)?;
     /// let m_struct_2 = MyStruct2 { key : (0,0), prop9 : 44};
     /// m_struct1.save_next_child(m_struct2,&db)?;
@@ -739,88 +3330,679 @@ pub trait Entity: Serialize + DeserializeOwned {
         child: &mut E,
         db: &Db,
     ) -> Result<E::Key> {
-        let increment = match E::get_tree(db)?.last()? {
-            Some((key, _)) => {
-                let u32_part = key
-                    .iter()
-                    .rev()
-                    .take(size_of::<u32>())
-                    .rev()
-                    .copied()
-                    .collect::<Vec<u8>>();
-                u32::from_be_bytes(u32_part.try_into().unwrap()) + 1
-            }
-            None => Default::default(),
+        Self::check_child_store::<E>(db)?;
+        // the last allocated child key is recorded along with the length of its parent part,
+        // so that its index is read right after its parent key, whatever the parent key holds
+        let last_children = db.open_tree(internal_tree_name("last_children"))?;
+        let prefix = E::child_prefix(self.get_key());
+        let key = loop {
+            let recorded = last_children.get(E::store_name())?;
+            // children saved with an explicit index are not recorded : the last key of the store is
+            // considered as well, its index being its last bytes
+            let last_stored = match E::get_tree(db)?.last()? {
+                Some((key, _)) if key.len() >= size_of::<u32>() => {
+                    child_index(&key, key.len() - size_of::<u32>())
+                }
+                _ => None,
+            };
+            let last_recorded = match &recorded {
+                Some(recorded) => {
+                    let (last_key, parent_len): (Vec<u8>, u32) = bincode::deserialize(recorded)?;
+                    child_index(&last_key, parent_len as usize)
+                }
+                None => None,
+            };
+            let increment = match last_stored.max(last_recorded) {
+                Some(index) => index.checked_add(1).ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::IntegrityError,
+                        format!("No child index left in {}", E::store_name()),
+                    )
+                })?,
+                None => 0,
+            };
+            let key = (self.get_key().clone(), increment);
+            let record = bincode::serialize(&(E::encode_key(&key), prefix.len() as u32))?;
+            if last_children
+                .compare_and_swap(E::store_name(), recorded, Some(record))?
+                .is_ok()
+            {
+                break key;
+            }
         };
-        let key = (self.get_key().clone(), increment);
         child.set_key(&key);
         child.save(db)?;
         Ok(key)
     }
 
-    /// Reparents a child to this entity and saves the result to the database.
-    ///
-    /// ### Exemple
-    /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
-    /// let m_struct_2 = MyStruct2::get(&(7,2),&db)?;
-    /// m_struct1.adopt_as_next_child(m_struct2,&db)?;
-    /// ```
-    /// After this code, m_struct_2 now has key (9,2) instead of (7,2) and has changed
-    /// accordingly in the database.
-    fn adopt_as_next_child<E: Entity<Key = (Self::Key, u32)>>(
-        &self,
-        child: &mut E,
-        db: &Db,
-    ) -> Result<()> {
-        let old_id = child.get_key().clone();
-        self.save_next_child(child, db)?;
-        Relation::change_entity_id(
-            E::store_name(),
-            &old_id.as_bytes(),
-            &child.get_key().as_bytes(),
-            db,
-        )?;
-        E::remove(&old_id, db)?;
-        Ok(())
+    /// Reparents a child to this entity and saves the result to the database.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// let m_struct_2 = MyStruct2::get(&(7,2),&db)?;
+    /// m_struct1.adopt_as_next_child(m_struct2,&db)?;
+    /// ```
+    /// After this code, m_struct_2 now has key (9,2) instead of (7,2) and has changed
+    /// accordingly in the database.
+    fn adopt_as_next_child<E: Entity<Key = (Self::Key, u32)>>(
+        &self,
+        child: &mut E,
+        db: &Db,
+    ) -> Result<()> {
+        let old_id = child.get_key().clone();
+        self.save_next_child(child, db)?;
+        Relation::change_entity_id(
+            E::store_name(),
+            &E::encode_key(&old_id),
+            &E::encode_key(child.get_key()),
+            db,
+        )?;
+        E::remove(&old_id, db)?;
+        Ok(())
+    }
+
+    /// Reparents a child to this entity and saves the result to the database.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// let m_struct_2 = MyStruct2::get(&(7,2),&db)?;
+    /// m_struct1.adopt_as_next_child(m_struct2,&db)?;
+    /// ```
+    /// After this code, m_struct_2 now has key (9,2) instead of (7,2) and has changed
+    /// accordingly in the database.
+    fn adopt_child<E: Entity<Key = (Self::Key, T)>, T: Clone + AsBytes>(
+        &self,
+        child: &mut E,
+        db: &Db,
+    ) -> Result<()> {
+        let old_id = child.get_key().clone();
+        self.save_child(child, db)?;
+        Relation::change_entity_id(
+            E::store_name(),
+            &E::encode_key(&old_id),
+            &E::encode_key(child.get_key()),
+            db,
+        )?;
+        E::remove(&old_id, db)?;
+        Ok(())
+    }
+
+    /// Reparents a child to this entity, keeping its trailing id, and saves the result to the database.
+    ///
+    /// Contrary to [`adopt_child`](entity/trait.Entity.html#method.adopt_child), this never overwrites
+    /// an existing child : if this entity already has a child with the same trailing id,
+    /// it results in an `IntegrityError` and nothing is changed.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// let m_struct_2 = MyStruct2::get(&(7,2),&db)?;
+    /// m_struct1.adopt_child_keep_id(m_struct2,&db)?;
+    /// ```
+    /// After this code, m_struct_2 now has key (9,2) instead of (7,2), unless (9,2) was already taken.
+    fn adopt_child_keep_id<E: Entity<Key = (Self::Key, T)>, T: Clone + AsBytes>(
+        &self,
+        child: &mut E,
+        db: &Db,
+    ) -> Result<()> {
+        Self::check_child_store::<E>(db)?;
+        let old_id = child.get_key().clone();
+        let new_id = (self.get_key().clone(), old_id.1.clone());
+        if E::encode_key(&new_id) == E::encode_key(&old_id) {
+            return Ok(());
+        }
+        child.set_key(&new_id);
+        // the new key is only written if it is vacant, so that a concurrent writer is never overwritten
+        if !child.save_if_vacant(db)? {
+            child.set_key(&old_id);
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Cannot adopt child : key {:?} is already taken in {}",
+                    E::encode_key(&new_id),
+                    E::store_name()
+                ),
+            ));
+        }
+        Relation::change_entity_id(
+            E::store_name(),
+            &E::encode_key(&old_id),
+            &E::encode_key(&new_id),
+            db,
+        )?;
+        E::remove(&old_id, db)?;
+        Ok(())
+    }
+
+    /// Gets children Entities from another store
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// let children = m_struct_1.get_children::<MyStruct2>(&db)?;
+    /// ```
+    fn get_children<E: Entity<Key = (Self::Key, u32)>>(&self, db: &Db) -> Result<Vec<E>> {
+        Self::check_child_store::<E>(db)?;
+        E::get_with_prefix(self.get_key(), db)
+    }
+
+    /// Gets the children Entities of several parents at once, as a map from the encoded key of each parent
+    /// to its children, in child index order. Every parent has an entry, even if it has no children.
+    ///
+    /// When the children of all parents are close in sort order, they are read with a single range scan,
+    /// as long as it skips at most a few entries per parent (see [`set_range_scan_ratio`](fn.set_range_scan_ratio.html)).
+    /// Otherwise, the children of each parent are read with their own prefix scan.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let chats = Chat::get_all(&db)?;
+    /// let mut messages = Chat::children_batch::<Message>(&chats, &db)?;
+    /// for chat in &chats {
+    ///     let chat_messages = messages.remove(&Chat::encode_key(chat.get_key())).unwrap_or_default();
+    /// }
+    /// ```
+    fn children_batch<E: Entity<Key = (Self::Key, u32)>>(
+        parents: &[Self],
+        db: &Db,
+    ) -> Result<HashMap<Vec<u8>, Vec<E>>> {
+        Self::check_child_store::<E>(db)?;
+        let mut children: HashMap<Vec<u8>, Vec<E>> = HashMap::new();
+        let mut parent_of = HashMap::new();
+        for parent in parents {
+            let key = Self::encode_key(parent.get_key());
+            parent_of.insert(E::child_prefix(parent.get_key()), key.clone());
+            children.insert(key, Vec::new());
+        }
+        // a child key is the prefix of its parent followed by its 4 bytes index
+        let owner = |key: &[u8]| {
+            key.len()
+                .checked_sub(4)
+                .and_then(|len| parent_of.get(&key[..len]))
+        };
+        let mut prefixes = parent_of.keys().collect::<Vec<_>>();
+        prefixes.sort();
+        let tree = E::read_tree(db)?;
+        let ratio = RANGE_SCAN_RATIO.load(Ordering::Relaxed);
+        let mut entries = Vec::new();
+        let mut scanned = false;
+        if let (Some(first), Some(last)) = (prefixes.first(), prefixes.last()) {
+            if ratio > 0 && prefixes.len() > 1 {
+                let max_skipped = prefixes.len().saturating_mul(ratio);
+                let mut skipped = 0;
+                scanned = true;
+                for elem in tree.range(first.as_slice()..) {
+                    let (key, vec) = elem?;
+                    if key.as_ref() > last.as_slice() && !key.starts_with(last) {
+                        break;
+                    }
+                    if owner(&key).is_some() {
+                        entries.push((key, vec));
+                    } else if skipped == max_skipped {
+                        scanned = false;
+                        entries.clear();
+                        break;
+                    } else {
+                        skipped += 1;
+                    }
+                }
+            }
+        }
+        if !scanned {
+            for prefix in &prefixes {
+                // the prefix of a parent also holds the children of parents whose key starts with its key
+                let parent = parent_of.get(*prefix);
+                for elem in tree.scan_prefix(prefix) {
+                    let (key, vec) = elem?;
+                    if owner(&key) == parent {
+                        entries.push((key, vec));
+                    }
+                }
+            }
+        }
+        let format = StorageFormat::of(db)?;
+        for (key, vec) in entries {
+            if let Some(parent_key) = owner(&key) {
+                if E::has_own_tag(&vec) {
+                    let child = E::from_ivec(&key, &vec, format)?;
+                    children.entry(parent_key.clone()).or_default().push(child);
+                }
+            }
+        }
+        Ok(children)
+    }
+
+    /// Gets at most `limit` children Entities from another store, starting right after the child of index `after`
+    /// (or at the first child if `after` is `None`), in child index order.
+    ///
+    /// Also returns the cursor to pass as `after` to get the next page, or `None` if there are no more children.
+    /// Each page is read by seeking directly to the cursor, so reading a page does not depend on how many pages come before it.
+    /// A `limit` of 0 results in an `IntegrityError`.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let chat = Chat::get(&9,&db)?;
+    /// let (messages, cursor) = chat.get_children_page::<Message>(None, 50, &db)?;
+    /// if let Some(cursor) = cursor {
+    ///     let (more_messages, cursor) = chat.get_children_page::<Message>(Some(cursor), 50, &db)?;
+    /// }
+    /// ```
+    fn get_children_page<E: Entity<Key = (Self::Key, u32)>>(
+        &self,
+        after: Option<u32>,
+        limit: usize,
+        db: &Db,
+    ) -> Result<(Vec<E>, Option<u32>)> {
+        check_page_size(limit)?;
+        Self::check_child_store::<E>(db)?;
+        let prefix = E::child_prefix(self.get_key());
+        let start = match after {
+            Some(index) => Bound::Excluded(E::encode_key(&(self.get_key().clone(), index))),
+            None => Bound::Included(prefix.clone()),
+        };
+        let format = StorageFormat::of(db)?;
+        let mut children = Vec::new();
+        let mut cursor = after;
+        for elem in E::read_tree(db)?.range::<Vec<u8>, _>((start, Bound::Unbounded)) {
+            let (key, vec) = elem?;
+            if !key.starts_with(&prefix) {
+                return Ok((children, None));
+            }
+            if !Relation::is_child_key(&prefix, &key) || !E::has_own_tag(&vec) {
+                continue;
+            }
+            if children.len() == limit {
+                return Ok((children, cursor));
+            }
+            let child = E::from_ivec(&key, &vec, format)?;
+            cursor = Some(child.get_key().1);
+            children.push(child);
+        }
+        Ok((children, None))
+    }
+
+    /// Checks that `E`'s store is declared as a child store of this entity, if this entity is registered.
+    /// Child keys only embed the parent key's bytes, so using a child store with the wrong parent type
+    /// would otherwise silently match unrelated entries.
+    #[doc(hidden)]
+    fn check_child_store<E: Entity>(db: &Db) -> Result<()> {
+        if let Some(family_descriptor) =
+            FamilyDescriptor::get(&String::from(Self::store_name()), db)?
+        {
+            if !family_descriptor
+                .child_trees
+                .iter()
+                .any(|(tree_name, _)| tree_name == E::store_name())
+            {
+                return Err(Error::new(
+                    ErrorKind::IntegrityError,
+                    format!(
+                        "{} is not registered as a child store of {}",
+                        E::store_name(),
+                        Self::store_name()
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// An entity type using a store, as recorded by `claim_store_name`
+#[derive(Serialize, Deserialize)]
+struct StoreClaim {
+    tag: Option<String>,
+    // the process that registered `type_name` last : type names are only comparable within a process
+    process: u64,
+    type_name: String,
+}
+
+// A random identifier of the current process
+fn process_id() -> u64 {
+    static PROCESS_ID: OnceLock<u64> = OnceLock::new();
+    *PROCESS_ID.get_or_init(|| RandomState::new().build_hasher().finish())
+}
+
+static RANGE_SCAN_RATIO: AtomicUsize = AtomicUsize::new(4);
+
+/// Sets how sparse a set of keys can be for related entities to be read with a single range scan.
+///
+/// When reading several entities at once (for instance with [`get_related`](entity/trait.Entity.html#method.get_related)),
+/// keys are read with one scan between the smallest and the greatest key, as long as the scan
+/// reads at most `ratio` entries per requested key. Beyond that, each key is read separately.
+///
+/// The default ratio is 4. Use 0 to always read keys separately.
+///
+/// ### Example
+/// ```rust,ignore
+/// reindeer::set_range_scan_ratio(8);
+/// ```
+pub fn set_range_scan_ratio(ratio: usize) {
+    RANGE_SCAN_RATIO.store(ratio, Ordering::Relaxed);
+}
+
+/// Forgets which entity types use the store `store_name`, so that another entity type can be registered with it.
+///
+/// This is needed to replace the entity types of a store with types that have different
+/// [`type_tag`s](entity/trait.Entity.html#method.type_tag), or with another type in the same process, since
+/// [`register`](entity/trait.Entity.html#method.register) would otherwise result in an `IntegrityError`.
+/// The entities of the store are left untouched.
+///
+/// ### Example
+/// ```rust,ignore
+/// reindeer::release_store_name("user", &db)?;
+/// User::register(&db)?;
+/// ```
+pub fn release_store_name(store_name: &str, db: &Db) -> Result<()> {
+    db.open_tree(internal_tree_name("store_types"))?
+        .remove(store_name)?;
+    Ok(())
+}
+
+/// Removes the entities of store `C` whose parent, as given by `parent_key_of`, does not exist in store `P`,
+/// and returns the number of removed entities.
+///
+/// This cleans up records that reference their parent through a key stored in their value, for instance
+/// after their parent was removed by older code that did not handle them. Orphans are removed with
+/// [`Entity::remove`](entity/trait.Entity.html#method.remove), so that their own relations are handled as usual.
+///
+/// ⚠ This is an O(n) operation, since every entity of store `C` is read.
+///
+/// ### Example
+/// ```rust,ignore
+/// let removed = reindeer::prune_orphans::<Comment, Post, _>(&db, |comment| comment.post_id)?;
+/// ```
+pub fn prune_orphans<C: Entity, P: Entity, F: Fn(&C) -> P::Key>(
+    db: &Db,
+    parent_key_of: F,
+) -> Result<usize> {
+    let mut removed = 0;
+    for child in C::get_all(db)? {
+        // a previous removal may have cascaded to this entity
+        if !P::exists(&parent_key_of(&child), db)? && C::exists(child.get_key(), db)? {
+            C::remove(child.get_key(), db)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+static REGISTRATION_CHECK: AtomicU8 = AtomicU8::new(RegistrationCheck::Lenient as u8);
+
+/// Defines what happens when entities are read from a store that was not registered with
+/// [`register`](entity/trait.Entity.html#method.register).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RegistrationCheck {
+    /// Reading from an unregistered store is allowed. This is the default.
+    Lenient,
+    /// Reading from an unregistered store is allowed, but a warning is emitted as a `tracing` event.
+    /// Without the `tracing` feature, this is the same as `Lenient`.
+    Warn,
+    /// Reading from an unregistered store results in an `UnregisteredEntity` error.
+    Error,
+}
+
+impl RegistrationCheck {
+    fn current() -> RegistrationCheck {
+        match REGISTRATION_CHECK.load(Ordering::Relaxed) {
+            1 => RegistrationCheck::Warn,
+            2 => RegistrationCheck::Error,
+            _ => RegistrationCheck::Lenient,
+        }
+    }
+}
+
+/// Sets how reads check that entities are registered, for every database of the process.
+///
+/// Unregistered entities can be read, but cannot be removed. Using `RegistrationCheck::Error`
+/// makes a forgotten call to [`register`](entity/trait.Entity.html#method.register) surface at the first read
+/// instead of the first removal, at the cost of an extra lookup for every read.
+///
+/// ### Example
+/// ```rust,ignore
+/// reindeer::set_registration_check(RegistrationCheck::Error);
+/// ```
+pub fn set_registration_check(check: RegistrationCheck) {
+    REGISTRATION_CHECK.store(check as u8, Ordering::Relaxed);
+}
+
+/// The kind of relation a store has with the stores it declares, as reported in a [`RegistrationDiff`](struct.RegistrationDiff.html).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum FamilyRelation {
+    /// A sibling store, declared by [`get_sibling_trees`](entity/trait.Entity.html#method.get_sibling_trees)
+    Sibling,
+    /// A child store, declared by [`get_child_trees`](entity/trait.Entity.html#method.get_child_trees)
+    Child,
+}
+
+/// Differences between the sibling and child stores recorded for an entity and the ones it currently declares,
+/// as returned by [`Entity::reconcile_registration`](trait.Entity.html#method.reconcile_registration).
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct RegistrationDiff {
+    /// Stores that are declared but were not recorded, with their deletion behaviour
+    pub added: Vec<(FamilyRelation, String, DeletionBehaviour)>,
+    /// Stores that were recorded but are not declared anymore, with their recorded deletion behaviour
+    pub removed: Vec<(FamilyRelation, String, DeletionBehaviour)>,
+    /// Stores whose deletion behaviour changed, from the recorded one to the declared one
+    pub changed: Vec<(FamilyRelation, String, DeletionBehaviour, DeletionBehaviour)>,
+}
+
+impl RegistrationDiff {
+    /// Whether the recorded stores are the same as the declared ones.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    fn compare(
+        &mut self,
+        relation: FamilyRelation,
+        recorded: &[(String, DeletionBehaviour)],
+        declared: &[(&'static str, DeletionBehaviour)],
+    ) {
+        for (tree_name, behaviour) in declared {
+            match recorded.iter().find(|(name, _)| name == tree_name) {
+                None => self
+                    .added
+                    .push((relation, String::from(*tree_name), *behaviour)),
+                Some((_, previous)) if previous != behaviour => {
+                    self.changed
+                        .push((relation, String::from(*tree_name), *previous, *behaviour))
+                }
+                _ => {}
+            }
+        }
+        for (tree_name, behaviour) in recorded {
+            if !declared.iter().any(|(name, _)| name == tree_name) {
+                self.removed.push((relation, tree_name.clone(), *behaviour));
+            }
+        }
+    }
+}
+
+/// The encoded key and error of every entity that could not be read, as collected with `ReadErrorPolicy::Collect`.
+pub type ReadErrors = Vec<(Vec<u8>, Error)>;
+
+/// What bulk readers such as [`Entity::get_all_with_policy`](trait.Entity.html#method.get_all_with_policy)
+/// do with entities that cannot be read.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ReadErrorPolicy {
+    /// The read is aborted with the error of the first unreadable entity
+    Abort,
+    /// Unreadable entities are ignored. With the `tracing` feature, a warning is emitted with their keys
+    Skip,
+    /// Unreadable entities are returned with their errors, along with the readable ones
+    Collect,
+}
+
+/// Strategy to use when importing an entity whose key already exists in the store.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ImportStrategy {
+    /// The existing entity is replaced by the imported one
+    Overwrite,
+    /// The existing entity is kept and the imported one is ignored
+    Skip,
+    /// The import is aborted with an error
+    Error,
+}
+
+/// Defines what happens to imported relations pointing to entities that do not exist.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DanglingRelations {
+    /// Dangling relations are not imported
+    Drop,
+    /// The import is aborted with an error
+    Error,
+}
+
+/// A page of entities, as returned by [`Entity::paginate`](trait.Entity.html#method.paginate).
+#[derive(Serialize, Debug)]
+pub struct Page<E> {
+    /// The entities of the page
+    pub items: Vec<E>,
+    /// The total number of entities in all pages
+    pub total: usize,
+    /// The index of the page, starting at 0
+    pub page: usize,
+    /// The maximum number of entities in a page
+    pub per_page: usize,
+    /// Whether there are entities after this page
+    pub has_next: bool,
+    /// Whether there are entities before this page
+    pub has_prev: bool,
+}
+
+impl<E> Page<E> {
+    fn new(items: Vec<E>, total: usize, page: usize, per_page: usize) -> Page<E> {
+        let end = page.saturating_add(1).saturating_mul(per_page);
+        Page {
+            items,
+            total,
+            page,
+            per_page,
+            has_next: end < total,
+            has_prev: page > 0 && total > 0,
+        }
+    }
+}
+
+// Reads the index of a child key, stored right after its parent part of `parent_len` bytes
+fn child_index(key: &[u8], parent_len: usize) -> Option<u32> {
+    key.get(parent_len..parent_len + size_of::<u32>())
+        .map(|index| u32::from_be_bytes(index.try_into().unwrap()))
+}
+
+// Scans the entries of the direct children of the parent whose children keys start with `prefix`
+fn child_entries(
+    tree: &Tree,
+    prefix: Vec<u8>,
+) -> impl DoubleEndedIterator<Item = sled::Result<(IVec, IVec)>> {
+    tree.scan_prefix(&prefix).filter(move |elem| match elem {
+        Ok((key, _)) => Relation::is_child_key(&prefix, key),
+        Err(_) => true,
+    })
+}
+
+// Pages of 0 entities never reach the end of a store
+fn check_page_size(per_page: usize) -> Result<()> {
+    if per_page == 0 {
+        return Err(Error::new(
+            ErrorKind::IntegrityError,
+            String::from("Pages must hold at least one entity"),
+        ));
+    }
+    Ok(())
+}
+
+/// The outcome of saving an entity, as returned by [`Entity::save_with_receipt`](trait.Entity.html#method.save_with_receipt).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveReceipt<K> {
+    /// The key of the saved entity
+    pub key: K,
+    /// The size of the stored value, in bytes
+    pub bytes_written: usize,
+    /// Whether the entity replaced an existing record with the same key
+    pub was_update: bool,
+}
+
+/// The number of entities saved by [`Entity::upsert_many`](trait.Entity.html#method.upsert_many).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UpsertStats {
+    /// The number of entities whose key did not exist yet
+    pub inserted: usize,
+    /// The number of existing entities that were merged with incoming ones
+    pub updated: usize,
+}
+
+/// The outcome of removing several entities, as returned by [`Entity::filter_remove_report`](trait.Entity.html#method.filter_remove_report).
+#[derive(Debug)]
+pub struct RemoveReport<E> {
+    /// The removed entities
+    pub removed: Vec<E>,
+    /// The entities that could not be removed, along with the error that prevented it
+    pub blocked: Vec<(E, Error)>,
+}
+
+/// An entity along with its descendants, as returned by [`Entity::fetch_tree`](trait.Entity.html#method.fetch_tree).
+#[derive(Serialize, Debug)]
+pub struct TreeNode<E> {
+    /// The entity
+    pub value: E,
+    /// The children of the entity, in the order they were given
+    pub children: Vec<TreeNode<E>>,
+}
+
+impl<E> TreeNode<E> {
+    fn assemble(index: usize, values: &mut [Option<E>], children: &[Vec<usize>]) -> TreeNode<E> {
+        TreeNode {
+            value: values[index].take().unwrap(),
+            children: children[index]
+                .iter()
+                .map(|&child| TreeNode::assemble(child, values, children))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedEntity<E> {
+    entity: E,
+    relations: Vec<ExportedRelation>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedRelation {
+    store: String,
+    #[serde(with = "base64_key")]
+    key: Vec<u8>,
+    deletion_behaviour: DeletionBehaviour,
+    #[serde(default)]
+    reverse_deletion_behaviour: Option<DeletionBehaviour>,
+    name: Option<String>,
+}
+
+/// Exported keys are written as base64 strings. Files exported before that, where keys are arrays of bytes,
+/// can still be imported.
+mod base64_key {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    #[derive(serde_derive::Deserialize)]
+    #[serde(untagged)]
+    enum EncodedKey {
+        Base64(String),
+        Bytes(Vec<u8>),
     }
 
-    /// Reparents a child to this entity and saves the result to the database.
-    ///
-    /// ### Exemple
-    /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
-    /// let m_struct_2 = MyStruct2::get(&(7,2),&db)?;
-    /// m_struct1.adopt_as_next_child(m_struct2,&db)?;
-    /// ```
-    /// After this code, m_struct_2 now has key (9,2) instead of (7,2) and has changed
-    /// accordingly in the database.
-    fn adopt_child<E: Entity<Key = (Self::Key, T)>, T: Clone + AsBytes>(
-        &self,
-        child: &mut E,
-        db: &Db,
-    ) -> Result<()> {
-        let old_id = child.get_key().clone();
-        self.save_child(child, db)?;
-        Relation::change_entity_id(
-            E::store_name(),
-            &old_id.as_bytes(),
-            &child.get_key().as_bytes(),
-            db,
-        )?;
-        E::remove(&old_id, db)?;
-        Ok(())
+    pub fn serialize<S: Serializer>(key: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(key))
     }
 
-    /// Gets children Entities from another store
-    ///
-    /// ### Exemple
-    /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
-    /// let children = m_struct_1.get_children::<MyStruct2>(&db)?;
-    /// ```
-    fn get_children<E: Entity<Key = (Self::Key, u32)>>(&self, db: &Db) -> Result<Vec<E>> {
-        E::get_with_prefix(self.get_key(), db)
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        match EncodedKey::deserialize(deserializer)? {
+            EncodedKey::Base64(key) => STANDARD.decode(key).map_err(D::Error::custom),
+            EncodedKey::Bytes(key) => Ok(key),
+        }
     }
 }
 
@@ -835,7 +4017,7 @@ pub trait AutoIncrementEntity: Entity<Key = u32> {
 
     /// Saves the entity to the database after having modified its key to an auto-incremented one.
     /// ### Example
-    /// ```rust
+    /// ```rust,ignore
     /// let m_struct = MyStruct { key : 0, prop9 : 44};
     /// m_struct.save_next(&db)?; // will have key 0
     /// let m_struct_2 = MyStruct { key : 0, prop9 :59};
@@ -864,11 +4046,319 @@ where
     }
 }
 
+/// `ChildEntity` is a trait automatically implemented on Entities
+/// that have `(P, u32)` as their `Key` type, such as the ones saved with
+/// [`save_next_child`](entity/trait.Entity.html#method.save_next_child).
+///
+/// It provides accessors to both parts of the key, so that call sites do not depend on its shape.
+///
+/// ### Example
+/// ```rust,ignore
+/// let mut tasks = project.get_children::<Task>(&db)?;
+/// tasks.sort_by_key(|task| task.child_index());
+/// assert!(tasks.iter().all(|task| task.parent_key() == *project.get_key()));
+/// ```
+pub trait ChildEntity<P>: Entity<Key = (P, u32)> {
+    /// Returns the key of the parent entity
+    fn parent_key(&self) -> P;
+
+    /// Returns the index of the entity among the children of its parent
+    fn child_index(&self) -> u32;
+}
+
+impl<T, P> ChildEntity<P> for T
+where
+    T: Entity<Key = (P, u32)>,
+    P: Clone,
+{
+    fn parent_key(&self) -> P {
+        self.get_key().0.clone()
+    }
+
+    fn child_index(&self) -> u32 {
+        self.get_key().1
+    }
+}
+
+/// `ContentAddressedEntity` is a trait automatically implemented on Entities
+/// that have `[u8; 32]` as their `Key` type, when the `content-addressed` feature is enabled.
+///
+/// It provides the `save_content_addressed()` method, that uses the hash of the entity's
+/// content as its key, so that identical contents are only stored once.
+#[cfg(feature = "content-addressed")]
+pub trait ContentAddressedEntity: Entity<Key = [u8; 32]> {
+    /// Saves the entity to the database after having modified its key to the `blake3` hash
+    /// of its serialized content (the key itself being excluded from the hash).
+    ///
+    /// Saving an entity whose content already exists in the store does not write anything,
+    /// and returns the same hash.
+    /// ### Example
+    /// ```rust,ignore
+    /// let mut blob = Blob { hash : [0; 32], data : vec![1, 2, 3]};
+    /// let hash = blob.save_content_addressed(&db)?;
+    /// // blob.hash is now the hash of its content
+    /// ```
+    fn save_content_addressed(&mut self, db: &Db) -> Result<[u8; 32]>;
+}
+
+#[cfg(feature = "content-addressed")]
+impl<T> ContentAddressedEntity for T
+where
+    T: Entity<Key = [u8; 32]>,
+{
+    fn save_content_addressed(&mut self, db: &Db) -> Result<[u8; 32]> {
+        self.set_key(&[0; 32]);
+        let hash: [u8; 32] = blake3::hash(&bincode::serialize(self)?).into();
+        self.set_key(&hash);
+        if !Self::exists(&hash, db)? {
+            self.save(db)?;
+        }
+        Ok(hash)
+    }
+}
+
+/// `Mergeable` is a trait for entities that can be combined with a delta
+/// instead of being overwritten, such as counters.
+///
+/// It relies on `sled` merge operators so that concurrent
+/// [`merge_save`](entity/trait.Mergeable.html#method.merge_save) calls compose without any
+/// read-modify-write cycle on the caller's side, and therefore without lost updates.
+///
+/// ⚠ Values passed to `merge_save` represent *deltas*, not absolute values : a counter entity
+/// incremented by one should be merged with a `count` of 1, not with the expected new total.
+///
+/// ⚠ The merge operator is installed by [`register_mergeable`](entity/trait.Mergeable.html#method.register_mergeable),
+/// which must be used instead of `register` every time the database is opened.
+///
+/// ### Example
+/// ```rust,ignore
+/// impl Mergeable for PageViews {
+///     fn merge(existing: Option<Self>, delta: Self) -> Self {
+///         match existing {
+///             Some(existing) => PageViews { count: existing.count + delta.count, ..delta },
+///             None => delta,
+///         }
+///     }
+/// }
+/// ```
+pub trait Mergeable: Entity {
+    /// Combines the currently stored value (if any) with a delta, returning the new value to store.
+    fn merge(existing: Option<Self>, delta: Self) -> Self;
+
+    /// Registers the entity like [`register`](entity/trait.Entity.html#method.register), and installs the
+    /// `sled` merge operator used by [`merge_save`](entity/trait.Mergeable.html#method.merge_save) on its store.
+    ///
+    /// Merge operators are not persisted, so this must be called every time the database is opened,
+    /// before any `merge_save`, which otherwise results in an error.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// PageViews::register_mergeable(&db)?;
+    /// ```
+    fn register_mergeable(db: &Db) -> Result<()>
+    where
+        Self: 'static,
+    {
+        Self::register(db)?;
+        let format = StorageFormat::of(db)?;
+        Self::get_tree(db)?.set_merge_operator(
+            move |key: &[u8], existing: Option<&[u8]>, delta: &[u8]| {
+                merge_values::<Self>(key, existing, delta, format)
+            },
+        );
+        Ok(())
+    }
+
+    /// Merges `delta` into the entity stored under `key`, using [`merge`](entity/trait.Mergeable.html#tymethod.merge).
+    /// If no entity exists for this key, `merge` is called with `None`.
+    ///
+    /// The merged value is checked against the currently stored entity before merging, so that an unreadable
+    /// stored entity or a merged value exceeding [`MAX_VALUE_SIZE`](entity/trait.Entity.html#associatedconstant.MAX_VALUE_SIZE)
+    /// results in an error and nothing is written.
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// PageViews::merge_save(&3, PageViews { id: 3, count: 1 }, &db)?;
+    /// ```
+    fn merge_save(key: &Self::Key, mut delta: Self, db: &Db) -> Result<()> {
+        delta.set_key(key);
+        let key_bytes = Self::encode_key(key);
+        check_key::<Self>(&key_bytes)?;
+        let tree = Self::get_tree(db)?;
+        let format = StorageFormat::of(db)?;
+        let delta = delta.to_ivec(format)?;
+        let existing = tree
+            .get(&key_bytes)?
+            .map(|vec| Self::from_ivec(&key_bytes, &vec, format))
+            .transpose()?;
+        Self::merge(existing, Self::from_ivec(&key_bytes, &delta, format)?).to_ivec(format)?;
+        tree.merge(&key_bytes, delta)?;
+        Self::record_version(&Self::encode_key(key), db)?;
+        #[cfg(feature = "changelog")]
+        crate::changelog::record(
+            Self::store_name(),
+            &Self::encode_key(key),
+            ChangeOp::Save,
+            db,
+        )?;
+        Ok(())
+    }
+}
+
+/// `Timestamped` is a trait for entities that keep track of the last time they were updated.
+///
+/// It provides [`touch`](entity/trait.Timestamped.html#method.touch), which only bumps that timestamp.
+///
+/// ### Example
+/// ```rust,ignore
+/// impl Timestamped for Session {
+///     fn set_updated_at(&mut self, timestamp: u64) {
+///         self.updated_at = timestamp;
+///     }
+/// }
+/// ```
+pub trait Timestamped: Entity {
+    /// Sets the time of the last update, in milliseconds since the UNIX epoch.
+    fn set_updated_at(&mut self, timestamp: u64);
+
+    /// Returns the current time in milliseconds since the UNIX epoch.
+    ///
+    /// This is the only source of time used by `touch`, and can be overridden, for instance in tests.
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default()
+    }
+
+    /// Sets the time of the last update of the entity stored under `key` to [`now`](entity/trait.Timestamped.html#method.now),
+    /// without any other change.
+    ///
+    /// If no entity exists for this key, it results in a `NotFound` error. The entity is changed
+    /// atomically, as with [`update`](entity/trait.Entity.html#method.update).
+    ///
+    /// ### Example
+    /// ```rust,ignore
+    /// Session::touch(&session_id, &db)?;
+    /// ```
+    fn touch(key: &Self::Key, db: &Db) -> Result<()> {
+        let now = Self::now();
+        let found = Cell::new(false);
+        Self::update(
+            key,
+            |entity| {
+                found.set(true);
+                entity.set_updated_at(now);
+            },
+            db,
+        )?;
+        if !found.get() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("Cannot touch missing entity in {}", Self::store_name()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Minimal pseudo-random number generator, good enough to sample entities.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Adds the writes saving `items` to `batches`, along with their versions, instance siblings and changelog records.
+fn save_in<E: Entity>(items: &[E], batches: &mut TreeBatches, db: &Db) -> Result<()> {
+    let format = StorageFormat::of(db)?;
+    let tree = E::get_tree(db)?;
+    for item in items {
+        let key = E::encode_key(item.get_key());
+        check_key::<E>(&key)?;
+        if E::type_tag().is_some() {
+            E::check_own_tag(&key, tree.get(&key)?.as_deref())?;
+        }
+        let value = item.to_ivec(format)?;
+        batches.tree(E::store_name()).insert(key.as_slice(), value);
+        #[cfg(feature = "lazy-migration")]
+        crate::migration::record_version_in(E::store_name(), &key, E::VERSION, batches);
+        let siblings = item
+            .sibling_trees_for()
+            .into_iter()
+            .map(|(name, behaviour)| (String::from(name), behaviour))
+            .collect::<Vec<_>>();
+        Relation::save_instance_siblings_in(E::store_name(), &key, &siblings, batches, db)?;
+        #[cfg(feature = "changelog")]
+        crate::changelog::record_in(E::store_name(), &key, ChangeOp::Save, batches, db)?;
+    }
+    Ok(())
+}
+
+fn check_same_key<E: Entity>(key: &[u8], entity: &E) -> Result<()> {
+    if E::STRICT_KEYS && E::encode_key(entity.get_key()) != key {
+        return Err(Error::new(
+            ErrorKind::IntegrityError,
+            format!(
+                "Modification changed the key {:?} of an entity of {} to {:?}",
+                key,
+                E::store_name(),
+                E::encode_key(entity.get_key())
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn check_key<E: Entity>(key: &[u8]) -> Result<()> {
+    if key.is_empty() {
+        return Err(Error::new(
+            ErrorKind::IntegrityError,
+            format!(
+                "Cannot save an entity with an empty key in {}",
+                E::store_name()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn merge_values<M: Mergeable>(
+    key: &[u8],
+    existing: Option<&[u8]>,
+    delta: &[u8],
+    format: StorageFormat,
+) -> Option<Vec<u8>> {
+    let merged = existing
+        .map(|bytes| M::from_ivec(key, bytes, format))
+        .transpose()
+        .and_then(|existing| Ok(M::merge(existing, M::from_ivec(key, delta, format)?)))
+        .and_then(|merged| merged.to_ivec(format));
+    match merged {
+        Ok(merged) => Some(merged.to_vec()),
+        // a merge operator returning None removes the value : keep the existing one instead
+        Err(_) => existing.map(|bytes| bytes.to_vec()),
+    }
+}
+
 /// Trait allowing values to be converted to `Vec<u8>`.
 /// This trait is not meant to be implemented, but you can if you need to.
 pub trait AsBytes {
     /// Returns a new binary representation of `self` as a `Vec<u8>`
     fn as_bytes(&self) -> Vec<u8>;
+
+    /// Returns a new binary representation of `self`, where the members of tuples are separated by `separator`.
+    /// This is the same as `as_bytes` for other types.
+    fn as_separated_bytes(&self, separator: u8) -> Vec<u8> {
+        let _ = separator;
+        self.as_bytes()
+    }
 }
 
 impl AsBytes for String {
@@ -901,6 +4391,47 @@ impl AsBytes for i64 {
     }
 }
 
+// Floats are encoded so that their binary representations sort in numeric order :
+// the sign bit of positive numbers is flipped, and all the bits of negative numbers are flipped.
+// NaNs are all encoded as the positive quiet NaN, which sorts after positive infinity.
+// -0.0 and 0.0 are distinct keys, -0.0 sorting right before 0.0.
+macro_rules! impl_bytes_for_float {
+    ($($t:ty => $bits:ty),*) => {
+        $(
+            impl AsBytes for $t {
+                fn as_bytes(&self) -> Vec<u8> {
+                    let bits = if self.is_nan() {
+                        <$t>::NAN.to_bits()
+                    } else {
+                        self.to_bits()
+                    };
+                    let sign = 1 << (<$bits>::BITS - 1);
+                    let ordered = if bits & sign == 0 { bits | sign } else { !bits };
+                    ordered.to_be_bytes().to_vec()
+                }
+            }
+
+            impl FromBytes for $t {
+                fn from_bytes(bytes: &[u8]) -> Result<Self> {
+                    let ordered = bytes
+                        .try_into()
+                        .map(<$bits>::from_be_bytes)
+                        .map_err(|_| wrong_length::<$t>(size_of::<$t>(), bytes))?;
+                    let sign = 1 << (<$bits>::BITS - 1);
+                    let bits = if ordered & sign != 0 { ordered ^ sign } else { !ordered };
+                    Ok(<$t>::from_bits(bits))
+                }
+
+                fn fixed_len() -> Option<usize> {
+                    Some(size_of::<$t>())
+                }
+            }
+        )*
+    };
+}
+
+impl_bytes_for_float!(f32 => u32, f64 => u64);
+
 impl AsBytes for Vec<u8> {
     fn as_bytes(&self) -> Vec<u8> {
         self.clone()
@@ -913,12 +4444,298 @@ impl AsBytes for &[u8] {
     }
 }
 
+impl<const N: usize> AsBytes for [u8; N] {
+    fn as_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
 impl<K1, K2> AsBytes for (K1, K2)
 where
     K1: AsBytes,
     K2: AsBytes,
 {
     fn as_bytes(&self) -> Vec<u8> {
-        vec![self.0.as_bytes(), self.1.as_bytes()].concat()
+        [self.0.as_bytes(), self.1.as_bytes()].concat()
+    }
+
+    fn as_separated_bytes(&self, separator: u8) -> Vec<u8> {
+        [
+            self.0.as_separated_bytes(separator),
+            vec![separator],
+            self.1.as_separated_bytes(separator),
+        ]
+        .concat()
+    }
+}
+
+/// Trait allowing values to be built back from their binary representation,
+/// as produced by [`AsBytes`](entity/trait.AsBytes.html).
+/// This trait is not meant to be implemented, but you can if you need to.
+pub trait FromBytes: Sized {
+    /// Builds a new value from its binary representation, or returns an error if
+    /// `bytes` is not a valid representation of `Self`
+    fn from_bytes(bytes: &[u8]) -> Result<Self>;
+
+    /// Builds a new value from its binary representation, as produced by
+    /// [`as_separated_bytes`](entity/trait.AsBytes.html#method.as_separated_bytes).
+    /// Tuples stored without separator are read as well. This is the same as `from_bytes` for other types.
+    fn from_separated_bytes(bytes: &[u8], separator: u8) -> Result<Self> {
+        let _ = separator;
+        Self::from_bytes(bytes)
+    }
+
+    /// Returns the length of the binary representation of every value of this type,
+    /// or `None` if it depends on the value. This allows splitting the representation of tuples.
+    fn fixed_len() -> Option<usize> {
+        None
+    }
+}
+
+fn wrong_length<T>(expected: usize, bytes: &[u8]) -> Error {
+    Error::new(
+        ErrorKind::SerializationError,
+        format!(
+            "Expected {} bytes to read a {}, found {}",
+            expected,
+            std::any::type_name::<T>(),
+            bytes.len()
+        ),
+    )
+}
+
+impl FromBytes for String {
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        String::from_utf8(bytes.to_vec())
+            .map_err(|e| Error::new(ErrorKind::SerializationError, e.to_string()))
+    }
+}
+
+macro_rules! impl_from_bytes_for_integer {
+    ($($t:ty),*) => {
+        $(
+            impl FromBytes for $t {
+                fn from_bytes(bytes: &[u8]) -> Result<Self> {
+                    bytes
+                        .try_into()
+                        .map(<$t>::from_be_bytes)
+                        .map_err(|_| wrong_length::<$t>(size_of::<$t>(), bytes))
+                }
+
+                fn fixed_len() -> Option<usize> {
+                    Some(size_of::<$t>())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_bytes_for_integer!(u32, u64, i32, i64);
+
+impl FromBytes for Vec<u8> {
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl<const N: usize> FromBytes for [u8; N] {
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bytes.try_into().map_err(|_| wrong_length::<Self>(N, bytes))
+    }
+
+    fn fixed_len() -> Option<usize> {
+        Some(N)
+    }
+}
+
+// Tuple representations are concatenated without separator, so they can only be split
+// if at least one of their members has a fixed length.
+impl<K1, K2> FromBytes for (K1, K2)
+where
+    K1: FromBytes,
+    K2: FromBytes,
+{
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let split = split_tuple::<K1, K2>(bytes)?;
+        Ok((
+            K1::from_bytes(&bytes[..split])?,
+            K2::from_bytes(&bytes[split..])?,
+        ))
+    }
+
+    fn from_separated_bytes(bytes: &[u8], separator: u8) -> Result<Self> {
+        let separator_at = match (K1::fixed_len(), K2::fixed_len()) {
+            (_, Some(len)) if len < bytes.len() => Some(bytes.len() - len - 1),
+            (Some(len), None) if len < bytes.len() => Some(len),
+            _ => None,
+        };
+        let (end, start) = match separator_at {
+            Some(index) if bytes[index] == separator => (index, index + 1),
+            // stored without separator
+            _ => {
+                let split = split_tuple::<K1, K2>(bytes)?;
+                (split, split)
+            }
+        };
+        Ok((
+            K1::from_separated_bytes(&bytes[..end], separator)?,
+            K2::from_separated_bytes(&bytes[start..], separator)?,
+        ))
+    }
+
+    fn fixed_len() -> Option<usize> {
+        Some(K1::fixed_len()? + K2::fixed_len()?)
+    }
+}
+
+// Returns the length of the first member of a tuple representation stored without separator
+fn split_tuple<K1: FromBytes, K2: FromBytes>(bytes: &[u8]) -> Result<usize> {
+    let split = match (K1::fixed_len(), K2::fixed_len()) {
+            (_, Some(len)) if len <= bytes.len() => bytes.len() - len,
+            (Some(len), None) if len <= bytes.len() => len,
+            (None, None) => {
+                return Err(Error::new(
+                    ErrorKind::SerializationError,
+                    format!(
+                        "Cannot split the binary representation of a {} : none of its members has a fixed length",
+                        std::any::type_name::<(K1, K2)>()
+                    ),
+                ))
+            }
+            _ => {
+                return Err(wrong_length::<(K1, K2)>(
+                    K1::fixed_len().or(K2::fixed_len()).unwrap_or_default(),
+                    bytes,
+                ))
+            }
+        };
+    Ok(split)
+}
+
+/// Trait allowing keys to be converted to and from a string, for instance to be used in URLs.
+///
+/// Integers use their decimal representation, `String` is percent-encoded (every byte except ASCII letters, digits,
+/// `-`, `.`, `_` and `~` is written as `%XX`), and byte arrays and vectors use lowercase hexadecimal.
+/// Tuples (such as child keys) join the representations of their members with `:`, so that the child
+/// `(42, 3)` is represented as `42:3`, `((42, 3), 7)` as `42:3:7`, and `("a/b", 1)` as `a%2Fb:1`.
+///
+/// Representations are therefore safe to use as a single URL path segment.
+/// This trait is not meant to be implemented, but you can if you need to.
+pub trait KeyStr: Sized {
+    /// Returns the string representation of `self`
+    fn to_key_str(&self) -> String;
+
+    /// Builds a new value from its string representation, or returns an error if
+    /// `s` is not a valid representation of `Self`
+    fn from_key_str(s: &str) -> Result<Self>;
+}
+
+fn invalid_key_str<T>(s: &str) -> Error {
+    Error::new(
+        ErrorKind::SerializationError,
+        format!("Cannot parse {} from {:?}", std::any::type_name::<T>(), s),
+    )
+}
+
+impl KeyStr for String {
+    fn to_key_str(&self) -> String {
+        let mut encoded = String::with_capacity(self.len());
+        for byte in self.bytes() {
+            if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        encoded
+    }
+
+    fn from_key_str(s: &str) -> Result<Self> {
+        let mut decoded = Vec::with_capacity(s.len());
+        let mut bytes = s.bytes();
+        while let Some(byte) = bytes.next() {
+            if byte == b'%' {
+                let hex = [bytes.next(), bytes.next()];
+                let byte = match hex {
+                    [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                    _ => None,
+                };
+                decoded.push(byte.ok_or_else(|| invalid_key_str::<Self>(s))?);
+            } else {
+                decoded.push(byte);
+            }
+        }
+        String::from_utf8(decoded).map_err(|_| invalid_key_str::<Self>(s))
+    }
+}
+
+macro_rules! impl_key_str_for_integer {
+    ($($t:ty),*) => {
+        $(
+            impl KeyStr for $t {
+                fn to_key_str(&self) -> String {
+                    self.to_string()
+                }
+
+                fn from_key_str(s: &str) -> Result<Self> {
+                    s.parse().map_err(|_| invalid_key_str::<$t>(s))
+                }
+            }
+        )*
+    };
+}
+
+impl_key_str_for_integer!(u32, u64, i32, i64);
+
+impl KeyStr for Vec<u8> {
+    fn to_key_str(&self) -> String {
+        self.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn from_key_str(s: &str) -> Result<Self> {
+        if !s.len().is_multiple_of(2) || !s.is_ascii() {
+            return Err(invalid_key_str::<Self>(s));
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| invalid_key_str::<Self>(s)))
+            .collect()
+    }
+}
+
+impl<const N: usize> KeyStr for [u8; N] {
+    fn to_key_str(&self) -> String {
+        self.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn from_key_str(s: &str) -> Result<Self> {
+        if s.len() != 2 * N || !s.is_ascii() {
+            return Err(invalid_key_str::<Self>(s));
+        }
+        let mut bytes = [0; N];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16)
+                .map_err(|_| invalid_key_str::<Self>(s))?;
+        }
+        Ok(bytes)
+    }
+}
+
+impl<K1, K2> KeyStr for (K1, K2)
+where
+    K1: KeyStr,
+    K2: KeyStr,
+{
+    fn to_key_str(&self) -> String {
+        format!("{}:{}", self.0.to_key_str(), self.1.to_key_str())
+    }
+
+    fn from_key_str(s: &str) -> Result<Self> {
+        let (first, second) = s
+            .rsplit_once(':')
+            .ok_or_else(|| invalid_key_str::<Self>(s))?;
+        Ok((K1::from_key_str(first)?, K2::from_key_str(second)?))
     }
 }
@@ -76,3 +76,12 @@ impl From<serde_json::Error> for Error {
         Error::new(ErrorKind::SerializationError, source.to_string())
     }
 }
+
+impl From<sled::transaction::TransactionError<Error>> for Error {
+    fn from(source: sled::transaction::TransactionError<Error>) -> Self {
+        match source {
+            sled::transaction::TransactionError::Abort(error) => error,
+            sled::transaction::TransactionError::Storage(error) => error.into(),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! # Handle Module
+//! This module provides [`Reindeer`](struct.Reindeer.html), a central database handle
+//! bundling the `sled` database and the registration of entity types,
+//! and [`ReadOnlyDb`](struct.ReadOnlyDb.html), a handle that cannot modify the database.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use crate::entity::Entity;
+use crate::error::Result;
+use crate::storage::StorageFormat;
+use crate::{Error, ErrorKind};
+use sled::Db;
+
+/// A database handle that registers every entity type it is built with.
+///
+/// It offers methods forwarding to the [`Entity`](entity/trait.Entity.html) trait, and dereferences to
+/// the underlying `sled::Db`, so that it can be passed to any other method of the trait.
+///
+/// The database is flushed when the handle is dropped, so that every change is durable once the
+/// application closes normally. Changes made since the last flush are still lost if the process crashes :
+/// see [`OpenConfig::flush_every_ms`](struct.OpenConfig.html#method.flush_every_ms).
+///
+/// ### Example
+/// ```rust,ignore
+/// let db = Reindeer::builder("./my-db")
+///     .register::<User>()
+///     .register::<Post>()
+///     .open()?;
+/// db.save(&user)?;
+/// let user = db.get::<User>(&3)?;
+/// let posts = user.get_children::<Post>(&db)?;
+/// ```
+pub struct Reindeer {
+    db: Db,
+}
+
+/// Builder for a [`Reindeer`](struct.Reindeer.html) handle, created with [`Reindeer::builder`](struct.Reindeer.html#method.builder).
+pub struct ReindeerBuilder {
+    path: PathBuf,
+    registrations: Vec<fn(&Db) -> Result<()>>,
+}
+
+impl ReindeerBuilder {
+    /// Adds an entity type to register when the database is opened.
+    pub fn register<E: Entity>(mut self) -> ReindeerBuilder {
+        self.registrations.push(E::register);
+        self
+    }
+
+    /// Opens the database and registers every entity type.
+    pub fn open(self) -> Result<Reindeer> {
+        let db = sled::open(&self.path)?;
+        for register in &self.registrations {
+            register(&db)?;
+        }
+        Ok(Reindeer { db })
+    }
+}
+
+impl Reindeer {
+    /// Creates a builder for a database stored at `path`.
+    pub fn builder<P: AsRef<Path>>(path: P) -> ReindeerBuilder {
+        ReindeerBuilder {
+            path: path.as_ref().to_path_buf(),
+            registrations: Vec::new(),
+        }
+    }
+
+    /// Returns the underlying `sled` database.
+    pub fn db(&self) -> &Db {
+        &self.db
+    }
+
+    /// Saves an entity. See [`Entity::save`](entity/trait.Entity.html#method.save).
+    pub fn save<E: Entity>(&self, entity: &E) -> Result<()> {
+        entity.save(&self.db)
+    }
+
+    /// Gets an entity from its key. See [`Entity::get`](entity/trait.Entity.html#method.get).
+    pub fn get<E: Entity>(&self, key: &E::Key) -> Result<Option<E>> {
+        E::get(key, &self.db)
+    }
+
+    /// Gets every entity of a store. See [`Entity::get_all`](entity/trait.Entity.html#method.get_all).
+    pub fn get_all<E: Entity>(&self) -> Result<Vec<E>> {
+        E::get_all(&self.db)
+    }
+
+    /// Checks if an entity exists. See [`Entity::exists`](entity/trait.Entity.html#method.exists).
+    pub fn exists<E: Entity>(&self, key: &E::Key) -> Result<bool> {
+        E::exists(key, &self.db)
+    }
+
+    /// Removes an entity from its key. See [`Entity::remove`](entity/trait.Entity.html#method.remove).
+    pub fn remove<E: Entity>(&self, key: &E::Key) -> Result<()> {
+        E::remove(key, &self.db)
+    }
+}
+
+impl Drop for Reindeer {
+    fn drop(&mut self) {
+        // errors cannot be reported from `drop`, call `flush` explicitly to handle them
+        let _ = self.db.flush();
+    }
+}
+
+impl Deref for Reindeer {
+    type Target = Db;
+
+    fn deref(&self) -> &Db {
+        &self.db
+    }
+}
+
+/// Opens a `sled` database that can only be read through the returned [`ReadOnlyDb`](struct.ReadOnlyDb.html) handle.
+///
+/// Opening a path that does not exist results in a `NotFound` error, instead of creating an empty database.
+///
+/// ### Example
+/// ```rust,ignore
+/// let db = reindeer::open_read_only("./production-snapshot")?;
+/// let users = db.get_all::<User>()?;
+/// ```
+pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<ReadOnlyDb> {
+    if !path.as_ref().exists() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("No database found at {}", path.as_ref().display()),
+        ));
+    }
+    Ok(ReadOnlyDb {
+        db: sled::open(path)?,
+    })
+}
+
+/// A database handle that only allows reading entities.
+///
+/// `sled` has no read-only mode, so this handle does not give access to the underlying `sled::Db` :
+/// it only exposes reading methods, and its mutating methods fail with an `IOError` without writing anything.
+pub struct ReadOnlyDb {
+    db: Db,
+}
+
+impl ReadOnlyDb {
+    /// Gets an entity from its key. See [`Entity::get`](entity/trait.Entity.html#method.get).
+    pub fn get<E: Entity>(&self, key: &E::Key) -> Result<Option<E>> {
+        let key = E::encode_key(key);
+        E::get_tree(&self.db)?
+            .get(&key)?
+            .filter(|vec| E::has_own_tag(vec))
+            .map(|vec| E::from_ivec(&key, &vec, StorageFormat::of(&self.db)?))
+            .transpose()
+    }
+
+    /// Gets every entity of a store. See [`Entity::get_all`](entity/trait.Entity.html#method.get_all).
+    pub fn get_all<E: Entity>(&self) -> Result<Vec<E>> {
+        E::decode_entries(E::get_tree(&self.db)?.iter(), StorageFormat::of(&self.db)?)
+    }
+
+    /// Gets every entity of a store matching a condition. See [`Entity::get_with_filter`](entity/trait.Entity.html#method.get_with_filter).
+    pub fn get_with_filter<E: Entity, F: Fn(&E) -> bool>(&self, f: F) -> Result<Vec<E>> {
+        Ok(self.get_all::<E>()?.into_iter().filter(|e| f(e)).collect())
+    }
+
+    /// Returns the number of entities of a store. See [`Entity::get_count`](entity/trait.Entity.html#method.get_count).
+    pub fn get_count<E: Entity>(&self) -> Result<usize> {
+        E::get_count(&self.db)
+    }
+
+    /// Checks if an entity exists. See [`Entity::exists`](entity/trait.Entity.html#method.exists).
+    pub fn exists<E: Entity>(&self, key: &E::Key) -> Result<bool> {
+        E::exists(key, &self.db)
+    }
+
+    /// Gets the entities of a store related to `entity`. See [`Entity::get_related`](entity/trait.Entity.html#method.get_related).
+    pub fn get_related<E1: Entity, E2: Entity>(&self, entity: &E1) -> Result<Vec<E2>> {
+        entity.related_iter::<E2>(&self.db)?.collect()
+    }
+
+    /// Always fails with an `IOError`, since the database is read-only.
+    pub fn save<E: Entity>(&self, _entity: &E) -> Result<()> {
+        Err(ReadOnlyDb::read_only_error())
+    }
+
+    /// Always fails with an `IOError`, since the database is read-only.
+    pub fn remove<E: Entity>(&self, _key: &E::Key) -> Result<()> {
+        Err(ReadOnlyDb::read_only_error())
+    }
+
+    fn read_only_error() -> Error {
+        Error::new(
+            ErrorKind::IOError,
+            String::from("Cannot modify a read-only database"),
+        )
+    }
+}
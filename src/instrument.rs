@@ -0,0 +1,63 @@
+//! # Instrument Module
+//! Records the count and the duration of entity operations using the [`metrics`](https://docs.rs/metrics/latest/metrics/) crate.
+//! This module only exists with the `metrics` feature.
+
+use std::time::Instant;
+
+/// The metric names of an operation, built once at compile time.
+pub(crate) struct Operation {
+    total: &'static str,
+    duration: &'static str,
+}
+
+macro_rules! operations {
+    ($($constant:ident => $name:literal),* $(,)?) => {
+        $(
+            pub(crate) const $constant: Operation = Operation {
+                total: concat!("reindeer_", $name, "_total"),
+                duration: concat!("reindeer_", $name, "_duration_seconds"),
+            };
+        )*
+    };
+}
+
+operations!(
+    GET => "get",
+    GET_ALL => "get_all",
+    GET_EACH => "get_each",
+    GET_IN_RANGE => "get_in_range",
+    GET_WITH_PREFIX => "get_with_prefix",
+    GET_WITH_FILTER => "get_with_filter",
+    SAVE => "save",
+    UPDATE => "update",
+    REMOVE => "remove",
+    CREATE_RELATION => "create_relation",
+    REMOVE_RELATION => "remove_relation",
+    GET_RELATED => "get_related",
+);
+
+/// Counts an operation on a store as soon as it is created, and records its duration when dropped,
+/// as `reindeer_{operation}_total{store}` and `reindeer_{operation}_duration_seconds{store}`.
+pub(crate) struct OperationTimer {
+    operation: Operation,
+    store: &'static str,
+    start: Instant,
+}
+
+impl OperationTimer {
+    pub(crate) fn start(operation: Operation, store: &'static str) -> OperationTimer {
+        metrics::counter!(operation.total, "store" => store).increment(1);
+        OperationTimer {
+            operation,
+            store,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for OperationTimer {
+    fn drop(&mut self) {
+        metrics::histogram!(self.operation.duration, "store" => self.store)
+            .record(self.start.elapsed().as_secs_f64());
+    }
+}
@@ -24,7 +24,7 @@
 //!
 //! Three types of relationships can be achieved :
 //!  - Sibling relationship : two or more `Entity` structs that share the same key type for which each entity has 0 or 1 counterpart
-//! in their sibling Entity stores (one-to-zero-or-one)
+//!    in their sibling Entity stores (one-to-zero-or-one)
 //!  - Parent-Child relationship : An entity has a collection of matching entities in another Entity Store (one-to-many)
 //!  - Free relationship : Any two entities can be linked together as a two-way link. (many-to-many)
 //!
@@ -33,13 +33,49 @@
 //!  - `DeletionBehaviour::Error` : Trying to remove this entity as related entities still exist will cause an error and abort
 //!  - `DeletionBehaviour::BreakLink` : Remove this entity and the links with its related entites, leaving the other ones untouched
 
+mod batch;
+#[cfg(feature = "changelog")]
+mod changelog;
+mod config;
 mod entity;
 mod error;
+mod handle;
+#[cfg(feature = "metrics")]
+mod instrument;
+#[cfg(feature = "lazy-migration")]
+mod migration;
 mod relation;
+mod storage;
+#[cfg(feature = "changelog")]
+pub use changelog::{changes_since, prune_changelog, ChangeOp, ChangeRecord};
+pub use config::{OpenConfig, SledMode};
 pub use entity::AutoIncrementEntity;
+pub use entity::ChildEntity;
+#[cfg(feature = "content-addressed")]
+pub use entity::ContentAddressedEntity;
 pub use entity::Entity;
+pub use entity::Mergeable;
+pub use entity::Timestamped;
+pub use entity::{
+    prune_orphans, release_store_name, set_range_scan_ratio, set_registration_check,
+    RegistrationCheck,
+};
+pub use entity::{AsBytes, FromBytes, KeyStr};
+pub use entity::{
+    DanglingRelations, FamilyRelation, ImportStrategy, Page, ReadErrorPolicy, ReadErrors,
+    RegistrationDiff, RemoveReport, SaveReceipt, TreeNode, UpsertStats,
+};
+pub use handle::{open_read_only, ReadOnlyDb, Reindeer, ReindeerBuilder};
 pub use relation::DeletionBehaviour;
+pub use relation::Relation;
+pub use relation::RelationBuilder;
+pub use relation::RelationMap;
+pub use relation::{remove_relations_by_name, rename_relation_name_global, set_max_cascade_depth};
 pub use serde_derive::{Deserialize, Serialize};
+pub use storage::{
+    backup, dump_store_json, health, open_json_backed, restore, total_size, HealthReport,
+    StorageFormat,
+};
 
 pub use error::{Error, ErrorKind, Result};
 /// Opens a `sled` database to store Entities. The resulting Db object can be copied accross threads. This is a re-export of `sled::open`.
@@ -0,0 +1,52 @@
+//! # Migration Module
+//! This module keeps track of the version of every saved entity, so that entities saved
+//! with a previous version of their struct can be migrated lazily when they are read.
+//! It is only available with the `lazy-migration` feature, since it turns some reads into writes.
+
+use crate::batch::TreeBatches;
+use crate::config::internal_tree_name;
+use crate::error::Result;
+use sled::Db;
+
+fn tree_name(store: &str) -> String {
+    internal_tree_name(&format!("ver_{}", store))
+}
+
+/// Returns the version an entity was saved with, entities saved without a version being version 0.
+pub(crate) fn stored_version(store: &str, key: &[u8], db: &Db) -> Result<u32> {
+    match db.open_tree(tree_name(store))?.get(key)? {
+        Some(version) => Ok(u32::from_be_bytes(
+            version.as_ref().try_into().unwrap_or_default(),
+        )),
+        None => Ok(0),
+    }
+}
+
+pub(crate) fn record_version(store: &str, key: &[u8], version: u32, db: &Db) -> Result<()> {
+    let tree = db.open_tree(tree_name(store))?;
+    if version == 0 {
+        tree.remove(key)?;
+    } else {
+        tree.insert(key, &version.to_be_bytes())?;
+    }
+    Ok(())
+}
+
+/// Same as [`record_version`], as part of a batch of writes.
+pub(crate) fn record_version_in(store: &str, key: &[u8], version: u32, batches: &mut TreeBatches) {
+    let batch = batches.tree(&tree_name(store));
+    if version == 0 {
+        batch.remove(key);
+    } else {
+        batch.insert(key, &version.to_be_bytes());
+    }
+}
+
+pub(crate) fn forget_version_in(store: &str, key: &[u8], batches: &mut TreeBatches) {
+    batches.tree(&tree_name(store)).remove(key);
+}
+
+pub(crate) fn forget_version(store: &str, key: &[u8], db: &Db) -> Result<()> {
+    db.open_tree(tree_name(store))?.remove(key)?;
+    Ok(())
+}
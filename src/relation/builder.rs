@@ -0,0 +1,66 @@
+use super::{DeletionBehaviour, Relation};
+use crate::entity::Entity;
+use crate::error::Result;
+use sled::Db;
+
+/// Builder for a free relation between two entities, created with [`Entity::relate_to`](../entity/trait.Entity.html#method.relate_to).
+///
+/// Both deletion behaviours default to `DeletionBehaviour::BreakLink`, and the relation is unnamed by default.
+///
+/// ### Example
+/// ```rust,ignore
+/// user.relate_to(&document)
+///     .on_self_delete(DeletionBehaviour::Cascade)
+///     .on_other_delete(DeletionBehaviour::Error)
+///     .named("owner")
+///     .commit(&db)?;
+/// ```
+pub struct RelationBuilder<'a, E1: Entity, E2: Entity> {
+    entity: &'a E1,
+    other: &'a E2,
+    self_to_other: DeletionBehaviour,
+    other_to_self: DeletionBehaviour,
+    name: Option<&'a str>,
+}
+
+impl<'a, E1: Entity, E2: Entity> RelationBuilder<'a, E1, E2> {
+    pub(crate) fn new(entity: &'a E1, other: &'a E2) -> RelationBuilder<'a, E1, E2> {
+        RelationBuilder {
+            entity,
+            other,
+            self_to_other: DeletionBehaviour::BreakLink,
+            other_to_self: DeletionBehaviour::BreakLink,
+            name: None,
+        }
+    }
+
+    /// Defines what happens to the other entity when this entity gets removed from the database.
+    pub fn on_self_delete(mut self, behaviour: DeletionBehaviour) -> RelationBuilder<'a, E1, E2> {
+        self.self_to_other = behaviour;
+        self
+    }
+
+    /// Defines what happens to this entity when the other entity gets removed from the database.
+    pub fn on_other_delete(mut self, behaviour: DeletionBehaviour) -> RelationBuilder<'a, E1, E2> {
+        self.other_to_self = behaviour;
+        self
+    }
+
+    /// Names the relation.
+    pub fn named(mut self, name: &'a str) -> RelationBuilder<'a, E1, E2> {
+        self.name = Some(name);
+        self
+    }
+
+    /// Creates the relation in the database.
+    pub fn commit(self, db: &Db) -> Result<()> {
+        Relation::create(
+            self.entity,
+            self.other,
+            self.self_to_other,
+            self.other_to_self,
+            self.name,
+            db,
+        )
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{DeletionBehaviour, FamilyDescriptor, Relation};
+use crate::config::internal_tree_name;
+use crate::entity::Entity;
+use crate::error::Result;
+use crate::{Error, ErrorKind};
+use sled::{Db, Tree};
+
+static MAX_CASCADE_DEPTH: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Sets the maximum depth a cascade deletion can reach, for every database of the process.
+///
+/// Removing an entity whose `Cascade` relations (free, sibling or child) lead further than `depth`
+/// hops away results in an `IntegrityError`, and nothing is removed. This is a safety valve
+/// against misconfigured relations removing an unexpectedly large part of the database.
+///
+/// Cascade deletions are unlimited by default. Use `usize::MAX` to make them unlimited again.
+///
+/// ### Example
+/// ```rust,ignore
+/// reindeer::set_max_cascade_depth(3);
+/// ```
+pub fn set_max_cascade_depth(depth: usize) {
+    MAX_CASCADE_DEPTH.store(depth, Ordering::Relaxed);
+}
+
+fn max_cascade_depth() -> usize {
+    MAX_CASCADE_DEPTH.load(Ordering::Relaxed)
+}
+
+struct Blocker {
+    kind: &'static str,
+    tree_name: String,
+    key: Vec<u8>,
+}
+
+impl Blocker {
+    fn new(kind: &'static str, tree_name: &str, key: &[u8]) -> Blocker {
+        Blocker {
+            kind,
+            tree_name: String::from(tree_name),
+            key: key.to_vec(),
+        }
+    }
+}
+
+/// Traversal of the entities removed along with a given one.
+///
+/// Entities are discovered breadth-first, so that the depth limit applies to the shortest cascade
+/// leading to each of them, and the plan is then ordered by a depth-first walk of the cascades found.
+///
+/// Opened trees and family descriptors are kept for the whole traversal, since a large cascade
+/// reads the same few trees for every entity it reaches.
+struct DeletionPlanner<'a> {
+    overrides: &'a [(&'a str, DeletionBehaviour)],
+    max_depth: usize,
+    db: &'a Db,
+    // Index of every discovered entity in `nodes`
+    visited: HashMap<(String, Vec<u8>), usize>,
+    nodes: Vec<(String, Vec<u8>)>,
+    // Entities reached through `Cascade` relations from each entity of `nodes`
+    cascades: Vec<Vec<usize>>,
+    blockers: Vec<Blocker>,
+    trees: HashMap<String, Tree>,
+    families: HashMap<String, FamilyDescriptor>,
+}
+
+impl<'a> DeletionPlanner<'a> {
+    fn new(
+        overrides: &'a [(&'a str, DeletionBehaviour)],
+        max_depth: usize,
+        db: &'a Db,
+    ) -> DeletionPlanner<'a> {
+        DeletionPlanner {
+            overrides,
+            max_depth,
+            db,
+            visited: HashMap::new(),
+            nodes: Vec::new(),
+            cascades: Vec::new(),
+            blockers: Vec::new(),
+            trees: HashMap::new(),
+            families: HashMap::new(),
+        }
+    }
+
+    fn blockers(mut self, tree_name: &str, key: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+        self.collect(tree_name, key)?;
+        let mut blockers: Vec<(String, Vec<u8>)> = Vec::new();
+        for blocker in self.blockers {
+            let blocker = (blocker.tree_name, blocker.key);
+            if !self.visited.contains_key(&blocker) && !blockers.contains(&blocker) {
+                blockers.push(blocker);
+            }
+        }
+        Ok(blockers)
+    }
+
+    fn plan(mut self, tree_name: &str, key: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("deletion_plan", tree_name, ?key).entered();
+        self.collect(tree_name, key)?;
+        if let Some(blocker) = self.blockers.iter().find(|b| {
+            !self
+                .visited
+                .contains_key(&(b.tree_name.clone(), b.key.clone()))
+        }) {
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Constrained {} entity exists in {}",
+                    blocker.kind, blocker.tree_name
+                ),
+            ));
+        }
+        Ok(self.ordered())
+    }
+
+    fn collect(&mut self, tree_name: &str, key: &[u8]) -> Result<()> {
+        self.discover(String::from(tree_name), key.to_vec());
+        let mut queue = VecDeque::from([(0, 0)]);
+        while let Some((index, depth)) = queue.pop_front() {
+            let (tree_name, key) = self.nodes[index].clone();
+            if depth > self.max_depth {
+                return Err(Error::new(
+                    ErrorKind::IntegrityError,
+                    format!(
+                        "Cascade deletion reaches {} beyond the maximum depth of {}",
+                        tree_name, self.max_depth
+                    ),
+                ));
+            }
+            for (other_tree_name, other_key) in self.expand(&tree_name, &key)? {
+                let (other, discovered) = self.discover(other_tree_name, other_key);
+                if discovered {
+                    queue.push_back((other, depth + 1));
+                }
+                self.cascades[index].push(other);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the index of an entity, and whether it was discovered by this call.
+    fn discover(&mut self, tree_name: String, key: Vec<u8>) -> (usize, bool) {
+        let node = (tree_name, key);
+        if let Some(index) = self.visited.get(&node) {
+            return (*index, false);
+        }
+        let index = self.nodes.len();
+        self.visited.insert(node.clone(), index);
+        self.nodes.push(node);
+        self.cascades.push(Vec::new());
+        (index, true)
+    }
+
+    /// Returns the discovered entities in topological order, the first one coming last.
+    fn ordered(mut self) -> Vec<(String, Vec<u8>)> {
+        let mut placed = vec![false; self.nodes.len()];
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![(0, 0)];
+        placed[0] = true;
+        while let Some((index, next)) = stack.pop() {
+            match self.cascades[index].get(next) {
+                Some(&other) => {
+                    stack.push((index, next + 1));
+                    if !placed[other] {
+                        placed[other] = true;
+                        stack.push((other, 0));
+                    }
+                }
+                None => order.push(index),
+            }
+        }
+        order
+            .into_iter()
+            .map(|index| std::mem::take(&mut self.nodes[index]))
+            .collect()
+    }
+
+    /// Records the blockers of an entity, and returns the entities its `Cascade` relations reach.
+    fn expand(&mut self, tree_name: &str, key: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut cascades = Vec::new();
+        let descriptor =
+            Relation::get_descriptor_from_tree(&self.tree(&Relation::tree_name(tree_name))?, key)?;
+        for (other_tree_name, entities) in &descriptor.related_entities {
+            for rd in entities {
+                let behaviour = self.resolve_behaviour(other_tree_name, rd.deletion_behaviour);
+                #[cfg(feature = "tracing")]
+                trace_decision("related", tree_name, other_tree_name, &rd.key, behaviour);
+                match behaviour {
+                    DeletionBehaviour::Error => {
+                        self.blockers
+                            .push(Blocker::new("related", other_tree_name, &rd.key))
+                    }
+                    DeletionBehaviour::Cascade => {
+                        cascades.push((other_tree_name.clone(), rd.key.clone()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let family_descriptor = self.family(tree_name)?;
+        let instance_siblings = Relation::get_instance_siblings_from_tree(
+            &self.tree(&Relation::instance_siblings_tree_name(tree_name))?,
+            key,
+        )?;
+        for (other_tree_name, behaviour) in
+            &Relation::sibling_trees(&family_descriptor, instance_siblings)
+        {
+            let behaviour = self.resolve_behaviour(other_tree_name, *behaviour);
+            if !self.tree(other_tree_name)?.contains_key(key)? {
+                continue;
+            }
+            #[cfg(feature = "tracing")]
+            trace_decision("sibling", tree_name, other_tree_name, key, behaviour);
+            if behaviour == DeletionBehaviour::BreakLink {
+                continue;
+            }
+            if behaviour == DeletionBehaviour::Error {
+                self.blockers
+                    .push(Blocker::new("sibling", other_tree_name, key));
+            } else {
+                cascades.push((other_tree_name.clone(), key.to_vec()));
+            }
+        }
+        for (other_tree_name, behaviour) in &family_descriptor.child_trees {
+            let behaviour = self.resolve_behaviour(other_tree_name, *behaviour);
+            if behaviour == DeletionBehaviour::BreakLink {
+                continue;
+            }
+            let prefix = Relation::child_prefix_from_tree(
+                &self.tree(&internal_tree_name("child_separators"))?,
+                other_tree_name,
+                key,
+            )?;
+            let keys = self
+                .tree(other_tree_name)?
+                .scan_prefix(&prefix)
+                .keys()
+                .filter(|key| match key {
+                    Ok(key) => Relation::is_child_key(&prefix, key),
+                    Err(_) => true,
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            for child_key in keys {
+                #[cfg(feature = "tracing")]
+                trace_decision("child", tree_name, other_tree_name, &child_key, behaviour);
+                if behaviour == DeletionBehaviour::Error {
+                    self.blockers
+                        .push(Blocker::new("child", other_tree_name, &child_key));
+                } else {
+                    cascades.push((other_tree_name.clone(), child_key.to_vec()));
+                }
+            }
+        }
+        Ok(cascades)
+    }
+
+    fn tree(&mut self, tree_name: &str) -> Result<Tree> {
+        if let Some(tree) = self.trees.get(tree_name) {
+            return Ok(tree.clone());
+        }
+        let tree = self.db.open_tree(tree_name)?;
+        self.trees.insert(String::from(tree_name), tree.clone());
+        Ok(tree)
+    }
+
+    fn family(&mut self, tree_name: &str) -> Result<FamilyDescriptor> {
+        if let Some(family_descriptor) = self.families.get(tree_name) {
+            return Ok(family_descriptor.clone());
+        }
+        let family_descriptor = match FamilyDescriptor::get(&String::from(tree_name), self.db)? {
+            Some(family_descriptor) => family_descriptor,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::UnregisteredEntity,
+                    format!("Trying to use unregistered entity {}", tree_name),
+                ))
+            }
+        };
+        self.families
+            .insert(String::from(tree_name), family_descriptor.clone());
+        Ok(family_descriptor)
+    }
+
+    fn resolve_behaviour(&self, tree_name: &str, declared: DeletionBehaviour) -> DeletionBehaviour {
+        self.overrides
+            .iter()
+            .find(|(name, _)| *name == tree_name)
+            .map(|(_, behaviour)| *behaviour)
+            .unwrap_or(declared)
+    }
+}
+
+/// Emits an event describing how a relation is handled while planning a deletion.
+#[cfg(feature = "tracing")]
+fn trace_decision(
+    kind: &str,
+    from: &str,
+    tree_name: &str,
+    key: &[u8],
+    behaviour: DeletionBehaviour,
+) {
+    let decision = match behaviour {
+        DeletionBehaviour::Cascade => "recurse",
+        DeletionBehaviour::Error => "block",
+        DeletionBehaviour::BreakLink => "skip",
+    };
+    tracing::debug!(
+        kind,
+        from,
+        tree_name,
+        ?key,
+        ?behaviour,
+        decision,
+        "relation considered"
+    );
+}
+
+impl Relation {
+    /// Computes every entity that has to be removed along with the entity `key` of store `tree_name`,
+    /// following `Cascade` relations, and checks that no `Error` relation forbids the deletion.
+    ///
+    /// `Error` relations are only checked once the whole set of removed entities is known, so
+    /// that a constrained entity that is removed by the same operation does not block it,
+    /// regardless of the order in which relations are traversed.
+    ///
+    /// The result is in topological order : entities always come before the entities they
+    /// depend on, the entity being removed being the last one.
+    pub fn deletion_plan(
+        tree_name: &str,
+        key: &[u8],
+        overrides: &[(&str, DeletionBehaviour)],
+        db: &Db,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        Self::deletion_plan_with_limit(tree_name, key, overrides, max_cascade_depth(), db)
+    }
+
+    /// Same as [`deletion_plan`](#method.deletion_plan), failing with an `IntegrityError` if
+    /// `Cascade` relations lead further than `max_depth` hops away from the removed entity.
+    pub fn deletion_plan_with_limit(
+        tree_name: &str,
+        key: &[u8],
+        overrides: &[(&str, DeletionBehaviour)],
+        max_depth: usize,
+        db: &Db,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        DeletionPlanner::new(overrides, max_depth, db).plan(tree_name, key)
+    }
+
+    /// Returns the store name and key of every entity whose `Error` relation forbids removing
+    /// the entity `key` of store `tree_name`, following `Cascade` relations.
+    /// Entities that are removed by the same operation are not considered blockers.
+    pub fn removal_blockers(
+        tree_name: &str,
+        key: &[u8],
+        db: &Db,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        DeletionPlanner::new(&[], max_cascade_depth(), db).blockers(tree_name, key)
+    }
+}
@@ -1,15 +1,17 @@
-use hashers::fx_hash::FxHasher;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::hash::BuildHasherDefault;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
 
-use crate::entity::AsBytes;
+use crate::config::internal_tree_name;
+use crate::error::Result;
 use crate::Entity;
 
 use super::DeletionBehaviour;
 
+/// Related entities, per store name. Both the stores and the entities of each store are kept
+/// ordered (by store name and key, respectively) so that traversals are deterministic.
 #[doc(hidden)]
-pub type RelationMap = HashMap<String, Vec<RelationDescriptor>, BuildHasherDefault<FxHasher>>;
+pub type RelationMap = BTreeMap<String, Vec<RelationDescriptor>>;
 
 #[doc(hidden)]
 #[derive(Serialize, Deserialize, Default)]
@@ -23,6 +25,7 @@ pub struct RelationDescriptor {
     pub key: Vec<u8>,
     pub deletion_behaviour: DeletionBehaviour,
     pub name: Option<String>,
+    pub order: Option<i64>,
 }
 
 impl RelationDescriptor {
@@ -30,17 +33,42 @@ impl RelationDescriptor {
         key: &[u8],
         deletion_behaviour: DeletionBehaviour,
         name: Option<&str>,
+        order: Option<i64>,
     ) -> RelationDescriptor {
         RelationDescriptor {
             key: key.to_owned(),
             deletion_behaviour,
             name: name.map(|s| s.to_owned()),
+            order,
         }
     }
+
+    /// Checks if both descriptors describe the same relation, regardless of its order.
+    fn same_relation(&self, other: &RelationDescriptor) -> bool {
+        self.key == other.key
+            && self.deletion_behaviour == other.deletion_behaviour
+            && self.name == other.name
+    }
+}
+
+/// Descriptors written before relations could be ordered start with their number of related stores,
+/// which can never reach `u64::MAX` : current descriptors start with this marker instead.
+const ORDERED_FORMAT_MARKER: u64 = u64::MAX;
+
+#[derive(Deserialize)]
+struct LegacyEntityRelations {
+    related_entities: BTreeMap<String, Vec<LegacyRelationDescriptor>>,
+}
+
+#[derive(Deserialize)]
+struct LegacyRelationDescriptor {
+    key: Vec<u8>,
+    deletion_behaviour: DeletionBehaviour,
+    name: Option<String>,
 }
 
 #[doc(hidden)]
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default, Clone)]
 pub struct FamilyDescriptor {
     pub tree_name: String,
     pub sibling_trees: Vec<(String, DeletionBehaviour)>,
@@ -49,14 +77,63 @@ pub struct FamilyDescriptor {
 
 #[doc(hidden)]
 impl EntityRelations {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(&(ORDERED_FORMAT_MARKER, self)).unwrap()
+    }
+
+    /// Reads a descriptor, whether it was written with or without relation orders.
+    pub fn from_bytes(bytes: &[u8]) -> Result<EntityRelations> {
+        if bytes.starts_with(&ORDERED_FORMAT_MARKER.to_le_bytes()) {
+            let (_, relations) = bincode::deserialize::<(u64, EntityRelations)>(bytes)?;
+            return Ok(relations);
+        }
+        let legacy = bincode::deserialize::<LegacyEntityRelations>(bytes)?;
+        Ok(EntityRelations {
+            related_entities: legacy
+                .related_entities
+                .into_iter()
+                .map(|(tree_name, entities)| {
+                    let entities = entities
+                        .into_iter()
+                        .map(|rd| RelationDescriptor {
+                            key: rd.key,
+                            deletion_behaviour: rd.deletion_behaviour,
+                            name: rd.name,
+                            order: None,
+                        })
+                        .collect();
+                    (tree_name, entities)
+                })
+                .collect(),
+        })
+    }
+
+    /// Removes the relations to store `tree_name` and returns them sorted by order.
+    /// Ordered relations come first, and relations with the same order (or without one) keep their key order.
+    pub fn take_ordered(&mut self, tree_name: &str) -> Vec<RelationDescriptor> {
+        let mut entities = self.related_entities.remove(tree_name).unwrap_or_default();
+        entities.sort_by_key(|rd| (rd.order.is_none(), rd.order));
+        entities
+    }
+
     pub fn add_related<E: Entity>(
         &mut self,
         e: &E,
         behaviour: DeletionBehaviour,
         name: Option<&str>,
     ) {
-        let key = e.get_key().as_bytes();
-        self.add_related_by_key(E::store_name(), &key, behaviour, name);
+        self.add_ordered(e, behaviour, name, None);
+    }
+
+    pub fn add_ordered<E: Entity>(
+        &mut self,
+        e: &E,
+        behaviour: DeletionBehaviour,
+        name: Option<&str>,
+        order: Option<i64>,
+    ) {
+        let key = E::encode_key(e.get_key());
+        self.add_ordered_by_key(E::store_name(), &key, behaviour, name, order);
     }
 
     pub fn add_related_by_key(
@@ -66,19 +143,54 @@ impl EntityRelations {
         behaviour: DeletionBehaviour,
         name: Option<&str>,
     ) {
+        self.add_ordered_by_key(tree_name, key, behaviour, name, None)
+    }
+
+    /// Adds a relation to the entity `key` of store `tree_name`. If the relation already exists,
+    /// only its order is changed, and only when `order` is provided.
+    pub fn add_ordered_by_key(
+        &mut self,
+        tree_name: &str,
+        key: &[u8],
+        behaviour: DeletionBehaviour,
+        name: Option<&str>,
+        order: Option<i64>,
+    ) {
+        let relation_descriptor = RelationDescriptor::new(key, behaviour, name, order);
         if let Some(v) = self.related_entities.get_mut(tree_name) {
-            let relation_descriptor = RelationDescriptor::new(key, behaviour, name);
-            if !v.contains(&relation_descriptor) {
-                v.push(relation_descriptor);
+            match v
+                .iter_mut()
+                .find(|rd| rd.same_relation(&relation_descriptor))
+            {
+                Some(existing) => {
+                    if order.is_some() {
+                        existing.order = order;
+                    }
+                }
+                None => {
+                    let index = v.partition_point(|rd| rd.key.as_slice() <= key);
+                    v.insert(index, relation_descriptor);
+                }
             }
         } else {
-            self.related_entities.insert(
-                String::from(tree_name),
-                vec![RelationDescriptor::new(key, behaviour, name)],
-            );
+            self.related_entities
+                .insert(String::from(tree_name), vec![relation_descriptor]);
         }
     }
 
+    /// Sets the order of every relation to the entity `e` of store `tree`.
+    /// Returns `false` if there is no such relation.
+    pub fn reorder_related(&mut self, tree: &str, e: &[u8], order: i64) -> bool {
+        let mut found = false;
+        if let Some(v) = self.related_entities.get_mut(tree) {
+            for rd in v.iter_mut().filter(|rd| rd.key == e) {
+                rd.order = Some(order);
+                found = true;
+            }
+        }
+        found
+    }
+
     pub fn remove_related_by_key<E: Entity>(&mut self, e: &[u8]) {
         self.remove_related_by_key_and_tree_name(E::store_name(), e)
     }
@@ -89,7 +201,7 @@ impl EntityRelations {
 
     pub fn remove_related_by_key_and_tree_name(&mut self, tree: &str, e: &[u8]) {
         if let Some(v) = self.related_entities.get_mut(tree) {
-            v.retain(|rd| rd.key.to_ascii_lowercase() != e.to_ascii_lowercase());
+            v.retain(|rd| !rd.key.eq_ignore_ascii_case(e));
         }
     }
 
@@ -98,17 +210,84 @@ impl EntityRelations {
         tree: &str,
         e: &[u8],
         name: &str,
+    ) {
+        self.remove_related_by_key_and_tree_name_with_optional_name(tree, e, Some(name))
+    }
+
+    pub fn remove_related_by_key_and_tree_name_with_optional_name(
+        &mut self,
+        tree: &str,
+        e: &[u8],
+        name: Option<&str>,
     ) {
         if let Some(v) = self.related_entities.get_mut(tree) {
-            v.retain(|rd| {
-                rd.key.to_ascii_lowercase() != e.to_ascii_lowercase()
-                    && if let Some(r_name) = &rd.name {
-                        name == r_name
-                    } else {
-                        false
-                    }
+            v.retain(|rd| rd.key != e || rd.name.as_deref() != name);
+        }
+    }
+
+    /// Changes the deletion behaviour and the name of every relation to the entity `e` of store `tree`.
+    /// Returns `false` if there is no such relation.
+    pub fn update_related_by_key_and_tree_name(
+        &mut self,
+        tree: &str,
+        e: &[u8],
+        behaviour: DeletionBehaviour,
+        name: Option<&str>,
+    ) -> bool {
+        match self.related_entities.get_mut(tree) {
+            Some(v) if v.iter().any(|rd| rd.key == e) => {
+                let order = v.iter().find(|rd| rd.key == e).and_then(|rd| rd.order);
+                v.retain(|rd| rd.key != e);
+                let index = v.partition_point(|rd| rd.key.as_slice() <= e);
+                v.insert(index, RelationDescriptor::new(e, behaviour, name, order));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Renames the relations named `old` to entities matching `filter` (given their store name and key),
+    /// and returns the number of renamed relations.
+    pub fn rename_related<F: FnMut(&str, &[u8]) -> bool>(
+        &mut self,
+        mut filter: F,
+        old: &str,
+        new: &str,
+    ) -> usize {
+        let mut count = 0;
+        for (tree_name, entities) in self.related_entities.iter_mut() {
+            for rd in entities.iter_mut() {
+                if rd.name.as_deref() == Some(old) && filter(tree_name, &rd.key) {
+                    rd.name = Some(String::from(new));
+                    count += 1;
+                }
+            }
+            let mut unique: Vec<RelationDescriptor> = Vec::with_capacity(entities.len());
+            for rd in entities.drain(..) {
+                if !unique.iter().any(|other| other.same_relation(&rd)) {
+                    unique.push(rd);
+                }
+            }
+            *entities = unique;
+        }
+        count
+    }
+
+    /// Removes every relation named `name`, and returns the store name and key of the entities
+    /// they pointed to.
+    pub fn remove_named(&mut self, name: &str) -> Vec<(String, Vec<u8>)> {
+        let mut removed = Vec::new();
+        for (tree_name, entities) in self.related_entities.iter_mut() {
+            entities.retain(|rd| {
+                if rd.name.as_deref() == Some(name) {
+                    removed.push((tree_name.clone(), rd.key.clone()));
+                    false
+                } else {
+                    true
+                }
             });
         }
+        removed
     }
 
     pub fn replace_id(&mut self, tree: &str, old_id: &[u8], new_id: &[u8]) {
@@ -131,7 +310,8 @@ impl Entity for FamilyDescriptor {
     type Key = String;
 
     fn store_name() -> &'static str {
-        "__$family_rel"
+        static FAMILY_TREE: OnceLock<String> = OnceLock::new();
+        FAMILY_TREE.get_or_init(|| internal_tree_name("family_rel"))
     }
 
     fn get_key(&self) -> &Self::Key {
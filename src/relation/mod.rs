@@ -1,13 +1,26 @@
+mod builder;
+mod deletion;
 mod descriptor;
-use crate::entity::{AsBytes, Entity};
+use crate::batch::TreeBatches;
+use crate::config::internal_tree_name;
+use crate::entity::{Entity, FromBytes};
 use crate::error::Result;
+#[cfg(feature = "metrics")]
+use crate::instrument::{self, OperationTimer};
 use crate::{Error, ErrorKind};
 use serde_derive::{Deserialize, Serialize};
-use sled::Db;
+use sled::{Db, IVec, Tree};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 
+pub use self::builder::RelationBuilder;
+pub use self::deletion::set_max_cascade_depth;
 pub use self::descriptor::FamilyDescriptor;
 pub use self::descriptor::{EntityRelations, RelationMap};
 
+/// Low-level relation operations, working on store names and binary keys.
+///
+/// Most of them are available in a more convenient form as methods of the
+/// [`Entity`](../entity/trait.Entity.html) trait.
 pub struct Relation;
 
 impl Relation {
@@ -19,12 +32,53 @@ impl Relation {
         name: Option<&str>,
         db: &Db,
     ) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::CREATE_RELATION, E1::store_name());
         Relation::create_link(e1, e2, e1_to_e2, name, db)?;
         Relation::create_link(e2, e1, e2_to_e1, name, db)?;
         Ok(())
     }
 
+    /// Same as [`create`](#method.create), giving the relation from `e1` to `e2` an order
+    /// used to sort the entities related to `e1`.
+    pub fn create_ordered<E1: Entity, E2: Entity>(
+        e1: &E1,
+        e2: &E2,
+        e1_to_e2: DeletionBehaviour,
+        e2_to_e1: DeletionBehaviour,
+        name: Option<&str>,
+        order: i64,
+        db: &Db,
+    ) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::CREATE_RELATION, E1::store_name());
+        let mut e1_descriptor = Self::get_descriptor(e1, db)?;
+        e1_descriptor.add_ordered(e2, e1_to_e2, name, Some(order));
+        Self::save_descriptor(e1, &e1_descriptor, db)?;
+        Relation::create_link(e2, e1, e2_to_e1, name, db)?;
+        Ok(())
+    }
+
+    /// Changes the order of every relation from `e1` to `e2`.
+    /// Results in a `NotFound` error if they are not related.
+    pub fn reorder<E1: Entity, E2: Entity>(e1: &E1, e2: &E2, order: i64, db: &Db) -> Result<()> {
+        let mut e1_descriptor = Self::get_descriptor(e1, db)?;
+        if !e1_descriptor.reorder_related(E2::store_name(), &E2::encode_key(e2.get_key()), order) {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No relation between entities of {} and {}",
+                    E1::store_name(),
+                    E2::store_name()
+                ),
+            ));
+        }
+        Self::save_descriptor(e1, &e1_descriptor, db)
+    }
+
     pub fn remove<E1: Entity, E2: Entity>(e1: &E1, e2: &E2, db: &Db) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::REMOVE_RELATION, E1::store_name());
         Relation::remove_link(e1, e2, db)?;
         Relation::remove_link(e2, e1, db)?;
         Ok(())
@@ -52,21 +106,29 @@ impl Relation {
             return Ok(());
         }
         let family_descriptor = family_descriptor.unwrap();
-        for (other_tree, _) in family_descriptor.child_trees {
-            let tree = db.open_tree(&other_tree)?;
-            for (key, value) in tree.scan_prefix(old_id).flatten() {
+        for (other_tree, _) in &family_descriptor.child_trees {
+            let tree = db.open_tree(other_tree)?;
+            let prefix = Relation::child_prefix(other_tree, old_id, db)?;
+            for (key, value) in tree.scan_prefix(&prefix).flatten() {
+                if !Relation::is_child_key(&prefix, &key) {
+                    continue;
+                }
                 let new_key = [new_id, &key[old_id.len()..]].concat();
                 tree.insert(&new_key, value)?;
-                Relation::change_entity_id(&other_tree, &key, &new_key, db)?;
+                Relation::change_entity_id(other_tree, &key, &new_key, db)?;
                 tree.remove(&key)?;
             }
         }
-        for (other_tree, _) in family_descriptor.sibling_trees {
+        let instance_siblings = Relation::get_instance_siblings(tree_name, old_id, db)?;
+        if !instance_siblings.is_empty() {
+            Relation::save_instance_siblings(tree_name, new_id, &instance_siblings, db)?;
+            Relation::save_instance_siblings(tree_name, old_id, &[], db)?;
+        }
+        for (other_tree, _) in Relation::sibling_trees(&family_descriptor, instance_siblings) {
             let tree = db.open_tree(&other_tree)?;
-            if let Some(value) = tree.get(old_id)? {
-                tree.insert(&new_id, value)?;
+            if let Some(value) = tree.remove(old_id)? {
+                tree.insert(new_id, value)?;
                 Relation::change_entity_id(&other_tree, old_id, new_id, db)?;
-                tree.remove(&old_id)?;
             }
         }
         Ok(())
@@ -78,30 +140,226 @@ impl Relation {
         name: &str,
         db: &Db,
     ) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::REMOVE_RELATION, E1::store_name());
         Relation::remove_link_with_name(e1, e2, name, db)?;
         Relation::remove_link_with_name(e2, e1, name, db)?;
         Ok(())
     }
 
+    pub fn set<E1: Entity, E2: Entity>(
+        e1: &E1,
+        desired: &[E2],
+        e1_to_e2: DeletionBehaviour,
+        e2_to_e1: DeletionBehaviour,
+        name: Option<&str>,
+        db: &Db,
+    ) -> Result<(usize, usize)> {
+        let e1_key = E1::encode_key(e1.get_key());
+        let mut descriptor = Relation::get_descriptor(e1, db)?;
+        let existing = descriptor
+            .related_entities
+            .get(E2::store_name())
+            .map(|entities| {
+                entities
+                    .iter()
+                    .filter(|rd| rd.name.as_deref() == name)
+                    .map(|rd| rd.key.clone())
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+        let desired = desired
+            .iter()
+            .map(|e2| E2::encode_key(e2.get_key()))
+            .collect::<HashSet<_>>();
+        let mut removed = 0;
+        for key in existing.difference(&desired) {
+            descriptor.remove_related_by_key_and_tree_name_with_optional_name(
+                E2::store_name(),
+                key,
+                name,
+            );
+            let mut other_descriptor =
+                Relation::get_descriptor_with_key_and_tree_name(E2::store_name(), key, db)?;
+            other_descriptor.remove_related_by_key_and_tree_name_with_optional_name(
+                E1::store_name(),
+                &e1_key,
+                name,
+            );
+            Relation::save_descriptor_with_key_and_tree_name(
+                E2::store_name(),
+                key,
+                &other_descriptor,
+                db,
+            )?;
+            removed += 1;
+        }
+        let mut added = 0;
+        for key in desired.difference(&existing) {
+            descriptor.add_related_by_key(E2::store_name(), key, e1_to_e2, name);
+            Relation::create_link_with_keys_and_tree_names(
+                E2::store_name(),
+                key,
+                E1::store_name(),
+                &e1_key,
+                e2_to_e1,
+                name,
+                db,
+            )?;
+            added += 1;
+        }
+        Relation::save_descriptor(e1, &descriptor, db)?;
+        Ok((added, removed))
+    }
+
+    pub fn update<E1: Entity, E2: Entity>(
+        e1: &E1,
+        e2: &E2,
+        e1_to_e2: DeletionBehaviour,
+        e2_to_e1: DeletionBehaviour,
+        name: Option<&str>,
+        db: &Db,
+    ) -> Result<()> {
+        let e1_key = E1::encode_key(e1.get_key());
+        let e2_key = E2::encode_key(e2.get_key());
+        let mut e1_descriptor = Relation::get_descriptor(e1, db)?;
+        let mut e2_descriptor = Relation::get_descriptor(e2, db)?;
+        if !e1_descriptor.update_related_by_key_and_tree_name(
+            E2::store_name(),
+            &e2_key,
+            e1_to_e2,
+            name,
+        ) {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No relation between entities of {} and {}",
+                    E1::store_name(),
+                    E2::store_name()
+                ),
+            ));
+        }
+        e2_descriptor.update_related_by_key_and_tree_name(
+            E1::store_name(),
+            &e1_key,
+            e2_to_e1,
+            name,
+        );
+        Relation::save_descriptor(e1, &e1_descriptor, db)?;
+        Relation::save_descriptor(e2, &e2_descriptor, db)?;
+        Ok(())
+    }
+
     pub fn remove_entity_entry<E1: Entity>(key: &[u8], db: &Db) -> Result<()> {
-        let descriptor = Self::get_descriptor_with_key::<E1>(key, db)?;
-        for (tree_name, referers) in descriptor.related_entities {
+        Self::remove_entity_entry_with_tree_name(E1::store_name(), key, db)
+    }
+
+    pub fn remove_entity_entry_with_tree_name(tree_name: &str, key: &[u8], db: &Db) -> Result<()> {
+        let descriptor = Self::get_descriptor_with_key_and_tree_name(tree_name, key, db)?;
+        for (other_tree_name, referers) in descriptor.related_entities {
             for referer in referers {
                 Self::remove_link_with_keys_and_tree_names(
-                    &tree_name,
+                    &other_tree_name,
                     &referer.key,
-                    E1::store_name(),
+                    tree_name,
                     key,
                     db,
                 )?;
             }
         }
-        let tree = db.open_tree(Relation::tree_name(E1::store_name()))?;
+        let tree = db.open_tree(Relation::tree_name(tree_name))?;
         tree.remove(key)?;
+        Relation::save_instance_siblings(tree_name, key, &[], db)?;
+        #[cfg(feature = "lazy-migration")]
+        crate::migration::forget_version(tree_name, key, db)?;
+        Ok(())
+    }
+
+    /// Adds the changes forgetting the entities `keys` of store `tree_name` to `batches` : their relation
+    /// descriptors and instance siblings are removed, and so are the links other entities have to them.
+    /// Applying them along with the removal of the entities leaves no dangling relation.
+    pub(crate) fn forget_entities_in(
+        tree_name: &str,
+        keys: &[Vec<u8>],
+        batches: &mut TreeBatches,
+        db: &Db,
+    ) -> Result<()> {
+        let forgotten = keys.iter().map(Vec::as_slice).collect::<HashSet<_>>();
+        let mut referers: HashMap<(String, Vec<u8>), EntityRelations> = HashMap::new();
+        for key in keys {
+            let descriptor = Self::get_descriptor_with_key_and_tree_name(tree_name, key, db)?;
+            for (other_tree_name, related) in descriptor.related_entities {
+                for referer in related {
+                    if other_tree_name == tree_name && forgotten.contains(referer.key.as_slice()) {
+                        continue;
+                    }
+                    let referer_descriptor = match referers
+                        .entry((other_tree_name.clone(), referer.key))
+                    {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            let (other_tree_name, referer_key) = entry.key();
+                            let referer_descriptor = Self::get_descriptor_with_key_and_tree_name(
+                                other_tree_name,
+                                referer_key,
+                                db,
+                            )?;
+                            entry.insert(referer_descriptor)
+                        }
+                    };
+                    referer_descriptor.remove_related_by_key_and_tree_name(tree_name, key);
+                }
+            }
+            batches
+                .tree(&Relation::tree_name(tree_name))
+                .remove(key.as_slice());
+            Self::save_instance_siblings_in(tree_name, key, &[], batches, db)?;
+        }
+        for ((other_tree_name, referer_key), descriptor) in referers {
+            batches
+                .tree(&Relation::tree_name(&other_tree_name))
+                .insert(referer_key, descriptor.to_bytes());
+        }
+        Ok(())
+    }
+
+    /// Same as [`create`](#method.create), for the entity `key1` of store `store1` and the entity `key2`
+    /// of store `store2`, so that entities do not need to be fetched to be related.
+    /// Results in a `NotFound` error if either entity does not exist.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_by_keys(
+        store1: &str,
+        key1: &[u8],
+        store2: &str,
+        key2: &[u8],
+        e1_to_e2: DeletionBehaviour,
+        e2_to_e1: DeletionBehaviour,
+        name: Option<&str>,
+        db: &Db,
+    ) -> Result<()> {
+        let tree_names = db.tree_names();
+        for (store, key) in [(store1, key1), (store2, key2)] {
+            if !tree_names.contains(&IVec::from(store))
+                || !db.open_tree(store)?.contains_key(key)?
+            {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("Entity with key {:?} does not exist in {}", key, store),
+                ));
+            }
+        }
+        Relation::create_link_with_keys_and_tree_names(
+            store1, key1, store2, key2, e1_to_e2, name, db,
+        )?;
+        Relation::create_link_with_keys_and_tree_names(
+            store2, key2, store1, key1, e2_to_e1, name, db,
+        )?;
         Ok(())
     }
 
     pub fn remove_by_keys<E1: Entity, E2: Entity>(e1: &[u8], e2: &[u8], db: &Db) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::REMOVE_RELATION, E1::store_name());
         Relation::remove_link_with_keys::<E1, E2>(e1, e2, db)?;
         Relation::remove_link_with_keys::<E2, E1>(e2, e1, db)?;
         Ok(())
@@ -111,182 +369,130 @@ impl Relation {
         Relation::get_descriptor(e1, db)
     }
 
-    pub fn can_be_deleted(
+    pub fn get<E1: Entity, E2: Entity>(e1: &E1, db: &Db) -> Result<Vec<E2>> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_RELATED, E1::store_name());
+        let related_keys = Relation::relations(e1, db)?
+            .take_ordered(E2::store_name())
+            .into_iter()
+            .map(|rd| rd.key)
+            .collect::<Vec<Vec<u8>>>();
+        E2::get_each_u8(&related_keys, db)
+    }
+
+    pub fn rename_with_tree_name(
         tree_name: &str,
-        e1: &[u8],
-        already_checked: &[(String, Vec<u8>)],
-        removable_entities: &mut EntityRelations,
+        key: &[u8],
+        old: &str,
+        new: &str,
         db: &Db,
-    ) -> Result<()> {
-        if already_checked
-            .iter()
-            .any(|(tn, k)| tn == tree_name && k == e1)
-        {
-            return Ok(());
+    ) -> Result<usize> {
+        let mut descriptor = Relation::get_descriptor_with_key_and_tree_name(tree_name, key, db)?;
+        let mut renamed = Vec::new();
+        let count = descriptor.rename_related(
+            |other_tree_name, other_key| {
+                renamed.push((String::from(other_tree_name), other_key.to_vec()));
+                true
+            },
+            old,
+            new,
+        );
+        for (other_tree_name, other_key) in renamed {
+            let mut other_descriptor =
+                Relation::get_descriptor_with_key_and_tree_name(&other_tree_name, &other_key, db)?;
+            other_descriptor.rename_related(
+                |name, other_key| name == tree_name && other_key == key,
+                old,
+                new,
+            );
+            Relation::save_descriptor_with_key_and_tree_name(
+                &other_tree_name,
+                &other_key,
+                &other_descriptor,
+                db,
+            )?;
         }
-        let descriptor = Self::get_descriptor_with_key_and_tree_name(tree_name, e1, db)?;
-        let family_descriptor = FamilyDescriptor::get(&String::from(tree_name), db)?;
+        Relation::save_descriptor_with_key_and_tree_name(tree_name, key, &descriptor, db)?;
+        Ok(count)
+    }
 
-        for (other_tree_name, entities) in &descriptor.related_entities {
-            for rd in entities {
-                match rd.deletion_behaviour {
-                    DeletionBehaviour::Error => {
-                        if already_checked.iter().any(|(tn, k)| {
-                            tn == other_tree_name && k.as_bytes() == rd.key.as_bytes()
-                        }) {
-                            continue;
-                        }
-                        return Err(Error::new(
-                            ErrorKind::IntegrityError,
-                            format!("Constrained related entity exists in {}", other_tree_name),
-                        ));
-                    }
-                    DeletionBehaviour::Cascade => {
-                        let mut new_already_checked = already_checked.to_owned();
-                        new_already_checked.push((String::from(tree_name), e1.to_vec()));
-                        Self::can_be_deleted(
-                            other_tree_name,
-                            &rd.key,
-                            &new_already_checked,
-                            removable_entities,
-                            db,
-                        )?;
-                        removable_entities.add_related_by_key(
-                            other_tree_name,
-                            &rd.key,
-                            DeletionBehaviour::Cascade,
-                            None,
-                        );
-                    }
-                    _ => {}
-                }
-            }
-        }
-        if family_descriptor.is_none() {
-            return Err(Error::new(
-                ErrorKind::UnregisteredEntity,
-                format!("Trying to use unregistered entity {}", tree_name),
-            ));
+    pub fn remove_named_with_tree_name(
+        tree_name: &str,
+        key: &[u8],
+        name: &str,
+        db: &Db,
+    ) -> Result<usize> {
+        let mut descriptor = Relation::get_descriptor_with_key_and_tree_name(tree_name, key, db)?;
+        let removed = descriptor.remove_named(name);
+        if removed.is_empty() {
+            return Ok(0);
         }
-        let family_descriptor = family_descriptor.unwrap();
-        for (other_tree_name, behaviour) in &family_descriptor.sibling_trees {
-            match behaviour {
-                DeletionBehaviour::Error => {
-                    if already_checked
-                        .iter()
-                        .any(|(tn, k)| tn == other_tree_name && k == e1)
-                    {
-                        continue;
-                    }
-                    let tree = db.open_tree(&other_tree_name)?;
-                    if tree.contains_key(e1)? {
-                        return Err(Error::new(
-                            ErrorKind::IntegrityError,
-                            format!("Constrained sibling entity exists in {}", &other_tree_name),
-                        ));
-                    }
-                }
-                DeletionBehaviour::Cascade => {
-                    let mut new_already_checked = already_checked.to_owned();
-                    new_already_checked.push((String::from(tree_name), e1.to_vec()));
-                    Self::can_be_deleted(
-                        other_tree_name,
-                        e1,
-                        &new_already_checked,
-                        removable_entities,
-                        db,
-                    )?;
-                    removable_entities.add_related_by_key(
-                        other_tree_name,
-                        e1,
-                        DeletionBehaviour::Cascade,
-                        None,
-                    );
-                }
-                _ => {}
-            }
+        Relation::save_descriptor_with_key_and_tree_name(tree_name, key, &descriptor, db)?;
+        for (other_tree_name, other_key) in &removed {
+            let mut other_descriptor =
+                Relation::get_descriptor_with_key_and_tree_name(other_tree_name, other_key, db)?;
+            other_descriptor.remove_related_by_key_and_tree_name_with_name(tree_name, key, name);
+            Relation::save_descriptor_with_key_and_tree_name(
+                other_tree_name,
+                other_key,
+                &other_descriptor,
+                db,
+            )?;
         }
-        for (other_tree_name, behaviour) in &family_descriptor.child_trees {
-            match behaviour {
-                DeletionBehaviour::Error => {
-                    let tree = db.open_tree(&other_tree_name)?;
-                    if tree.scan_prefix(e1).count() > 0 {
-                        return Err(Error::new(
-                            ErrorKind::IntegrityError,
-                            format!("Constrained child entity exists in {}", &other_tree_name),
-                        ));
-                    }
-                }
-                DeletionBehaviour::Cascade => {
-                    let mut new_already_checked = already_checked.to_owned();
-                    new_already_checked.push((String::from(tree_name), e1.to_vec()));
-                    let tree = db.open_tree(&other_tree_name)?;
-                    let keys = tree
-                        .scan_prefix(e1)
-                        .filter_map(|e| {
-                            if let Ok((key, _)) = e {
-                                Some(key.to_vec())
-                            } else {
-                                None
+        Ok(removed.len())
+    }
+
+    pub fn summary<E1: Entity>(e1: &E1, db: &Db) -> Result<HashMap<String, Vec<Vec<u8>>>> {
+        Ok(Relation::relations(e1, db)?
+            .related_entities
+            .into_iter()
+            .filter(|(_, entities)| !entities.is_empty())
+            .map(|(tree_name, entities)| (tree_name, entities.into_iter().map(|e| e.key).collect()))
+            .collect())
+    }
+
+    pub fn get_in_depth<E1: Entity, E2: Entity>(e1: &E1, depth: usize, db: &Db) -> Result<Vec<E2>> {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_RELATED, E1::store_name());
+        let start = (String::from(E1::store_name()), E1::encode_key(e1.get_key()));
+        let mut visited = HashSet::new();
+        visited.insert(start.clone());
+        let mut current_level = vec![start];
+        let mut found = Vec::new();
+        for _ in 0..depth {
+            let mut next_level = Vec::new();
+            for (tree_name, key) in &current_level {
+                let descriptor = Self::get_descriptor_with_key_and_tree_name(tree_name, key, db)?;
+                for (other_tree_name, entities) in descriptor.related_entities {
+                    for rd in entities {
+                        let node = (other_tree_name.clone(), rd.key);
+                        if visited.insert(node.clone()) {
+                            if node.0 == E2::store_name() {
+                                found.push(node.1.clone());
                             }
-                        })
-                        .collect::<Vec<Vec<u8>>>();
-                    for key in keys {
-                        Self::can_be_deleted(
-                            other_tree_name,
-                            &key,
-                            &new_already_checked.clone(),
-                            removable_entities,
-                            db,
-                        )?;
-                        removable_entities.add_related_by_key(
-                            other_tree_name,
-                            &key,
-                            DeletionBehaviour::Cascade,
-                            None,
-                        );
+                            next_level.push(node);
+                        }
                     }
                 }
-                _ => {}
             }
+            if next_level.is_empty() {
+                break;
+            }
+            current_level = next_level;
         }
-        Ok(())
-    }
-
-    pub fn get<E1: Entity, E2: Entity>(e1: &E1, db: &Db) -> Result<Vec<E2>> {
-        let referers = Relation::relations(e1, db)?;
-        if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
-            Ok(E2::get_each_u8(
-                (related_keys
-                    .iter()
-                    .map(|e| e.key.clone())
-                    .collect::<Vec<Vec<u8>>>())
-                .as_slice(),
-                db,
-            ))
-        } else {
-            Ok(Vec::new())
-        }
+        E2::get_each_u8(&found, db)
     }
 
     pub fn get_with_name<E1: Entity, E2: Entity>(e1: &E1, name: &str, db: &Db) -> Result<Vec<E2>> {
-        let referers = Relation::relations(e1, db)?;
-        if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
-            Ok(E2::get_each_u8(
-                (related_keys
-                    .iter()
-                    .filter(|rd| match &rd.name {
-                        Some(n) => name == n,
-                        None => false,
-                    })
-                    .map(|e| e.key.clone())
-                    .collect::<Vec<Vec<u8>>>())
-                .as_slice(),
-                db,
-            ))
-        } else {
-            Ok(Vec::new())
-        }
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_RELATED, E1::store_name());
+        let related_keys = Relation::relations(e1, db)?
+            .take_ordered(E2::store_name())
+            .into_iter()
+            .filter(|rd| rd.name.as_deref() == Some(name))
+            .map(|rd| rd.key)
+            .collect::<Vec<Vec<u8>>>();
+        E2::get_each_u8(&related_keys, db)
     }
 
     pub fn get_one<E1: Entity, E2: Entity>(e1: &E1, db: &Db) -> Result<Option<E2>> {
@@ -325,12 +531,35 @@ impl Relation {
         }
     }
 
+    /// Checks if a free relation exists from the entity `key1` of store `store1` to the
+    /// entity `key2` of store `store2`, without loading either entity.
+    ///
+    /// Only the relation descriptor of the first entity is read.
+    ///
+    /// ### Exemple
+    /// ```rust,ignore
+    /// let is_member = Relation::exists_between("user", &user_id.as_bytes(), "org", &org_id.as_bytes(), &db)?;
+    /// ```
+    pub fn exists_between(
+        store1: &str,
+        key1: &[u8],
+        store2: &str,
+        key2: &[u8],
+        db: &Db,
+    ) -> Result<bool> {
+        let descriptor = Relation::get_descriptor_with_key_and_tree_name(store1, key1, db)?;
+        Ok(descriptor
+            .related_entities
+            .get(store2)
+            .is_some_and(|related| related.iter().any(|rd| rd.key == key2)))
+    }
+
     pub fn are_related<E1: Entity, E2: Entity>(e1: &E1, e2: &E2, db: &Db) -> Result<bool> {
         let referers = Relation::relations(e1, db)?;
         if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
             Ok(related_keys
                 .iter()
-                .any(|rd| rd.key == e2.get_key().as_bytes()))
+                .any(|rd| rd.key == E2::encode_key(e2.get_key())))
         } else {
             Ok(false)
         }
@@ -345,7 +574,7 @@ impl Relation {
         let referers = Relation::relations(e1, db)?;
         if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
             Ok(related_keys.iter().any(|rd| {
-                rd.key == e2.get_key().as_bytes()
+                rd.key == E2::encode_key(e2.get_key())
                     && (match &rd.name {
                         Some(rname) => rname == name,
                         None => false,
@@ -356,6 +585,22 @@ impl Relation {
         }
     }
 
+    pub fn has_related_with_name<E1: Entity, E2: Entity>(
+        e1: &E1,
+        name: &str,
+        db: &Db,
+    ) -> Result<bool> {
+        let referers = Relation::relations(e1, db)?;
+        Ok(referers
+            .related_entities
+            .get(E2::store_name())
+            .is_some_and(|related_keys| {
+                related_keys
+                    .iter()
+                    .any(|rd| rd.name.as_deref() == Some(name))
+            }))
+    }
+
     pub fn are_related_with_any_name<E1: Entity, E2: Entity>(
         e1: &E1,
         e2: &E2,
@@ -365,7 +610,7 @@ impl Relation {
         let referers = Relation::relations(e1, db)?;
         if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
             Ok(related_keys.iter().any(|rd| {
-                rd.key == e2.get_key().as_bytes()
+                rd.key == E2::encode_key(e2.get_key())
                     && (match &rd.name {
                         Some(rname) => names.iter().any(|name| name == rname),
                         None => false,
@@ -377,19 +622,150 @@ impl Relation {
     }
 
     fn tree_name(entity_tree: &str) -> String {
-        format!("__$rel_{}", entity_tree)
+        internal_tree_name(&format!("rel_{}", entity_tree))
+    }
+
+    fn instance_siblings_tree_name(entity_tree: &str) -> String {
+        internal_tree_name(&format!("sib_{}", entity_tree))
+    }
+
+    /// Returns the sibling trees declared for the entity type, followed by the ones declared
+    /// by the instance itself and not already declared for the type.
+    fn sibling_trees(
+        family_descriptor: &FamilyDescriptor,
+        instance_siblings: Vec<(String, DeletionBehaviour)>,
+    ) -> Vec<(String, DeletionBehaviour)> {
+        let mut sibling_trees = family_descriptor.sibling_trees.clone();
+        for (other_tree_name, behaviour) in instance_siblings {
+            if !sibling_trees
+                .iter()
+                .any(|(name, _)| *name == other_tree_name)
+            {
+                sibling_trees.push((other_tree_name, behaviour));
+            }
+        }
+        sibling_trees
+    }
+
+    pub fn get_instance_siblings(
+        tree_name: &str,
+        key: &[u8],
+        db: &Db,
+    ) -> Result<Vec<(String, DeletionBehaviour)>> {
+        Self::get_instance_siblings_from_tree(
+            &db.open_tree(Relation::instance_siblings_tree_name(tree_name))?,
+            key,
+        )
     }
 
-    fn get_descriptor_with_key_and_tree_name(
+    /// Same as [`get_instance_siblings`](#method.get_instance_siblings), reading from the already opened
+    /// instance siblings tree of the store.
+    pub(crate) fn get_instance_siblings_from_tree(
+        tree: &Tree,
+        key: &[u8],
+    ) -> Result<Vec<(String, DeletionBehaviour)>> {
+        match tree.get(key)? {
+            Some(siblings) => Ok(bincode::deserialize(&siblings)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn save_instance_siblings(
+        tree_name: &str,
+        key: &[u8],
+        siblings: &[(String, DeletionBehaviour)],
+        db: &Db,
+    ) -> Result<()> {
+        let sibling_tree_name = Relation::instance_siblings_tree_name(tree_name);
+        if !siblings.is_empty() {
+            db.open_tree(sibling_tree_name)?
+                .insert(key, bincode::serialize(siblings)?)?;
+        } else if Self::has_instance_siblings(tree_name, key, db)? {
+            db.open_tree(sibling_tree_name)?.remove(key)?;
+        }
+        Ok(())
+    }
+
+    /// Same as [`save_instance_siblings`](#method.save_instance_siblings), as part of a batch of writes.
+    pub(crate) fn save_instance_siblings_in(
+        tree_name: &str,
+        key: &[u8],
+        siblings: &[(String, DeletionBehaviour)],
+        batches: &mut TreeBatches,
+        db: &Db,
+    ) -> Result<()> {
+        let sibling_tree_name = Relation::instance_siblings_tree_name(tree_name);
+        if !siblings.is_empty() {
+            batches
+                .tree(&sibling_tree_name)
+                .insert(key, bincode::serialize(siblings)?);
+        } else if Self::has_instance_siblings(tree_name, key, db)? {
+            batches.tree(&sibling_tree_name).remove(key);
+        }
+        Ok(())
+    }
+
+    // Avoids creating the instance siblings tree or writing to it when there is nothing to forget
+    fn has_instance_siblings(tree_name: &str, key: &[u8], db: &Db) -> Result<bool> {
+        let sibling_tree_name = Relation::instance_siblings_tree_name(tree_name);
+        Ok(db
+            .tree_names()
+            .contains(&IVec::from(sibling_tree_name.as_str()))
+            && db.open_tree(sibling_tree_name)?.contains_key(key)?)
+    }
+
+    /// Records the [child key separator](entity/trait.Entity.html#associatedconstant.CHILD_KEY_SEPARATOR) of a store.
+    pub fn save_child_key_separator(tree_name: &str, separator: Option<u8>, db: &Db) -> Result<()> {
+        let tree = db.open_tree(internal_tree_name("child_separators"))?;
+        match separator {
+            Some(separator) => tree.insert(tree_name, &[separator])?,
+            None => tree.remove(tree_name)?,
+        };
+        Ok(())
+    }
+
+    /// Returns the prefix of the keys of the children of the entity `parent_key` in the child store `tree_name`.
+    pub fn child_prefix(tree_name: &str, parent_key: &[u8], db: &Db) -> Result<Vec<u8>> {
+        Self::child_prefix_from_tree(
+            &db.open_tree(internal_tree_name("child_separators"))?,
+            tree_name,
+            parent_key,
+        )
+    }
+
+    /// Whether `key` is the key of a direct child of the parent whose children keys start with `prefix`.
+    ///
+    /// Child keys are the prefix followed by the 4 bytes of the child index, so that the children of a parent
+    /// whose key starts with the key of another parent (such as `"id10"` and `"id1"`) are told apart by their length.
+    pub fn is_child_key(prefix: &[u8], key: &[u8]) -> bool {
+        key.len() == prefix.len() + std::mem::size_of::<u32>() && key.starts_with(prefix)
+    }
+
+    /// Same as [`child_prefix`](#method.child_prefix), reading from the already opened tree of child key separators.
+    pub(crate) fn child_prefix_from_tree(
+        separators: &Tree,
+        tree_name: &str,
+        parent_key: &[u8],
+    ) -> Result<Vec<u8>> {
+        Ok(match separators.get(tree_name)? {
+            Some(separator) => [parent_key, &separator].concat(),
+            None => parent_key.to_vec(),
+        })
+    }
+
+    pub fn get_descriptor_with_key_and_tree_name(
         tree_name: &str,
         e: &[u8],
         db: &Db,
     ) -> Result<EntityRelations> {
-        let tree = db.open_tree(Relation::tree_name(tree_name))?;
+        Self::get_descriptor_from_tree(&db.open_tree(Relation::tree_name(tree_name))?, e)
+    }
+
+    /// Same as [`get_descriptor_with_key_and_tree_name`](#method.get_descriptor_with_key_and_tree_name),
+    /// reading from the already opened relation tree of the store.
+    pub(crate) fn get_descriptor_from_tree(tree: &Tree, e: &[u8]) -> Result<EntityRelations> {
         match tree.get(e)? {
-            Some(relation_descriptor) => {
-                Ok(bincode::deserialize::<EntityRelations>(&relation_descriptor).unwrap())
-            }
+            Some(relation_descriptor) => EntityRelations::from_bytes(&relation_descriptor),
             None => Ok(EntityRelations::default()),
         }
     }
@@ -399,12 +775,12 @@ impl Relation {
     }
 
     fn get_descriptor<E: Entity>(e: &E, db: &Db) -> Result<EntityRelations> {
-        Self::get_descriptor_with_key::<E>(&e.get_key().as_bytes(), db)
+        Self::get_descriptor_with_key::<E>(&E::encode_key(e.get_key()), db)
     }
 
     fn save_descriptor_with_key<E: Entity>(e: &[u8], r_d: &EntityRelations, db: &Db) -> Result<()> {
         let tree = db.open_tree(Relation::tree_name(E::store_name()))?;
-        tree.insert(e, bincode::serialize(r_d).unwrap())?;
+        tree.insert(e, r_d.to_bytes())?;
         Ok(())
     }
 
@@ -415,17 +791,58 @@ impl Relation {
         db: &Db,
     ) -> std::io::Result<()> {
         let tree = db.open_tree(Relation::tree_name(tree_name))?;
-        tree.insert(e, bincode::serialize(r_d).unwrap())?;
+        tree.insert(e, r_d.to_bytes())?;
         Ok(())
     }
 
     pub fn save_descriptor<E: Entity>(e: &E, r_d: &EntityRelations, db: &Db) -> Result<()> {
-        Self::save_descriptor_with_key::<E>(&e.get_key().as_bytes(), r_d, db)
+        Self::save_descriptor_with_key::<E>(&E::encode_key(e.get_key()), r_d, db)
     }
 
-    pub fn remove_descriptor(tree_name: &str, e: &[u8], db: &Db) -> Result<()> {
-        let tree = db.open_tree(Relation::tree_name(tree_name))?;
-        tree.remove(e)?;
+    /// Returns the keys of the entities of store `E2` related to `e1`, checking that every stored key
+    /// is a valid representation of `E2::Key`. A malformed key results in an `IntegrityError`.
+    pub fn get_keys<E1: Entity, E2: Entity>(e1: &E1, db: &Db) -> Result<Vec<E2::Key>>
+    where
+        E2::Key: FromBytes,
+    {
+        #[cfg(feature = "metrics")]
+        let _timer = OperationTimer::start(instrument::GET_RELATED, E1::store_name());
+        let referers = Relation::relations(e1, db)?;
+        referers
+            .related_entities
+            .get(E2::store_name())
+            .map(|related| related.iter().map(|e| e.key.as_slice()).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|key| {
+                E2::decode_key(key).map_err(|error| {
+                    Error::new(
+                        ErrorKind::IntegrityError,
+                        format!(
+                            "Malformed relation from {} to {} with key {:?} : {}",
+                            E1::store_name(),
+                            E2::store_name(),
+                            key,
+                            error
+                        ),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    pub fn create_link_with_keys_and_tree_names(
+        tree1: &str,
+        e1: &[u8],
+        tree2: &str,
+        e2: &[u8],
+        e1_to_e2: DeletionBehaviour,
+        name: Option<&str>,
+        db: &Db,
+    ) -> Result<()> {
+        let mut e1_descriptor = Self::get_descriptor_with_key_and_tree_name(tree1, e1, db)?;
+        e1_descriptor.add_related_by_key(tree2, e2, e1_to_e2, name);
+        Self::save_descriptor_with_key_and_tree_name(tree1, e1, &e1_descriptor, db)?;
         Ok(())
     }
 
@@ -476,8 +893,8 @@ impl Relation {
 
     fn remove_link<E1: Entity, E2: Entity>(e1: &E1, e2: &E2, db: &Db) -> Result<()> {
         Relation::remove_link_with_keys::<E1, E2>(
-            &e1.get_key().as_bytes(),
-            &e2.get_key().as_bytes(),
+            &E1::encode_key(e1.get_key()),
+            &E2::encode_key(e2.get_key()),
             db,
         )
     }
@@ -489,14 +906,54 @@ impl Relation {
         db: &Db,
     ) -> Result<()> {
         Relation::remove_link_with_keys_and_relation_name::<E1, E2>(
-            &e1.get_key().as_bytes(),
-            &e2.get_key().as_bytes(),
+            &E1::encode_key(e1.get_key()),
+            &E2::encode_key(e2.get_key()),
             name,
             db,
         )
     }
 }
 
+/// Renames every relation named `old` to `new` for all entities of a store, on both ends of each relation,
+/// and returns the number of renamed relations.
+///
+/// ### Example
+/// ```rust,ignore
+/// reindeer::rename_relation_name_global("user", "owner", "admin", &db)?;
+/// ```
+pub fn rename_relation_name_global(store: &str, old: &str, new: &str, db: &Db) -> Result<usize> {
+    let keys = db
+        .open_tree(Relation::tree_name(store))?
+        .iter()
+        .keys()
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let mut count = 0;
+    for key in keys {
+        count += Relation::rename_with_tree_name(store, &key, old, new, db)?;
+    }
+    Ok(count)
+}
+
+/// Removes every relation named `name` for all entities of a store, on both ends of each relation,
+/// and returns the number of removed relations. Related entities are left untouched.
+///
+/// ### Example
+/// ```rust,ignore
+/// reindeer::remove_relations_by_name("article", "draft", &db)?;
+/// ```
+pub fn remove_relations_by_name(store: &str, name: &str, db: &Db) -> Result<usize> {
+    let keys = db
+        .open_tree(Relation::tree_name(store))?
+        .iter()
+        .keys()
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let mut count = 0;
+    for key in keys {
+        count += Relation::remove_named_with_tree_name(store, &key, name, db)?;
+    }
+    Ok(count)
+}
+
 /// Enum for use in relation description, defining how the database must behave if one end of the relation is removed.
 #[derive(PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Debug)]
 pub enum DeletionBehaviour {
@@ -0,0 +1,406 @@
+//! # Storage Module
+//! This module describes how entities are encoded in the database.
+//! Databases store entities as `bincode` by default, but can be opened in a JSON-backed mode
+//! with [`open_json_backed`](fn.open_json_backed.html), allowing type-erased inspection of their content.
+//! It also provides statistics about the space occupied by stores, health checks, and raw backups of whole databases.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+use crate::config::{internal_prefix, internal_tree_name};
+use crate::error::Result;
+use crate::{Error, ErrorKind};
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+use sled::{Db, Subscriber, Tree};
+
+const STORAGE_FORMAT_KEY: &str = "storage_format";
+// `bincode`, followed by the varint flag and the limit as a big-endian u64 (0 meaning no limit)
+const BINCODE_OPTIONS_LEN: usize = 7 + 1 + 8;
+
+// Storage formats of the open databases, keyed by the address of their default tree. Each entry
+// watches the format key of its database, which tells when the format changed or the database was closed.
+static FORMATS: OnceLock<Mutex<HashMap<usize, (StorageFormat, Subscriber)>>> = OnceLock::new();
+
+/// The format used to encode entities in a database
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum StorageFormat {
+    /// Entities are stored as `bincode`. This is the default, most compact format.
+    Bincode,
+    /// Entities are stored as JSON. Values are self-describing, and fields added to a struct
+    /// can be read from older values using `#[serde(default)]`.
+    Json,
+    /// Entities are stored as `bincode` with custom options, set with [`OpenConfig`](struct.OpenConfig.html).
+    BincodeWith {
+        /// Whether integers use a variable-length encoding, making small integers more compact.
+        /// Values encoded with and without this option cannot be read with the other one.
+        varint: bool,
+        /// The maximum size of a value, in bytes. Reading or writing a larger value results in a `SerializationError`.
+        limit: Option<u64>,
+    },
+}
+
+// Applies the options of a `BincodeWith` format to `bincode`'s default options, which differ in
+// every branch by their type.
+macro_rules! with_bincode_options {
+    ($varint:expr, $limit:expr, |$options:ident| $body:expr) => {{
+        let options = bincode::DefaultOptions::new().allow_trailing_bytes();
+        match ($varint, $limit) {
+            (true, Some(limit)) => {
+                let $options = options.with_varint_encoding().with_limit(limit);
+                $body
+            }
+            (true, None) => {
+                let $options = options.with_varint_encoding().with_no_limit();
+                $body
+            }
+            (false, Some(limit)) => {
+                let $options = options.with_fixint_encoding().with_limit(limit);
+                $body
+            }
+            (false, None) => {
+                let $options = options.with_fixint_encoding().with_no_limit();
+                $body
+            }
+        }
+    }};
+}
+
+impl StorageFormat {
+    /// Returns the storage format of a database.
+    ///
+    /// The format is read once per open database, and cached until it changes.
+    pub fn of(db: &Db) -> Result<StorageFormat> {
+        let id = &***db as *const _ as usize;
+        let mut formats = FORMATS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some((format, subscriber)) = formats.get_mut(&id) {
+            // Any event means the format was changed, and a disconnection that the database
+            // was closed, another one possibly being opened at the same address since.
+            if let Err(RecvTimeoutError::Timeout) = subscriber.next_timeout(Duration::ZERO) {
+                return Ok(*format);
+            }
+        }
+        let meta = db.open_tree(internal_tree_name("meta"))?;
+        let subscriber = meta.watch_prefix(STORAGE_FORMAT_KEY);
+        let format = StorageFormat::read(&meta)?;
+        formats.insert(id, (format, subscriber));
+        Ok(format)
+    }
+
+    fn read(meta: &Tree) -> Result<StorageFormat> {
+        match meta.get(STORAGE_FORMAT_KEY)? {
+            Some(format) if format.as_ref() == b"json" => Ok(StorageFormat::Json),
+            Some(format) if format.len() == BINCODE_OPTIONS_LEN => {
+                let limit =
+                    u64::from_be_bytes(format[BINCODE_OPTIONS_LEN - 8..].try_into().unwrap());
+                Ok(StorageFormat::BincodeWith {
+                    varint: format[BINCODE_OPTIONS_LEN - 9] == 1,
+                    limit: (limit != 0).then_some(limit),
+                })
+            }
+            _ => Ok(StorageFormat::Bincode),
+        }
+    }
+
+    fn is_varint(&self) -> bool {
+        matches!(self, StorageFormat::BincodeWith { varint: true, .. })
+    }
+
+    pub(crate) fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            StorageFormat::Bincode => Ok(bincode::serialize(value)?),
+            StorageFormat::Json => Ok(serde_json::to_vec(value)?),
+            StorageFormat::BincodeWith { varint, limit } => {
+                Ok(with_bincode_options!(*varint, *limit, |options| options.serialize(value))?)
+            }
+        }
+    }
+
+    pub(crate) fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        match self {
+            StorageFormat::Bincode => Ok(bincode::deserialize(bytes)?),
+            StorageFormat::Json => Ok(serde_json::from_slice(bytes)?),
+            StorageFormat::BincodeWith { varint, limit } => {
+                Ok(with_bincode_options!(*varint, *limit, |options| options.deserialize(bytes))?)
+            }
+        }
+    }
+}
+
+/// Opens a `sled` database that stores entities as JSON instead of `bincode`.
+///
+/// The format is recorded in the database, so it can be reopened later with either this function
+/// or [`open`](fn.open.html). Opening an existing database that already contains `bincode` entities
+/// results in an `IntegrityError`.
+///
+/// ### Example
+/// ```rust,ignore
+/// let db = reindeer::open_json_backed("./admin-db")?;
+/// MyStruct::register(&db)?;
+/// ```
+pub fn open_json_backed<P: AsRef<Path>>(path: P) -> Result<Db> {
+    let db = sled::open(path)?;
+    if StorageFormat::of(&db)? == StorageFormat::Json {
+        return Ok(db);
+    }
+    if let Some(name) = first_non_empty_store(&db)? {
+        return Err(Error::new(
+            ErrorKind::IntegrityError,
+            format!(
+                "Cannot open database as JSON-backed : store {} already contains bincode entities",
+                name
+            ),
+        ));
+    }
+    db.open_tree(internal_tree_name("meta"))?
+        .insert(STORAGE_FORMAT_KEY, "json")?;
+    Ok(db)
+}
+
+/// Records the `bincode` options of a database. Changing the integer encoding of a database
+/// that already contains entities results in an `IntegrityError`, since they could not be read anymore.
+pub(crate) fn set_bincode_options(db: &Db, varint: bool, limit: Option<u64>) -> Result<()> {
+    let format = StorageFormat::of(db)?;
+    if format == StorageFormat::Json {
+        return Err(Error::new(
+            ErrorKind::IntegrityError,
+            String::from("Cannot set bincode options : the database is JSON-backed"),
+        ));
+    }
+    if format.is_varint() != varint {
+        if let Some(name) = first_non_empty_store(db)? {
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Cannot change the integer encoding of the database : store {} already contains entities",
+                    name
+                ),
+            ));
+        }
+    }
+    let value = [
+        b"bincode".as_slice(),
+        &[varint as u8],
+        &limit.unwrap_or(0).to_be_bytes(),
+    ]
+    .concat();
+    db.open_tree(internal_tree_name("meta"))?
+        .insert(STORAGE_FORMAT_KEY, value)?;
+    Ok(())
+}
+
+fn first_non_empty_store(db: &Db) -> Result<Option<String>> {
+    for name in db.tree_names() {
+        if name.starts_with(internal_prefix().as_bytes()) {
+            continue;
+        }
+        if !db.open_tree(&name)?.is_empty() {
+            return Ok(Some(String::from_utf8_lossy(&name).into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns every entity of a store as a `serde_json::Value`, without knowing its type.
+///
+/// This is only possible for JSON-backed databases (see [`open_json_backed`](fn.open_json_backed.html)),
+/// since `bincode` values are not self-describing. Calling it on a `bincode` database results in
+/// a `SerializationError`.
+///
+/// ### Example
+/// ```rust,ignore
+/// for value in reindeer::dump_store_json("my_struct", &db)? {
+///     println!("{}", value);
+/// }
+/// ```
+pub fn dump_store_json(store_name: &str, db: &Db) -> Result<Vec<serde_json::Value>> {
+    if StorageFormat::of(db)? != StorageFormat::Json {
+        return Err(Error::new(
+            ErrorKind::SerializationError,
+            format!(
+                "Cannot dump store {} as JSON : the database is not JSON-backed",
+                store_name
+            ),
+        ));
+    }
+    let mut result = Vec::new();
+    for elem in db.open_tree(store_name)?.iter() {
+        let (_, value) = elem?;
+        result.push(match serde_json::from_slice(&value) {
+            Ok(value) => value,
+            // values of entities with a type tag are prefixed by the tag
+            Err(error) => match value.first() {
+                Some(&len) if value.len() > 1 + len as usize => {
+                    serde_json::from_slice(&value[1 + len as usize..])?
+                }
+                _ => return Err(error.into()),
+            },
+        });
+    }
+    Ok(result)
+}
+
+pub(crate) fn tree_size(tree: &Tree) -> Result<u64> {
+    let mut size = 0;
+    for elem in tree.iter() {
+        let (key, value) = elem?;
+        size += (key.len() + value.len()) as u64;
+    }
+    Ok(size)
+}
+
+/// Returns the space occupied by every tree of the database, including internal ones,
+/// as the sum of the byte lengths of their keys and values.
+///
+/// ⚠ This is an O(n) operation, since every entry of the database is read.
+///
+/// ### Example
+/// ```rust,ignore
+/// let bytes = reindeer::total_size(&db)?;
+/// ```
+pub fn total_size(db: &Db) -> Result<u64> {
+    let mut size = 0;
+    for name in db.tree_names() {
+        size += tree_size(&db.open_tree(name)?)?;
+    }
+    Ok(size)
+}
+
+/// Summary of the state of a database, returned by [`health`](fn.health.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthReport {
+    /// Number of trees in the database, including internal ones.
+    pub tree_count: usize,
+    /// Number of entries in user stores, that is every tree except the internal ones.
+    pub record_count: usize,
+    /// Whether a sentinel entry could be written to, read from and removed from an internal tree.
+    pub writable: bool,
+}
+
+/// Returns a summary of the database, meant to be used by health checks : its number of trees,
+/// its number of records and whether it can still be written to, without knowing its entity types.
+///
+/// Writability is checked by writing, reading and removing a sentinel entry in an internal tree.
+/// A failure of this probe is reported as `writable: false` rather than as an error.
+///
+/// ⚠ This is an O(n) operation, since records are counted by reading every entry of each user store.
+///
+/// ### Example
+/// ```rust,ignore
+/// let report = reindeer::health(&db)?;
+/// if !report.writable {
+///     return StatusCode::SERVICE_UNAVAILABLE;
+/// }
+/// ```
+pub fn health(db: &Db) -> Result<HealthReport> {
+    let tree_names = db.tree_names();
+    let mut record_count = 0;
+    for name in &tree_names {
+        if name.starts_with(internal_prefix().as_bytes()) || *name == db.name() {
+            continue;
+        }
+        record_count += db.open_tree(name)?.len();
+    }
+    Ok(HealthReport {
+        tree_count: tree_names.len(),
+        record_count,
+        writable: probe(db).unwrap_or(false),
+    })
+}
+
+fn probe(db: &Db) -> Result<bool> {
+    const SENTINEL: &[u8] = b"sentinel";
+    let tree = db.open_tree(internal_tree_name("health"))?;
+    tree.insert(SENTINEL, SENTINEL)?;
+    let read = tree.get(SENTINEL)?;
+    tree.remove(SENTINEL)?;
+    Ok(read.as_deref() == Some(SENTINEL))
+}
+
+type ExportedTree = (Vec<u8>, Vec<u8>, Vec<Vec<Vec<u8>>>);
+
+/// Writes a raw snapshot of every tree of the database, including relations and other internal trees, to `path`.
+///
+/// This is the most faithful backup of a database, since it keeps the exact bytes of every entry,
+/// but it is tied to `sled`'s export representation : contrary to the JSON exporters
+/// (see [`Entity::export_json`](entity/trait.Entity.html#method.export_json)), it cannot be read or edited by other tools.
+/// Use [`restore`](fn.restore.html) to create a database from it.
+///
+/// ⚠ The whole database is loaded in memory while it is written.
+///
+/// ### Example
+/// ```rust,ignore
+/// reindeer::backup(&db, "./backups/2024-01-01.bak")?;
+/// ```
+pub fn backup<P: AsRef<Path>>(db: &Db, path: P) -> Result<()> {
+    let mut trees: Vec<ExportedTree> = Vec::new();
+    for name in db.tree_names() {
+        let mut entries = Vec::new();
+        for elem in db.open_tree(&name)?.iter() {
+            let (key, value) = elem?;
+            entries.push(vec![key.to_vec(), value.to_vec()]);
+        }
+        trees.push((b"tree".to_vec(), name.to_vec(), entries));
+    }
+    bincode::serialize_into(BufWriter::new(File::create(path)?), &trees)?;
+    Ok(())
+}
+
+/// Creates a database at `db_path` from a snapshot written by [`backup`](fn.backup.html) at `backup_path`.
+///
+/// The database at `db_path` must be empty : restoring into a database that already contains
+/// entries results in an `IntegrityError`, and nothing is imported. A file that is not a valid backup
+/// results in a `SerializationError`, and nothing is imported either.
+///
+/// ### Example
+/// ```rust,ignore
+/// let db = reindeer::restore("./backups/2024-01-01.bak", "./restored-db")?;
+/// ```
+pub fn restore<P: AsRef<Path>, Q: AsRef<Path>>(backup_path: P, db_path: Q) -> Result<Db> {
+    let file = File::open(backup_path)?;
+    // lengths read from the file cannot exceed its size, so that a corrupted file cannot exhaust memory
+    let trees: Vec<ExportedTree> = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(file.metadata()?.len())
+        .deserialize_from(BufReader::new(file))?;
+    for (kind, name, entries) in &trees {
+        if kind.as_slice() != b"tree" || entries.iter().any(|entry| entry.len() != 2) {
+            return Err(Error::new(
+                ErrorKind::SerializationError,
+                format!(
+                    "Malformed backup : invalid entry in tree {}",
+                    String::from_utf8_lossy(name)
+                ),
+            ));
+        }
+    }
+    let db = sled::open(db_path)?;
+    for name in db.tree_names() {
+        if !db.open_tree(&name)?.is_empty() {
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Cannot restore a backup into a database that is not empty : tree {} contains entries",
+                    String::from_utf8_lossy(&name)
+                ),
+            ));
+        }
+    }
+    db.import(
+        trees
+            .into_iter()
+            .map(|(kind, name, entries)| (kind, name, entries.into_iter()))
+            .collect(),
+    );
+    Ok(db)
+}
@@ -1,11 +1,15 @@
 mod test_entities;
 
 use crate::{
-    error::Result, relation::FamilyDescriptor, test::test_entities::GrandChildEntity,
-    AutoIncrementEntity, DeletionBehaviour, Entity,
+    error::Result, relation::FamilyDescriptor, test::test_entities::GrandChildEntity, AsBytes,
+    AutoIncrementEntity, DanglingRelations, DeletionBehaviour, Entity, FamilyRelation, FromBytes,
+    ImportStrategy, KeyStr, Mergeable, Reindeer, Relation, StorageFormat, Timestamped,
 };
+use std::{marker::PhantomData, ops::Bound};
 use test_entities::{
-    set_up, set_up_content, tear_down, ChildEntity1, ChildEntity2, Entity1, Entity2, Entity3,
+    reopen, set_up, set_up_content, tear_down, Bearer, Blob, ChildEntity1, ChildEntity2, Counter,
+    Entity1, Entity2, Entity3, Folder, FolderItem, Heartbeat, Impostor, Intruder, Note, Opaque,
+    OpaquePart, Oversized, Sample, Session, Tag, Token,
 };
 use uuid::Uuid;
 
@@ -449,3 +453,3332 @@ fn test_named_relations() -> Result<()> {
     tear_down(&name)?;
     Ok(())
 }
+
+#[test]
+fn test_remove_with_override() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    assert!(Entity3::remove(&2, &db).is_err());
+    Entity3::remove_with_override(
+        &2,
+        &[
+            ("child_entity_2", DeletionBehaviour::Cascade),
+            ("entity_1", DeletionBehaviour::BreakLink),
+        ],
+        &db,
+    )?;
+    assert!(Entity3::get(&2, &db)?.is_none());
+    assert!(Entity1::get(&2, &db)?.is_some());
+    assert_eq!(ChildEntity2::get_count(&db)?, 0);
+    let e3_1 = Entity3::get(&1, &db)?.unwrap();
+    let mut child = ChildEntity2 { id: (0, 0) };
+    e3_1.save_next_child(&mut child, &db)?;
+    assert!(
+        Entity3::remove_with_override(&1, &[("entity_1", DeletionBehaviour::BreakLink)], &db)
+            .is_err()
+    );
+    assert!(Entity3::get(&1, &db)?.is_some());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_save_concurrent() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Counter::register_mergeable(&db)?;
+    let handles = (0..8)
+        .map(|_| {
+            let db = db.clone();
+            std::thread::spawn(move || -> Result<()> {
+                for _ in 0..100 {
+                    Counter::merge_save(&1, Counter { id: 0, count: 1 }, &db)?;
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+    let counter = Counter::get(&1, &db)?.unwrap();
+    assert_eq!(counter.id, 1);
+    assert_eq!(counter.count, 800);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_save_errors() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    assert!(Counter::merge_save(&1, Counter { id: 0, count: 1 }, &db).is_err());
+    Counter::register_mergeable(&db)?;
+    Counter::merge_save(&1, Counter { id: 0, count: 1 }, &db)?;
+    Counter::get_tree(&db)?.insert(2u32.as_bytes(), vec![1u8])?;
+    let error = Counter::merge_save(&2, Counter { id: 0, count: 1 }, &db)
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::SerializationError));
+    assert_eq!(
+        Counter::get_tree(&db)?.get(2u32.as_bytes())?.unwrap(),
+        vec![1u8]
+    );
+    assert_eq!(Counter::get(&1, &db)?.unwrap().count, 1);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[cfg(feature = "changelog")]
+#[test]
+fn test_changelog() -> Result<()> {
+    use crate::{changes_since, prune_changelog, ChangeOp};
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let start = changes_since(0, &db)?;
+    assert!(start.iter().all(|c| c.op == ChangeOp::Save));
+    assert!(start.iter().all(|c| !c.store.starts_with("__$")));
+    let checkpoint = start.last().unwrap().timestamp + 1;
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    Entity2::remove(&String::from("id3"), &db)?;
+    let changes = changes_since(checkpoint, &db)?;
+    assert_eq!(changes.len(), 7);
+    assert!(changes.iter().all(|c| c.op == ChangeOp::Remove));
+    assert!(changes
+        .iter()
+        .any(|c| c.store == "entity_2" && c.key == b"id3".to_vec()));
+    assert_eq!(prune_changelog(checkpoint, &db)?, start.len());
+    assert_eq!(changes_since(0, &db)?.len(), 7);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[cfg(feature = "changelog")]
+#[test]
+fn test_changelog_replace_all() -> Result<()> {
+    use crate::{changes_since, ChangeOp};
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let checkpoint = changes_since(0, &db)?.last().unwrap().timestamp + 1;
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    let kept = Entity1::get(&1, &db)?.unwrap();
+    Entity1::replace_all(&[kept], DeletionBehaviour::BreakLink, &db)?;
+    let changes = changes_since(checkpoint, &db)?;
+    assert_eq!(
+        changes
+            .iter()
+            .filter(|c| c.store == "entity_1" && c.op == ChangeOp::Remove)
+            .count(),
+        2
+    );
+    assert!(changes
+        .iter()
+        .any(|c| c.op == ChangeOp::Save && c.key == 1u32.as_bytes()));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_save_batch_instance_siblings() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    Token::<Session>::register(&db)?;
+    Token::<Session>::save_batch(
+        &[Token::<Session> {
+            id: String::from("id1"),
+            value: String::from("session"),
+            kind: PhantomData,
+        }],
+        &db,
+    )?;
+    Token::<Session>::remove(&String::from("id1"), &db)?;
+    assert!(!Entity2::exists(&String::from("id1"), &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_exists_all_and_missing_keys() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    assert!(Entity1::exists_all(&[0, 1, 2], &db)?);
+    assert!(!Entity1::exists_all(&[0, 5, 2], &db)?);
+    assert!(Entity1::missing_keys(&[0, 1, 2], &db)?.is_empty());
+    assert_eq!(Entity1::missing_keys(&[7, 1, 5], &db)?, vec![7, 5]);
+    assert_eq!(
+        Entity2::missing_keys(&[String::from("id1"), String::from("id4")], &db)?,
+        vec![String::from("id4")]
+    );
+    Token::<Session>::register(&db)?;
+    Token::<Session> {
+        id: String::from("a"),
+        value: String::from("session a"),
+        kind: PhantomData,
+    }
+    .save(&db)?;
+    Token::<Bearer> {
+        id: String::from("c"),
+        value: String::from("bearer c"),
+        kind: PhantomData,
+    }
+    .save(&db)?;
+    let keys = [String::from("a"), String::from("c")];
+    assert!(!Token::<Session>::exists_all(&[String::from("c")], &db)?);
+    assert_eq!(
+        Token::<Session>::missing_keys(&keys, &db)?,
+        vec![String::from("c")]
+    );
+    assert!(Token::<Bearer>::exists_all(&[String::from("c")], &db)?);
+    assert_eq!(
+        Token::<Bearer>::missing_keys(&keys, &db)?,
+        vec![String::from("a")]
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_related_depth() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1_0 = Entity1::get(&0, &db)?.unwrap();
+    let e1_1 = Entity1::get(&1, &db)?.unwrap();
+    let e1_2 = Entity1::get(&2, &db)?.unwrap();
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e2_2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    let e3_0 = Entity3::get(&0, &db)?.unwrap();
+    e1_0.create_relation(
+        &e2_1,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    e2_1.create_relation(
+        &e1_1,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    e1_1.create_relation(
+        &e2_2,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    e2_2.create_relation(
+        &e1_2,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    e1_2.create_relation(
+        &e3_0,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    assert_eq!(e1_0.get_related_depth::<Entity1>(1, &db)?.len(), 0);
+    let depth_2 = e1_0.get_related_depth::<Entity1>(2, &db)?;
+    assert_eq!(depth_2.len(), 1);
+    assert_eq!(depth_2[0].id, 1);
+    assert_eq!(e1_0.get_related_depth::<Entity1>(4, &db)?.len(), 2);
+    assert_eq!(e1_0.get_related_depth::<Entity2>(10, &db)?.len(), 2);
+    assert_eq!(e1_0.get_related_depth::<Entity3>(4, &db)?.len(), 0);
+    assert_eq!(e1_0.get_related_depth::<Entity3>(5, &db)?.len(), 1);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_relations_are_ordered() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&1, &db)?.unwrap();
+    for id in ["id3", "id1", "id2"] {
+        let e2 = Entity2::get(&String::from(id), &db)?.unwrap();
+        e1.create_relation(
+            &e2,
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            None,
+            &db,
+        )?;
+    }
+    let related = e1.get_related::<Entity2>(&db)?;
+    let keys = related.iter().map(|e| e.id.as_str()).collect::<Vec<&str>>();
+    assert_eq!(keys, vec!["id1", "id2", "id3"]);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_cascade_with_constrained_entity_in_same_deletion() -> Result<()> {
+    for reversed in [false, true] {
+        let name = get_random_name();
+        let db = set_up(&name)?;
+        set_up_content(&db)?;
+        let mut e1 = Entity1 {
+            id: 0,
+            prop1: String::from("Root"),
+        };
+        e1.save_next(&db)?;
+        let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+        let e2_2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+        let mut targets = vec![&e2_1, &e2_2];
+        if reversed {
+            targets.reverse();
+        }
+        for target in targets {
+            e1.create_relation(
+                target,
+                DeletionBehaviour::Cascade,
+                DeletionBehaviour::BreakLink,
+                None,
+                &db,
+            )?;
+        }
+        e2_1.create_relation(
+            &e2_2,
+            DeletionBehaviour::Error,
+            DeletionBehaviour::BreakLink,
+            None,
+            &db,
+        )?;
+        assert!(Entity2::remove(e2_1.get_key(), &db).is_err());
+        Entity1::remove(e1.get_key(), &db)?;
+        assert!(Entity1::get(e1.get_key(), &db)?.is_none());
+        assert!(Entity2::get(e2_1.get_key(), &db)?.is_none());
+        assert!(Entity2::get(e2_2.get_key(), &db)?.is_none());
+        assert!(Entity2::get(&String::from("id3"), &db)?.is_some());
+        tear_down(&name)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_cascade_breaks_links_of_removed_entities() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e3 = Entity3::get(&0, &db)?.unwrap();
+    e3.create_relation(
+        &e2_1,
+        DeletionBehaviour::Cascade,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    e2_1.create_relation(
+        &e1,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    assert!(e1.is_related_to(&e2_1, &db)?);
+    Entity3::remove_with_override(&0, &[("entity_1", DeletionBehaviour::BreakLink)], &db)?;
+    assert!(Entity2::get(e2_1.get_key(), &db)?.is_none());
+    assert!(!e1.is_related_to(&e2_1, &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_fixed_size_array_keys() -> Result<()> {
+    let hash = [7u8; 16];
+    assert_eq!(hash.as_bytes(), vec![7u8; 16]);
+    assert_eq!(<[u8; 16]>::from_bytes(&hash.as_bytes())?, hash);
+    assert!(<[u8; 32]>::from_bytes(&hash.as_bytes()).is_err());
+    assert_eq!(u32::from_bytes(&42u32.as_bytes())?, 42);
+    assert!(u64::from_bytes(&42u32.as_bytes()).is_err());
+    assert_eq!(String::from_bytes(b"id1")?, "id1");
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Blob::register(&db)?;
+    let key = [3u8; 32];
+    Blob {
+        hash: key,
+        data: vec![1, 2],
+    }
+    .save(&db)?;
+    assert_eq!(Blob::get(&key, &db)?.unwrap().data, vec![1, 2]);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[cfg(feature = "content-addressed")]
+#[test]
+fn test_save_content_addressed() -> Result<()> {
+    use crate::ContentAddressedEntity;
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let mut blob = Blob {
+        hash: [0; 32],
+        data: vec![1, 2, 3],
+    };
+    let hash = blob.save_content_addressed(&db)?;
+    assert_eq!(blob.hash, hash);
+    let mut same = Blob {
+        hash: [9; 32],
+        data: vec![1, 2, 3],
+    };
+    assert_eq!(same.save_content_addressed(&db)?, hash);
+    let mut other = Blob {
+        hash: [0; 32],
+        data: vec![3, 2, 1],
+    };
+    assert_ne!(other.save_content_addressed(&db)?, hash);
+    assert_eq!(Blob::get_count(&db)?, 2);
+    assert_eq!(Blob::get(&hash, &db)?.unwrap().data, vec![1, 2, 3]);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_export_json_pretty() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let mut path = std::env::temp_dir();
+    path.push(format!("{}.json", name));
+    Entity2::export_json_pretty(std::fs::File::create(&path)?, &db)?;
+    let content = std::fs::read_to_string(&path)?;
+    assert!(content.contains("\n  {"));
+    let ids = serde_json::from_str::<Vec<Entity2>>(&content)?
+        .into_iter()
+        .map(|e| e.id)
+        .collect::<Vec<String>>();
+    assert_eq!(ids, vec!["id1", "id2", "id3"]);
+    std::fs::remove_file(&path)?;
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_import_json_with_strategy() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let mut path = std::env::temp_dir();
+    path.push(format!("{}.json", name));
+    std::fs::write(
+        &path,
+        r#"[{"id":"id1","prop2":42},{"id":"id4","prop2":43}]"#,
+    )?;
+    assert!(
+        Entity2::import_json_with(std::fs::File::open(&path)?, ImportStrategy::Error, &db).is_err()
+    );
+    assert!(Entity2::get(&String::from("id4"), &db)?.is_none());
+    Entity2::import_json_with(std::fs::File::open(&path)?, ImportStrategy::Skip, &db)?;
+    assert_eq!(Entity2::get(&String::from("id1"), &db)?.unwrap().prop2, 3);
+    assert_eq!(Entity2::get(&String::from("id4"), &db)?.unwrap().prop2, 43);
+    Entity2::import_json_with(std::fs::File::open(&path)?, ImportStrategy::Overwrite, &db)?;
+    assert_eq!(Entity2::get(&String::from("id1"), &db)?.unwrap().prop2, 42);
+    std::fs::remove_file(&path)?;
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_import_json_validation() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let mut path = std::env::temp_dir();
+    path.push(format!("{}.json", name));
+    std::fs::write(
+        &path,
+        r#"[{"id":"id4","prop2":1},{"id":"","prop2":2},{"id":"id4","prop2":3}]"#,
+    )?;
+    let error = Entity2::import_json(std::fs::File::open(&path)?, true, &db)
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    assert!(error.to_string().contains("record 1"));
+    assert!(Entity2::get(&String::from("id4"), &db)?.is_none());
+    std::fs::write(
+        &path,
+        r#"[{"id":"id4","prop2":1},{"id":"id5","prop2":2},{"id":"id4","prop2":3}]"#,
+    )?;
+    let error = Entity2::import_json(std::fs::File::open(&path)?, true, &db)
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("record 2"));
+    Entity2::import_json(std::fs::File::open(&path)?, false, &db)?;
+    assert_eq!(Entity2::get(&String::from("id4"), &db)?.unwrap().prop2, 3);
+    std::fs::remove_file(&path)?;
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_export_import_json_with_relations() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e3_0 = Entity3::get(&0, &db)?.unwrap();
+    let e3_2 = Entity3::get(&2, &db)?.unwrap();
+    e2_1.create_relation(
+        &e3_0,
+        DeletionBehaviour::Cascade,
+        DeletionBehaviour::BreakLink,
+        Some("rel1"),
+        &db,
+    )?;
+    e2_1.create_relation(
+        &e3_2,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    let mut path = std::env::temp_dir();
+    path.push(format!("{}.json", name));
+    Entity2::export_json_with_relations(std::fs::File::create(&path)?, &db)?;
+
+    let other_name = get_random_name();
+    let other_db = set_up(&other_name)?;
+    Entity3 { id: 0 }.save(&other_db)?;
+    assert!(Entity2::import_json_with_relations(
+        std::fs::File::open(&path)?,
+        DanglingRelations::Error,
+        &other_db
+    )
+    .is_err());
+    assert_eq!(Entity2::get_count(&other_db)?, 0);
+    Entity2::import_json_with_relations(
+        std::fs::File::open(&path)?,
+        DanglingRelations::Drop,
+        &other_db,
+    )?;
+    assert_eq!(Entity2::get_count(&other_db)?, 3);
+    let imported = Entity2::get(&String::from("id1"), &other_db)?.unwrap();
+    let related = imported.get_related::<Entity3>(&other_db)?;
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].id, 0);
+    assert!(imported.is_related_to_with_name(&related[0], "rel1", &other_db)?);
+    assert!(related[0].is_related_to_with_name(&imported, "rel1", &other_db)?);
+    assert!(Entity2::remove(&String::from("id1"), &other_db).is_ok());
+    assert!(Entity3::get(&0, &other_db)?.is_none());
+    std::fs::remove_file(&path)?;
+    tear_down(&name)?;
+    tear_down(&other_name)?;
+    Ok(())
+}
+
+#[test]
+fn test_export_relations_base64_keys() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    e2_1.create_relation(
+        &Entity3 { id: 0 },
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    let mut path = std::env::temp_dir();
+    path.push(format!("{}.json", name));
+    Entity2::export_json_with_relations(std::fs::File::create(&path)?, &db)?;
+    let mut exported: serde_json::Value = serde_json::from_reader(std::fs::File::open(&path)?)?;
+    let relation = exported
+        .as_array_mut()
+        .unwrap()
+        .iter_mut()
+        .find(|e| e["entity"]["id"] == "id1")
+        .unwrap()["relations"][0]
+        .as_object_mut()
+        .unwrap();
+    assert_eq!(relation["key"], "AAAAAA==");
+    relation.insert(String::from("key"), serde_json::json!([0, 0, 0, 0]));
+    std::fs::write(&path, serde_json::to_string(&exported)?)?;
+    Entity2::import_json_with_relations(
+        std::fs::File::open(&path)?,
+        DanglingRelations::Error,
+        &db,
+    )?;
+    assert_eq!(e2_1.get_related::<Entity3>(&db)?[0].id, 0);
+    std::fs::remove_file(&path)?;
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_or_create_sibling() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let mut e1 = Entity1 {
+        id: 0,
+        prop1: String::from("Lonely"),
+    };
+    e1.save_next(&db)?;
+    assert!(e1.get_sibling::<Entity3>(&db)?.is_none());
+    let e3 = e1.get_or_create_sibling(|| Entity3 { id: 0 }, &db)?;
+    assert_eq!(e3.id, e1.id);
+    assert!(e1.get_sibling::<Entity3>(&db)?.is_some());
+    let e3 = e1.get_or_create_sibling::<Entity3, _>(|| panic!("Sibling already exists"), &db)?;
+    assert_eq!(e3.id, e1.id);
+    Token::<Session>::register(&db)?;
+    Token::<Bearer> {
+        id: String::from("id1"),
+        value: String::from("bearer id1"),
+        kind: PhantomData,
+    }
+    .save(&db)?;
+    let new_token = || Token::<Session> {
+        id: String::new(),
+        value: String::from("session"),
+        kind: PhantomData,
+    };
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    assert!(matches!(
+        e2_1.get_or_create_sibling(new_token, &db),
+        Err(e) if matches!(e.kind(), crate::ErrorKind::IntegrityError)
+    ));
+    assert_eq!(
+        Token::<Bearer>::get(&String::from("id1"), &db)?
+            .unwrap()
+            .value,
+        "bearer id1"
+    );
+    let e2_2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    let session = e2_2.get_or_create_sibling(new_token, &db)?;
+    assert_eq!(session.id, "id2");
+    assert!(Token::<Session>::exists(&String::from("id2"), &db)?);
+    let empty = Entity2 {
+        id: String::new(),
+        prop2: 0,
+    };
+    assert!(matches!(
+        empty.get_or_create_sibling(new_token, &db),
+        Err(e) if matches!(e.kind(), crate::ErrorKind::IntegrityError)
+    ));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_child_store_must_match_parent() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&1, &db)?.unwrap();
+    let mut child = ChildEntity2 { id: (0, 0) };
+    assert!(e1.save_next_child(&mut child, &db).is_err());
+    assert!(e1.get_children::<ChildEntity2>(&db).is_err());
+    let e3 = Entity3::get(&1, &db)?.unwrap();
+    assert!(e3.save_next_child(&mut child, &db).is_ok());
+    assert_eq!(e3.get_children::<ChildEntity2>(&db)?.len(), 1);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_type_tag_filters_shared_store() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Token::<Session>::register(&db)?;
+    for (id, value) in [("a", "session a"), ("b", "session b")] {
+        Token::<Session> {
+            id: String::from(id),
+            value: String::from(value),
+            kind: PhantomData,
+        }
+        .save(&db)?;
+    }
+    Token::<Bearer> {
+        id: String::from("c"),
+        value: String::from("bearer c"),
+        kind: PhantomData,
+    }
+    .save(&db)?;
+    let sessions = Token::<Session>::get_all(&db)?;
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions.iter().all(|t| t.value.starts_with("session")));
+    assert_eq!(Token::<Bearer>::get_count(&db)?, 1);
+    assert!(Token::<Session>::get(&String::from("c"), &db)?.is_none());
+    assert!(!Token::<Session>::exists(&String::from("c"), &db)?);
+    assert_eq!(
+        Token::<Bearer>::get(&String::from("c"), &db)?
+            .unwrap()
+            .value,
+        "bearer c"
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_type_tag_protects_other_types() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    Token::<Session>::register(&db)?;
+    Token::<Session> {
+        id: String::from("a"),
+        value: String::from("session a"),
+        kind: PhantomData,
+    }
+    .save(&db)?;
+    let bearer = Token::<Bearer> {
+        id: String::from("a"),
+        value: String::from("bearer a"),
+        kind: PhantomData,
+    };
+    let error = bearer.save(&db).unwrap_err();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    let error = Token::<Bearer>::save_batch(&[bearer], &db).unwrap_err();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    let error = Token::<Bearer>::remove(&String::from("a"), &db).unwrap_err();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    assert_eq!(
+        Token::<Session>::get(&String::from("a"), &db)?
+            .unwrap()
+            .value,
+        "session a"
+    );
+    Token::<Session>::remove(&String::from("a"), &db)?;
+    assert!(Token::<Session>::get(&String::from("a"), &db)?.is_none());
+    let error = Token::<Oversized>::register(&db).unwrap_err();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    let error = Token::<Oversized> {
+        id: String::from("b"),
+        value: String::from("oversized"),
+        kind: PhantomData,
+    }
+    .save(&db)
+    .unwrap_err();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_instance_sibling_trees() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    Token::<Session>::register(&db)?;
+    Token::<Session> {
+        id: String::from("id1"),
+        value: String::from("session"),
+        kind: PhantomData,
+    }
+    .save(&db)?;
+    Token::<Bearer> {
+        id: String::from("id2"),
+        value: String::from("bearer"),
+        kind: PhantomData,
+    }
+    .save(&db)?;
+    Token::<Session>::remove(&String::from("id1"), &db)?;
+    Token::<Bearer>::remove(&String::from("id2"), &db)?;
+    assert!(!Entity2::exists(&String::from("id1"), &db)?);
+    assert!(Entity2::exists(&String::from("id2"), &db)?);
+    let sibling_tree = sled::IVec::from(crate::config::internal_tree_name("sib_entity_1").as_str());
+    assert!(!db.tree_names().contains(&sibling_tree));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_related_summary() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&2, &db)?.unwrap();
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e3 = Entity3::get(&0, &db)?.unwrap();
+    e1.create_relation(
+        &e2,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    e1.create_relation(
+        &e3,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    let summary = e1.related_summary(&db)?;
+    assert_eq!(summary.len(), 2);
+    assert_eq!(summary["entity_2"], vec![String::from("id1").as_bytes()]);
+    assert_eq!(summary["entity_3"], vec![0u32.as_bytes()]);
+    e1.remove_relation(&e2, &db)?;
+    assert!(!e1.related_summary(&db)?.contains_key("entity_2"));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_json_backed_storage() -> Result<()> {
+    let name = get_random_name();
+    {
+        let db = set_up(&name)?;
+        set_up_content(&db)?;
+        assert!(crate::dump_store_json("entity_1", &db).is_err());
+    }
+    assert!(crate::open_json_backed(std::env::temp_dir().join(&name)).is_err());
+    tear_down(&name)?;
+
+    let db = crate::open_json_backed(std::env::temp_dir().join(&name))?;
+    assert_eq!(StorageFormat::of(&db)?, StorageFormat::Json);
+    Entity1::register(&db)?;
+    Token::<Session>::register(&db)?;
+    Entity1 {
+        id: 4,
+        prop1: String::from("Hello, JSON!"),
+    }
+    .save(&db)?;
+    Token::<Bearer> {
+        id: String::from("b"),
+        value: String::from("bearer"),
+        kind: PhantomData,
+    }
+    .save(&db)?;
+    assert_eq!(Entity1::get(&4, &db)?.unwrap().prop1, "Hello, JSON!");
+    let values = crate::dump_store_json("entity_1", &db)?;
+    assert_eq!(values.len(), 1);
+    assert_eq!(values[0]["prop1"], "Hello, JSON!");
+    assert_eq!(crate::dump_store_json("token", &db)?[0]["value"], "bearer");
+    drop(db);
+    let db = reopen(&name, |path| Ok(crate::open(path)?))?;
+    assert_eq!(Entity1::get(&4, &db)?.unwrap().prop1, "Hello, JSON!");
+    drop(db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_save_async_and_durable() -> Result<()> {
+    let name = get_random_name();
+    {
+        let db = set_up(&name)?;
+        Entity1 {
+            id: 10,
+            prop1: String::from("async"),
+        }
+        .save_async(&db)?;
+        Entity1 {
+            id: 11,
+            prop1: String::from("durable"),
+        }
+        .save_durable(&db)?;
+        assert_eq!(Entity1::get(&10, &db)?.unwrap().prop1, "async");
+    }
+    let db = reopen(&name, |path| Ok(crate::open(path)?))?;
+    assert_eq!(Entity1::get(&11, &db)?.unwrap().prop1, "durable");
+    drop(db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_relation_exists_between() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&1, &db)?.unwrap();
+    let e2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    e1.create_relation(
+        &e2,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    let key1 = 1u32.as_bytes();
+    let key2 = AsBytes::as_bytes(e2.get_key());
+    assert!(Relation::exists_between(
+        "entity_1", &key1, "entity_2", &key2, &db
+    )?);
+    assert!(Relation::exists_between(
+        "entity_2", &key2, "entity_1", &key1, &db
+    )?);
+    assert!(!Relation::exists_between(
+        "entity_1", &key1, "entity_2", b"id1", &db
+    )?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_related_iter() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    for e2 in Entity2::get_all(&db)? {
+        e1.create_relation(
+            &e2,
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            None,
+            &db,
+        )?;
+    }
+    let first_two = e1
+        .related_iter::<Entity2>(&db)?
+        .take(2)
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(first_two.len(), 2);
+    assert_eq!(first_two[0].id, "id1");
+    let found = e1
+        .related_iter::<Entity2>(&db)?
+        .find(|e2| matches!(e2, Ok(e2) if e2.id == "id3"));
+    assert!(found.is_some());
+    assert_eq!(e1.related_iter::<Entity3>(&db)?.count(), 0);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_size_on_disk() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    assert_eq!(Entity3::size_on_disk(&db)?, 0);
+    set_up_content(&db)?;
+    // 3 entities with a 4 bytes key and a 4 bytes value
+    assert_eq!(Entity3::size_on_disk(&db)?, 24);
+    let total = crate::total_size(&db)?;
+    assert!(total > Entity1::size_on_disk(&db)? + Entity3::size_on_disk(&db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_remove_with_limit() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    // id3 -> child_entity_1 -> grand_child_entity
+    assert!(Entity2::remove_with_limit(&String::from("id3"), 1, &db).is_err());
+    assert!(Entity2::exists(&String::from("id3"), &db)?);
+    assert_eq!(ChildEntity1::get_count(&db)?, 3);
+    assert_eq!(GrandChildEntity::get_count(&db)?, 3);
+    Entity2::remove_with_limit(&String::from("id3"), 2, &db)?;
+    assert!(!Entity2::exists(&String::from("id3"), &db)?);
+    assert_eq!(ChildEntity1::get_count(&db)?, 0);
+    assert_eq!(GrandChildEntity::get_count(&db)?, 0);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_remove_with_limit_shortest_path() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let entities = (100..104)
+        .map(|id| Entity1 {
+            id,
+            prop1: String::from("chain"),
+        })
+        .collect::<Vec<_>>();
+    Entity1::save_batch(&entities, &db)?;
+    // 100 -> 101 -> 102 -> 103, with a shortcut from 100 to 102 declared last
+    for (from, to) in [(0, 1), (0, 2), (1, 2), (2, 3)] {
+        entities[from].create_relation(
+            &entities[to],
+            DeletionBehaviour::Cascade,
+            DeletionBehaviour::BreakLink,
+            None,
+            &db,
+        )?;
+    }
+    assert!(Entity1::remove_with_limit(&100, 1, &db).is_err());
+    assert_eq!(Entity1::get_count(&db)?, 4);
+    Entity1::remove_with_limit(&100, 2, &db)?;
+    assert_eq!(Entity1::get_count(&db)?, 0);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_set_relations() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    let e2s = Entity2::get_all(&db)?;
+    let other = Entity2::get(&String::from("id3"), &db)?.unwrap();
+    e1.create_relation(
+        &other,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        Some("other"),
+        &db,
+    )?;
+    let b = DeletionBehaviour::BreakLink;
+    assert_eq!(
+        e1.set_relations(&e2s[0..2], b, b, Some("roles"), &db)?,
+        (2, 0)
+    );
+    assert_eq!(
+        e1.set_relations(&e2s[1..3], b, b, Some("roles"), &db)?,
+        (1, 1)
+    );
+    assert_eq!(
+        e1.set_relations(&e2s[1..3], b, b, Some("roles"), &db)?,
+        (0, 0)
+    );
+    let roles = e1.get_related_with_name::<Entity2>("roles", &db)?;
+    assert_eq!(
+        roles.iter().map(|e2| e2.id.as_str()).collect::<Vec<_>>(),
+        vec!["id2", "id3"]
+    );
+    assert!(!e2s[0].is_related_to(&e1, &db)?);
+    assert!(e2s[1].is_related_to_with_name(&e1, "roles", &db)?);
+    assert!(e1.is_related_to_with_name(&other, "other", &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_for_each_chunk() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let mut sizes = Vec::new();
+    Entity1::for_each_chunk(
+        2,
+        |chunk| {
+            sizes.push(chunk.len());
+            Ok(())
+        },
+        &db,
+    )?;
+    assert_eq!(sizes, vec![2, 1]);
+    let mut calls = 0;
+    let result = Entity1::for_each_chunk(
+        1,
+        |_| {
+            calls += 1;
+            Err(crate::Error::new(
+                crate::ErrorKind::IOError,
+                String::from("stop"),
+            ))
+        },
+        &db,
+    );
+    assert!(result.is_err());
+    assert_eq!(calls, 1);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_replace_all() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    e1.create_relation(
+        &e2,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    let items = vec![
+        Entity1 {
+            id: 1,
+            prop1: String::from("Replaced"),
+        },
+        Entity1 {
+            id: 5,
+            prop1: String::from("New"),
+        },
+    ];
+    assert!(Entity1::replace_all(&items, DeletionBehaviour::Error, &db).is_err());
+    assert_eq!(Entity1::get_count(&db)?, 3);
+    Entity1::replace_all(&items, DeletionBehaviour::BreakLink, &db)?;
+    let all = Entity1::get_all(&db)?;
+    assert_eq!(
+        all.iter()
+            .map(|e| (e.id, e.prop1.as_str()))
+            .collect::<Vec<_>>(),
+        vec![(1, "Replaced"), (5, "New")]
+    );
+    assert!(e2.get_related::<Entity1>(&db)?.is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_replace_all_breaks_every_link() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1_0 = Entity1::get(&0, &db)?.unwrap();
+    let e1_2 = Entity1::get(&2, &db)?.unwrap();
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    for (from, to) in [(&e1_0, &e2), (&e1_2, &e2)] {
+        from.create_relation(
+            to,
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            None,
+            &db,
+        )?;
+    }
+    e1_0.create_relation(
+        &e1_2,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    let kept = Entity1::get(&1, &db)?.unwrap();
+    Entity1::replace_all(&[kept], DeletionBehaviour::BreakLink, &db)?;
+    assert!(e2.get_related::<Entity1>(&db)?.is_empty());
+    assert!(
+        Relation::get_descriptor_with_key_and_tree_name("entity_1", &0u32.as_bytes(), &db)?
+            .related_entities
+            .is_empty()
+    );
+    assert!(
+        Relation::get_descriptor_with_key_and_tree_name("entity_1", &2u32.as_bytes(), &db)?
+            .related_entities
+            .is_empty()
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_float_keys() -> Result<()> {
+    let values = [
+        f64::NEG_INFINITY,
+        -1e10,
+        -1.5,
+        -f64::MIN_POSITIVE,
+        -0.0,
+        0.0,
+        f64::MIN_POSITIVE,
+        1.5,
+        1e10,
+        f64::INFINITY,
+        f64::NAN,
+    ];
+    for pair in values.windows(2) {
+        assert!(pair[0].as_bytes() < pair[1].as_bytes());
+    }
+    for value in values {
+        let decoded = f64::from_bytes(&value.as_bytes())?;
+        assert!(decoded.to_bits() == value.to_bits() || (value.is_nan() && decoded.is_nan()));
+    }
+    assert_eq!((-f64::NAN).as_bytes(), f64::NAN.as_bytes());
+    assert!((-2.5f32).as_bytes() < 0.5f32.as_bytes());
+    assert_eq!(f32::from_bytes(&(-2.5f32).as_bytes())?, -2.5);
+    assert!(f32::from_bytes(&[0, 1]).is_err());
+
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Sample::register(&db)?;
+    for (value, time) in [3.5, -2.0, 0.25, -10.0, 7.0].into_iter().enumerate() {
+        Sample {
+            time,
+            value: value as u32,
+        }
+        .save(&db)?;
+    }
+    let in_range = Sample::get_in_range(-5.0, 5.0, &db)?;
+    assert_eq!(
+        in_range.iter().map(|s| s.time).collect::<Vec<_>>(),
+        vec![-2.0, 0.25, 3.5]
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_update_relation() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let b = DeletionBehaviour::BreakLink;
+    assert!(matches!(
+        e1.update_relation(&e2, b, b, None, &db),
+        Err(e) if matches!(e.kind(), crate::ErrorKind::NotFound)
+    ));
+    e1.create_relation(&e2, b, b, Some("old"), &db)?;
+    e1.update_relation(&e2, DeletionBehaviour::Cascade, b, Some("new"), &db)?;
+    assert!(e1.is_related_to_with_name(&e2, "new", &db)?);
+    assert!(e2.is_related_to_with_name(&e1, "new", &db)?);
+    assert!(!e1.is_related_to_with_name(&e2, "old", &db)?);
+    Entity1::remove(&0, &db)?;
+    assert!(!Entity2::exists(&String::from("id1"), &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_reindeer_handle() -> Result<()> {
+    let name = get_random_name();
+    let db = Reindeer::builder(std::env::temp_dir().join(&name))
+        .register::<Entity1>()
+        .register::<Entity3>()
+        .open()?;
+    assert!(FamilyDescriptor::exists(&String::from("entity_1"), &db)?);
+    assert!(!FamilyDescriptor::exists(&String::from("entity_2"), &db)?);
+    db.save(&Entity1 {
+        id: 1,
+        prop1: String::from("Handle"),
+    })?;
+    assert!(db.exists::<Entity1>(&1)?);
+    assert_eq!(db.get::<Entity1>(&1)?.unwrap().prop1, "Handle");
+    assert_eq!(db.get_all::<Entity1>()?.len(), 1);
+    db.remove::<Entity1>(&1)?;
+    assert!(Entity1::get(&1, &db)?.is_none());
+    drop(db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_to_json_with_relations() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    let b = DeletionBehaviour::BreakLink;
+    for id in ["id1", "id2"] {
+        let e2 = Entity2::get(&String::from(id), &db)?.unwrap();
+        e1.create_relation(&e2, b, b, Some(id), &db)?;
+    }
+    let json = e1.to_json_with_relations::<Entity2>(None, &db)?;
+    assert_eq!(json["self"]["prop1"], "Hello, World!");
+    assert_eq!(json["related"].as_array().unwrap().len(), 2);
+    let json = e1.to_json_with_relations::<Entity2>(Some("id2"), &db)?;
+    assert_eq!(json["related"].as_array().unwrap().len(), 1);
+    assert_eq!(json["related"][0]["id"], "id2");
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_scan_while() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let first = Entity1::scan_while(|e| e.id < 2, &db)?;
+    assert_eq!(first.iter().map(|e| e.id).collect::<Vec<_>>(), vec![0, 1]);
+    assert!(Entity1::scan_while(|e| e.id > 0, &db)?.is_empty());
+    assert_eq!(Entity1::scan_while(|_| true, &db)?.len(), 3);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[cfg(feature = "lazy-migration")]
+#[test]
+fn test_lazy_migration() -> Result<()> {
+    use test_entities::Versioned;
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Versioned::register(&db)?;
+    // a record saved before the struct had an email
+    let old = bincode::serialize(&(5u32, String::from("Bob")))?;
+    Versioned::get_tree(&db)?.insert(5u32.as_bytes(), old)?;
+    let migrated = Versioned::get(&5, &db)?.unwrap();
+    assert_eq!(migrated.name, "Bob");
+    assert_eq!(migrated.email, "unknown");
+    assert_eq!(
+        crate::migration::stored_version("versioned", &5u32.as_bytes(), &db)?,
+        1
+    );
+    let stored = Versioned::get_tree(&db)?.get(5u32.as_bytes())?.unwrap();
+    assert_eq!(stored.as_ref(), bincode::serialize(&migrated)?.as_slice());
+    assert_eq!(Versioned::get(&5, &db)?.unwrap().email, "unknown");
+    Versioned::remove(&5, &db)?;
+    assert_eq!(
+        crate::migration::stored_version("versioned", &5u32.as_bytes(), &db)?,
+        0
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_empty_keys_are_rejected() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    // an empty key is a prefix of every key, but only owns the children whose key is a bare index
+    assert!(ChildEntity1::get_with_prefix(&String::new(), &db)?.is_empty());
+    let empty = Entity2 {
+        id: String::new(),
+        prop2: 0,
+    };
+    assert!(matches!(
+        empty.save(&db),
+        Err(e) if matches!(e.kind(), crate::ErrorKind::IntegrityError)
+    ));
+    assert!(!Entity2::exists(&String::new(), &db)?);
+    assert!(Entity2::replace_all(&[empty], DeletionBehaviour::BreakLink, &db).is_err());
+    assert_eq!(Entity2::get_count(&db)?, 3);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_rename_relation_name() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let b = DeletionBehaviour::BreakLink;
+    let e1_0 = Entity1::get(&0, &db)?.unwrap();
+    let e1_1 = Entity1::get(&1, &db)?.unwrap();
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e2_2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    e1_0.create_relation(&e2_1, b, b, Some("owner"), &db)?;
+    e1_0.create_relation(&e2_2, b, b, Some("reader"), &db)?;
+    e1_1.create_relation(&e2_1, b, b, Some("owner"), &db)?;
+    assert_eq!(e1_0.rename_relation_name("owner", "admin", &db)?, 1);
+    assert!(e1_0.is_related_to_with_name(&e2_1, "admin", &db)?);
+    assert!(e2_1.is_related_to_with_name(&e1_0, "admin", &db)?);
+    assert!(e2_1.is_related_to_with_name(&e1_1, "owner", &db)?);
+    assert!(e1_0.is_related_to_with_name(&e2_2, "reader", &db)?);
+    assert_eq!(
+        crate::rename_relation_name_global("entity_1", "owner", "admin", &db)?,
+        1
+    );
+    assert!(e2_1.is_related_to_with_name(&e1_1, "admin", &db)?);
+    assert!(!e1_1.is_related_to_with_name(&e2_1, "owner", &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_deserialization_error_context() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    Entity1::get_tree(&db)?.insert(7u32.as_bytes(), &[1, 2, 3])?;
+    let error = Entity1::get(&7, &db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::SerializationError));
+    let message = error.to_string();
+    assert!(message.contains("Entity1"));
+    assert!(message.contains("3 bytes"));
+    assert!(message.contains("entity_1"));
+    assert!(message.contains("[0, 0, 0, 7]"));
+    assert!(Entity1::get_all(&db).is_err());
+    assert!(Entity1::update(&7, |e| e.prop1.clear(), &db).is_err());
+    assert!(Entity1::get(&0, &db)?.is_some());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_open_read_only() -> Result<()> {
+    let name = get_random_name();
+    {
+        let db = set_up(&name)?;
+        set_up_content(&db)?;
+    }
+    let db = reopen(&name, crate::open_read_only)?;
+    assert_eq!(db.get::<Entity1>(&1)?.unwrap().prop1, "Hello, Nancy!");
+    assert_eq!(db.get_all::<Entity2>()?.len(), 3);
+    assert_eq!(db.get_count::<Entity3>()?, 3);
+    assert!(db.exists::<Entity1>(&2)?);
+    let error = db
+        .save(&Entity1 {
+            id: 8,
+            prop1: String::new(),
+        })
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IOError));
+    assert!(db.remove::<Entity1>(&1).is_err());
+    assert!(!db.exists::<Entity1>(&8)?);
+    assert!(db.exists::<Entity1>(&1)?);
+    drop(db);
+    tear_down(&name)?;
+    let missing = std::env::temp_dir().join(get_random_name());
+    let error = crate::open_read_only(&missing).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::NotFound));
+    assert!(!missing.exists());
+    Ok(())
+}
+
+#[test]
+fn test_paginate() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let first = Entity1::paginate(0, 2, &db)?;
+    assert_eq!(first.items.len(), 2);
+    assert_eq!(first.total, 3);
+    assert!(first.has_next);
+    assert!(!first.has_prev);
+    let second = Entity1::paginate(1, 2, &db)?;
+    assert_eq!(second.items.len(), 1);
+    assert_eq!(second.items[0].prop1, "Hello, Jack!");
+    assert!(!second.has_next);
+    assert!(second.has_prev);
+    let beyond = Entity1::paginate(usize::MAX, 2, &db)?;
+    assert!(beyond.items.is_empty());
+    assert!(!beyond.has_next);
+    assert!(Entity1::get_from_start(usize::MAX, 2, None::<u32>, &db)?.is_empty());
+    assert!(Entity1::get_from_end(usize::MAX, 2, None::<u32>, &db)?.is_empty());
+    let filtered = Entity2::paginate_with_filter(|e| e.prop2 > 4, 0, 1, &db)?;
+    assert_eq!(filtered.total, 2);
+    assert_eq!(filtered.items[0].prop2, 5);
+    assert!(filtered.has_next);
+    let error = Entity1::paginate(0, 0, &db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    let error = Entity2::paginate_with_filter(|_| true, 0, 0, &db)
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_in_range_bounds() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    for id in 0..10 {
+        Entity1 {
+            id,
+            prop1: id.to_string(),
+        }
+        .save(&db)?;
+    }
+    assert_eq!(Entity1::get_in_range(2, 5, &db)?.len(), 3);
+    assert_eq!(Entity1::get_in_range_bounds(2..=5, &db)?.len(), 4);
+    assert_eq!(Entity1::get_in_range_bounds(7.., &db)?.len(), 3);
+    assert_eq!(Entity1::get_in_range_bounds(..=0, &db)?.len(), 1);
+    assert_eq!(Entity1::get_in_range_bounds::<u32>(.., &db)?.len(), 10);
+    let excluded = Entity1::get_in_range_bounds((Bound::Excluded(2), Bound::Included(4)), &db)?;
+    assert_eq!(
+        excluded.iter().map(|e| e.id).collect::<Vec<_>>(),
+        vec![3, 4]
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_related_range_scan() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let e2 = Entity2 {
+        id: String::from("id1"),
+        prop2: 3,
+    };
+    e2.save(&db)?;
+    for id in 0..10 {
+        let e1 = Entity1 {
+            id,
+            prop1: id.to_string(),
+        };
+        e1.save(&db)?;
+        if [8, 1, 3, 2].contains(&id) {
+            e2.create_relation(
+                &e1,
+                DeletionBehaviour::BreakLink,
+                DeletionBehaviour::BreakLink,
+                None,
+                &db,
+            )?;
+        }
+    }
+    let keys = Relation::get::<Entity2, Entity1>(&e2, &db)?
+        .iter()
+        .map(|e| e.id)
+        .collect::<Vec<_>>();
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(sorted, vec![1, 2, 3, 8]);
+    let found = Entity1::get_each_in_range(
+        &keys.iter().map(|key| key.as_bytes()).collect::<Vec<_>>(),
+        &db,
+    )?
+    .unwrap();
+    assert_eq!(found.len(), 4);
+    assert!(Entity1::get_each_in_range(&[1.as_bytes(), 9.as_bytes()], &db)?.is_none());
+    assert_eq!(
+        Entity1::get_each_u8(&[9.as_bytes(), 1.as_bytes(), 42.as_bytes()], &db)?
+            .iter()
+            .map(|e| e.id)
+            .collect::<Vec<_>>(),
+        vec![9, 1]
+    );
+    Entity1::get_tree(&db)?.insert(2.as_bytes(), vec![0xff])?;
+    let keys = [1.as_bytes(), 2.as_bytes(), 3.as_bytes()];
+    assert!(Entity1::get_each_in_range(&keys, &db).is_err());
+    assert!(Entity1::get_each_u8(&keys, &db).is_err());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_touch() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Heartbeat::register(&db)?;
+    Heartbeat {
+        id: 1,
+        status: String::from("online"),
+        updated_at: 0,
+    }
+    .save(&db)?;
+    Heartbeat::touch(&1, &db)?;
+    let heartbeat = Heartbeat::get(&1, &db)?.unwrap();
+    assert_eq!(heartbeat.updated_at, 1_000);
+    assert_eq!(heartbeat.status, "online");
+    let error = Heartbeat::touch(&2, &db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::NotFound));
+    assert!(!Heartbeat::exists(&2, &db)?);
+    // touching concurrently with other updates does not lose them
+    std::thread::scope(|scope| {
+        let toucher = scope.spawn(|| -> Result<()> {
+            for _ in 0..200 {
+                Heartbeat::touch(&1, &db)?;
+            }
+            Ok(())
+        });
+        for _ in 0..200 {
+            Heartbeat::update(&1, |heartbeat| heartbeat.status.push('!'), &db)?;
+        }
+        toucher.join().unwrap()
+    })?;
+    assert_eq!(Heartbeat::get(&1, &db)?.unwrap().status.len(), 206);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_mutual_cascade_siblings() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let mutual = [("entity_1", DeletionBehaviour::Cascade)];
+    for id in [5, 6] {
+        Entity1 {
+            id,
+            prop1: String::from("sibling"),
+        }
+        .save(&db)?;
+        Entity3 { id }.save(&db)?;
+    }
+    let plan = Relation::deletion_plan("entity_3", &5.as_bytes(), &mutual, &db)?;
+    assert_eq!(plan.len(), 2);
+    assert_eq!(plan.last().unwrap().0, "entity_3");
+    Entity3::remove_with_override(&5, &mutual, &db)?;
+    assert!(Entity1::get(&5, &db)?.is_none());
+    assert!(Entity3::get(&5, &db)?.is_none());
+    let plan = Relation::deletion_plan("entity_1", &6.as_bytes(), &mutual, &db)?;
+    assert_eq!(plan.len(), 2);
+    Entity1::remove_with_override(&6, &mutual, &db)?;
+    assert!(Entity1::get(&6, &db)?.is_none());
+    assert!(Entity3::get(&6, &db)?.is_none());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_reserved_store_names() -> Result<()> {
+    let name = get_random_name();
+    let db = crate::OpenConfig::new(std::env::temp_dir().join(&name))
+        .internal_prefix("__$")
+        .open()?;
+    let error = Intruder::register(&db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    Entity1::register(&db)?;
+    assert!(crate::OpenConfig::new(std::env::temp_dir().join(&name))
+        .internal_prefix("")
+        .open()
+        .is_err());
+    assert!(crate::OpenConfig::new(std::env::temp_dir().join(&name))
+        .internal_prefix("__other$")
+        .open()
+        .is_err());
+    drop(db);
+    tear_down(&name)?;
+    // a database created with another prefix
+    let db = reopen(&name, |path| Ok(crate::open(path)?))?;
+    db.open_tree("__other$meta")?
+        .insert("internal_prefix", "__other$")?;
+    let error = Entity1::register(&db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    drop(db);
+    let error = reopen(&name, |path| crate::OpenConfig::new(path).open())
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_all_keys() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    assert_eq!(Entity1::all_keys(&db)?, vec![0, 1, 2]);
+    assert_eq!(Entity2::all_keys(&db)?, vec!["id1", "id2", "id3"]);
+    assert_eq!(
+        ChildEntity1::child_keys_of(&String::from("id3"), &db)?,
+        vec![
+            (String::from("id3"), 0),
+            (String::from("id3"), 1),
+            (String::from("id3"), 2)
+        ]
+    );
+    assert!(ChildEntity1::child_keys_of(&String::from("id1"), &db)?.is_empty());
+    assert_eq!(
+        <((String, u32), u32)>::from_bytes(&((String::from("id"), 1), 2).as_bytes())?,
+        ((String::from("id"), 1), 2)
+    );
+    assert!(<(String, String)>::from_bytes(b"ab").is_err());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_import_iter() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let mut progress = Vec::new();
+    let count = Entity1::import_iter(
+        (0..25).map(|id| Entity1 {
+            id,
+            prop1: format!("imported {}", id),
+        }),
+        10,
+        |count| progress.push(count),
+        &db,
+    )?;
+    assert_eq!(count, 25);
+    assert_eq!(progress, vec![10, 20, 25]);
+    assert_eq!(Entity1::get_count(&db)?, 25);
+    assert_eq!(Entity1::get(&24, &db)?.unwrap().prop1, "imported 24");
+    assert_eq!(Entity1::import_iter(std::iter::empty(), 0, |_| {}, &db)?, 0);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_related_keys() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    for id in [2, 0] {
+        e2.create_relation(
+            &Entity1::get(&id, &db)?.unwrap(),
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            None,
+            &db,
+        )?;
+    }
+    assert_eq!(e2.get_related_keys::<Entity1>(&db)?, vec![0, 2]);
+    assert!(e2.get_related_keys::<Entity3>(&db)?.is_empty());
+    Relation::create_link_with_keys_and_tree_names(
+        "entity_2",
+        b"id1",
+        "entity_1",
+        &[1, 2],
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    let error = e2.get_related_keys::<Entity1>(&db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_adopt_child_keep_id() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e2_3 = Entity2::get(&String::from("id3"), &db)?.unwrap();
+    let mut child = ChildEntity1::get(&(String::from("id3"), 1), &db)?.unwrap();
+    e2_1.adopt_child_keep_id(&mut child, &db)?;
+    assert_eq!(child.id, (String::from("id1"), 1));
+    assert!(ChildEntity1::get(&(String::from("id3"), 1), &db)?.is_none());
+    assert!(ChildEntity1::exists(&(String::from("id1"), 1), &db)?);
+    let mut other = ChildEntity1::get(&(String::from("id3"), 2), &db)?.unwrap();
+    e2_1.adopt_child_keep_id(&mut other, &db)?;
+    let mut back = ChildEntity1::get(&(String::from("id1"), 1), &db)?.unwrap();
+    e2_3.save_child(
+        &mut ChildEntity1 {
+            id: (String::new(), 1),
+        },
+        &db,
+    )?;
+    let error = e2_3.adopt_child_keep_id(&mut back, &db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    assert_eq!(back.id, (String::from("id1"), 1));
+    assert!(ChildEntity1::exists(&(String::from("id1"), 1), &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_relation_builder() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let e1 = Entity1 {
+        id: 5,
+        prop1: String::from("owned"),
+    };
+    let e2 = Entity2 {
+        id: String::from("owner"),
+        prop2: 1,
+    };
+    let e3 = Entity3 { id: 7 };
+    e1.save(&db)?;
+    e2.save(&db)?;
+    e3.save(&db)?;
+    e2.relate_to(&e1)
+        .on_self_delete(DeletionBehaviour::Cascade)
+        .on_other_delete(DeletionBehaviour::Error)
+        .named("owner")
+        .commit(&db)?;
+    e2.relate_to(&e3).commit(&db)?;
+    assert_eq!(e2.get_related_with_name::<Entity1>("owner", &db)?.len(), 1);
+    assert!(Entity1::remove(&5, &db).is_err());
+    Entity3::remove(&7, &db)?;
+    assert!(e2.get_related::<Entity3>(&db)?.is_empty());
+    Entity2::remove(&String::from("owner"), &db)?;
+    assert!(Entity1::get(&5, &db)?.is_none());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_strict_keys() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Heartbeat::register(&db)?;
+    Heartbeat {
+        id: 1,
+        status: String::from("online"),
+        updated_at: 0,
+    }
+    .save(&db)?;
+    let error = Heartbeat::update(&1, |h| h.id = 2, &db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    assert_eq!(Heartbeat::get(&1, &db)?.unwrap().id, 1);
+    assert!(Heartbeat::filter_update(|_| true, |h| h.id = 3, &db).is_err());
+    assert!(!Heartbeat::exists(&3, &db)?);
+    Heartbeat::update(&1, |h| h.status = String::from("away"), &db)?;
+    assert_eq!(Heartbeat::get(&1, &db)?.unwrap().status, "away");
+    Entity1 {
+        id: 1,
+        prop1: String::from("lenient"),
+    }
+    .save(&db)?;
+    Entity1::update(&1, |e| e.id = 2, &db)?;
+    assert!(Entity1::get(&1, &db)?.is_none());
+    assert_eq!(Entity1::get(&2, &db)?.unwrap().prop1, "lenient");
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_update_key_change() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    let e2 = Entity2::get(&String::from("id3"), &db)?.unwrap();
+    e2.relate_to(&e1).commit(&db)?;
+    Entity2::update(&String::from("id3"), |e| e.id = String::from("id9"), &db)?;
+    assert!(Entity2::get(&String::from("id3"), &db)?.is_none());
+    let moved = Entity2::get(&String::from("id9"), &db)?.unwrap();
+    assert_eq!(moved.prop2, 1000);
+    assert_eq!(moved.get_related_keys::<Entity1>(&db)?, vec![0]);
+    assert_eq!(e1.get_related_keys::<Entity2>(&db)?, vec!["id9"]);
+    assert_eq!(
+        ChildEntity1::child_keys_of(&String::from("id9"), &db)?.len(),
+        3
+    );
+    assert!(ChildEntity1::child_keys_of(&String::from("id3"), &db)?.is_empty());
+    let error = Entity2::update(&String::from("id1"), |e| e.id = String::from("id2"), &db)
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    assert_eq!(Entity2::get(&String::from("id1"), &db)?.unwrap().prop2, 3);
+    assert_eq!(Entity2::get(&String::from("id2"), &db)?.unwrap().prop2, 5);
+    Entity1::update(
+        &2,
+        |e| {
+            e.id = 12;
+            e.prop1 = String::from("Moved");
+        },
+        &db,
+    )?;
+    assert!(Entity1::get(&2, &db)?.is_none());
+    assert_eq!(Entity1::get(&12, &db)?.unwrap().prop1, "Moved");
+    assert!(Entity3::get(&2, &db)?.is_none());
+    assert!(Entity3::get(&12, &db)?.is_some());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_or_default() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Heartbeat::register(&db)?;
+    let heartbeat = Heartbeat::get_or_default(&4, &db)?;
+    assert_eq!(heartbeat.id, 4);
+    assert_eq!(heartbeat.status, "");
+    assert!(!Heartbeat::exists(&4, &db)?);
+    Heartbeat {
+        id: 4,
+        status: String::from("online"),
+        updated_at: 0,
+    }
+    .save(&db)?;
+    assert_eq!(Heartbeat::get_or_default(&4, &db)?.status, "online");
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_backup_and_restore() -> Result<()> {
+    let name = get_random_name();
+    let restored_name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    e2.relate_to(&Entity1::get(&0, &db)?.unwrap())
+        .on_self_delete(DeletionBehaviour::Cascade)
+        .commit(&db)?;
+    let path = std::env::temp_dir().join(format!("{}.bak", name));
+    crate::backup(&db, &path)?;
+    let restored = crate::restore(&path, std::env::temp_dir().join(&restored_name))?;
+    assert_eq!(Entity1::get_count(&restored)?, 3);
+    assert_eq!(ChildEntity1::get_count(&restored)?, 3);
+    assert_eq!(e2.get_related_keys::<Entity1>(&restored)?, vec![0]);
+    assert_eq!(restored.checksum()?, db.checksum()?);
+    drop(restored);
+    assert!(reopen(&restored_name, |db_path| crate::restore(&path, db_path)).is_err());
+    // malformed backups are rejected without creating the database
+    let malformed_name = get_random_name();
+    let malformed = vec![(
+        b"tree".to_vec(),
+        b"entity_1".to_vec(),
+        vec![vec![vec![1u8]]],
+    )];
+    std::fs::write(&path, bincode::serialize(&malformed)?)?;
+    let error = crate::restore(&path, std::env::temp_dir().join(&malformed_name))
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::SerializationError));
+    std::fs::write(&path, [0xff; 16])?;
+    let error = crate::restore(&path, std::env::temp_dir().join(&malformed_name))
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::SerializationError));
+    assert!(!std::env::temp_dir().join(&malformed_name).exists());
+    std::fs::remove_file(&path)?;
+    tear_down(&restored_name)?;
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_related_filtered() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    for e2 in Entity2::get_all(&db)? {
+        e1.relate_to(&e2).commit(&db)?;
+    }
+    let filtered = e1.get_related_filtered::<Entity2, _>(|e2| e2.prop2 > 4, &db)?;
+    assert_eq!(
+        filtered.iter().map(|e2| e2.prop2).collect::<Vec<_>>(),
+        vec![5, 1000]
+    );
+    assert!(e1
+        .get_related_filtered::<Entity3, _>(|_| true, &db)?
+        .is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_join() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let joined = Entity2::join::<Entity1, _>(|e2| e2.prop2 % 4, &db)?;
+    assert_eq!(
+        joined
+            .iter()
+            .map(|(e2, e1)| (e2.prop2, e1.as_ref().map(|e1| e1.id)))
+            .collect::<Vec<_>>(),
+        vec![(3, None), (5, Some(1)), (1000, Some(0))]
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_registration_check() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    // the check applies to the whole process : every test registers the entities it reads
+    crate::set_registration_check(crate::RegistrationCheck::Warn);
+    let warned = Sample::get_all(&db);
+    crate::set_registration_check(crate::RegistrationCheck::Error);
+    let error = Sample::get_all(&db).err();
+    let registered = Entity1::get_all(&db);
+    crate::set_registration_check(crate::RegistrationCheck::Lenient);
+    assert!(warned?.is_empty());
+    assert!(matches!(
+        error.unwrap().kind(),
+        crate::ErrorKind::UnregisteredEntity
+    ));
+    assert!(registered?.is_empty());
+    assert!(Sample::get_all(&db)?.is_empty());
+    Sample::register(&db)?;
+    Sample::check_registered(crate::RegistrationCheck::Error, &db)?;
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_sample() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    for id in 0..100 {
+        Entity1 {
+            id,
+            prop1: id.to_string(),
+        }
+        .save(&db)?;
+    }
+    let sample = Entity1::sample(10, &db)?;
+    assert_eq!(sample.len(), 10);
+    let mut ids = sample.iter().map(|e| e.id).collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 10);
+    let ids_of = |sample: Vec<Entity1>| sample.into_iter().map(|e| e.id).collect::<Vec<_>>();
+    assert_eq!(
+        ids_of(Entity1::sample_with_seed(5, 42, &db)?),
+        ids_of(Entity1::sample_with_seed(5, 42, &db)?)
+    );
+    assert_eq!(Entity1::sample(200, &db)?.len(), 100);
+    assert!(Entity3::sample(3, &db)?.is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_custom_key_encoding() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Tag::register(&db)?;
+    Tag {
+        name: String::from("Rust"),
+    }
+    .save(&db)?;
+    assert_eq!(Tag::get(&String::from("rUST"), &db)?.unwrap().name, "Rust");
+    assert!(Tag::exists(&String::from("RUST"), &db)?);
+    assert_eq!(Tag::all_keys(&db)?, vec!["rust"]);
+    let e1 = Entity1 {
+        id: 3,
+        prop1: String::from("tagged"),
+    };
+    e1.save(&db)?;
+    let tag = Tag::get(&String::from("rust"), &db)?.unwrap();
+    tag.relate_to(&e1).commit(&db)?;
+    assert_eq!(e1.get_related_keys::<Tag>(&db)?, vec!["rust"]);
+    Tag::remove(&String::from("RUST"), &db)?;
+    assert_eq!(Tag::get_count(&db)?, 0);
+    assert!(e1.get_related_keys::<Tag>(&db)?.is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_exists_and_remove_sibling() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let mut e1 = Entity1 {
+        id: 0,
+        prop1: String::from("Lonely"),
+    };
+    e1.save_next(&db)?;
+    assert!(!e1.exists_sibling::<Entity3>(&db)?);
+    e1.save_sibling(&mut Entity3 { id: 0 }, &db)?;
+    assert!(e1.exists_sibling::<Entity3>(&db)?);
+    assert!(e1.remove_sibling::<Entity3>(&db).is_err());
+    assert!(e1.exists_sibling::<Entity3>(&db)?);
+    let e3 = e1.get_sibling::<Entity3>(&db)?.unwrap();
+    e3.remove_sibling::<Entity1>(&db)?;
+    assert!(!e3.exists_sibling::<Entity1>(&db)?);
+    assert!(!e1.exists_sibling::<Entity3>(&db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_remove_relations_by_name() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let b = DeletionBehaviour::BreakLink;
+    let e1_0 = Entity1::get(&0, &db)?.unwrap();
+    let e1_1 = Entity1::get(&1, &db)?.unwrap();
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e2_2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    e1_0.create_relation(&e2_1, b, b, Some("draft"), &db)?;
+    e1_0.create_relation(&e2_2, b, b, Some("published"), &db)?;
+    e1_1.create_relation(&e2_1, b, b, Some("draft"), &db)?;
+    assert_eq!(
+        crate::remove_relations_by_name("entity_1", "draft", &db)?,
+        2
+    );
+    assert!(!e1_0.is_related_to_with_name(&e2_1, "draft", &db)?);
+    assert!(!e2_1.is_related_to_with_name(&e1_0, "draft", &db)?);
+    assert!(!e2_1.is_related_to_with_name(&e1_1, "draft", &db)?);
+    assert!(e2_2.is_related_to_with_name(&e1_0, "published", &db)?);
+    assert_eq!(
+        crate::remove_relations_by_name("entity_1", "draft", &db)?,
+        0
+    );
+    assert!(Entity2::exists(&String::from("id1"), &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_ordered_relations() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let b = DeletionBehaviour::BreakLink;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    let ids = |entities: Vec<Entity2>| entities.into_iter().map(|e| e.id).collect::<Vec<_>>();
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e2_2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    let e2_3 = Entity2::get(&String::from("id3"), &db)?.unwrap();
+    e1.create_relation_ordered(&e2_3, b, b, Some("tracks"), 0, &db)?;
+    e1.create_relation(&e2_1, b, b, Some("tracks"), &db)?;
+    e1.create_relation_ordered(&e2_2, b, b, Some("tracks"), 5, &db)?;
+    assert_eq!(ids(e1.get_related(&db)?), ["id3", "id2", "id1"]);
+    e1.reorder_relation(&e2_2, -1, &db)?;
+    assert_eq!(
+        ids(e1.get_related_with_name("tracks", &db)?),
+        ["id2", "id3", "id1"]
+    );
+    assert_eq!(e2_2.get_related::<Entity1>(&db)?.len(), 1);
+    let e1_1 = Entity1::get(&1, &db)?.unwrap();
+    assert!(e1_1.reorder_relation(&e2_2, 0, &db).is_err());
+
+    // descriptors written before relations could be ordered are still readable
+    let legacy = vec![(
+        String::from("entity_2"),
+        vec![(b"id1".to_vec(), b, None::<String>)],
+    )];
+    db.open_tree(crate::config::internal_tree_name("rel_entity_1"))?
+        .insert(1u32.as_bytes(), bincode::serialize(&legacy)?)?;
+    assert_eq!(ids(e1_1.get_related(&db)?), ["id1"]);
+    e1_1.create_relation_ordered(&e2_3, b, b, None, 0, &db)?;
+    assert_eq!(ids(e1_1.get_related(&db)?), ["id3", "id1"]);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_has_related_with_name() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let b = DeletionBehaviour::BreakLink;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    assert!(!e1.has_related_with_name::<Entity2>("member", &db)?);
+    e1.create_relation(&e2, b, b, Some("member"), &db)?;
+    assert!(e1.has_related_with_name::<Entity2>("member", &db)?);
+    assert!(e2.has_related_with_name::<Entity1>("member", &db)?);
+    assert!(!e1.has_related_with_name::<Entity2>("admin", &db)?);
+    assert!(!e1.has_related_with_name::<Entity3>("member", &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_key_str() -> Result<()> {
+    assert_eq!(Entity1::key_to_str(&42), "42");
+    assert_eq!(Entity1::key_from_str("42")?, 42);
+    assert!(Entity1::key_from_str("forty-two").is_err());
+    assert_eq!(ChildEntity1::key_to_str(&(String::from("id1"), 3)), "id1:3");
+    assert_eq!(
+        ChildEntity1::key_from_str("id1:3")?,
+        (String::from("id1"), 3)
+    );
+    assert!(ChildEntity1::key_from_str("id1").is_err());
+    assert_eq!(
+        <((String, u32), u32)>::from_key_str("a:b:7:3")?,
+        ((String::from("a:b"), 7), 3)
+    );
+    let key = (String::from("a:b/c d?é%"), 7);
+    assert_eq!(
+        ChildEntity1::key_to_str(&key),
+        "a%3Ab%2Fc%20d%3F%C3%A9%25:7"
+    );
+    assert_eq!(
+        ChildEntity1::key_from_str(&ChildEntity1::key_to_str(&key))?,
+        key
+    );
+    assert_eq!(String::from_key_str("id-1.a_b~")?.to_key_str(), "id-1.a_b~");
+    assert!(String::from_key_str("a%2").is_err());
+    assert!(String::from_key_str("a%zz").is_err());
+    assert!(String::from_key_str("%C3").is_err());
+    let hash = [0xab; 32];
+    assert_eq!(<[u8; 32]>::from_key_str(&hash.to_key_str())?, hash);
+    assert!(<[u8; 2]>::from_key_str("zz00").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_byte_vector_keys() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    Opaque::register(&db)?;
+    OpaquePart::register(&db)?;
+    for id in [vec![0xff, 0x00, 0x10], vec![0x01], vec![0x7f, 0xfe]] {
+        Opaque {
+            id,
+            data: String::from("opaque"),
+        }
+        .save(&db)?;
+    }
+    let opaque = Opaque::get(&vec![0xff, 0x00, 0x10], &db)?.unwrap();
+    assert_eq!(opaque.data, "opaque");
+    assert_eq!(
+        Opaque::all_keys(&db)?,
+        vec![vec![0x01], vec![0x7f, 0xfe], vec![0xff, 0x00, 0x10]]
+    );
+    let in_range = Opaque::get_in_range(vec![0x02], vec![0xff], &db)?;
+    assert_eq!(in_range.len(), 1);
+    assert_eq!(in_range[0].id, vec![0x7f, 0xfe]);
+    opaque.save_next_child(&mut OpaquePart { id: (vec![], 0) }, &db)?;
+    opaque.save_next_child(&mut OpaquePart { id: (vec![], 0) }, &db)?;
+    let parts = opaque.get_children::<OpaquePart>(&db)?;
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[1].id, (vec![0xff, 0x00, 0x10], 1));
+    assert_eq!(
+        OpaquePart::child_keys_of(&vec![0xff, 0x00, 0x10], &db)?,
+        vec![(vec![0xff, 0x00, 0x10], 0), (vec![0xff, 0x00, 0x10], 1)]
+    );
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    opaque.relate_to(&e1).commit(&db)?;
+    assert_eq!(
+        e1.get_related_keys::<Opaque>(&db)?,
+        vec![vec![0xff, 0x00, 0x10]]
+    );
+    assert_eq!(Opaque::key_to_str(opaque.get_key()), "ff0010");
+    assert_eq!(Opaque::key_from_str("ff0010")?, opaque.id);
+    // children of parents whose key starts with another parent's key are not mixed up
+    let parents = [
+        vec![0x01],
+        vec![0x01, 0x00],
+        vec![0x01, 0x00, 0x01],
+        vec![0x01, 0x02],
+    ];
+    for id in &parents {
+        let parent = Opaque {
+            id: id.clone(),
+            data: String::from("parent"),
+        };
+        parent.save(&db)?;
+        parent.save_next_child(&mut OpaquePart { id: (vec![], 0) }, &db)?;
+    }
+    for id in &parents {
+        let keys = OpaquePart::child_keys_of(id, &db)?;
+        assert_eq!(keys.len(), 1);
+        assert_eq!(&keys[0].0, id);
+    }
+    assert_eq!(
+        Opaque::all_keys(&db)?,
+        vec![
+            vec![0x01],
+            vec![0x01, 0x00],
+            vec![0x01, 0x00, 0x01],
+            vec![0x01, 0x02],
+            vec![0x7f, 0xfe],
+            vec![0xff, 0x00, 0x10]
+        ]
+    );
+    Opaque::remove(&vec![0x01], &db)?;
+    assert_eq!(OpaquePart::get_count(&db)?, 5);
+    assert_eq!(
+        Opaque::get_tree(&db)?.first()?.unwrap().0.as_ref(),
+        &[0x01, 0x00]
+    );
+    assert_eq!(Vec::<u8>::from_bytes(&[0x01, 0x00])?, vec![0x01, 0x00]);
+    Opaque::remove(&opaque.id, &db)?;
+    assert_eq!(OpaquePart::get_count(&db)?, 3);
+    assert!(e1.get_related_keys::<Opaque>(&db)?.is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_operation_metrics() -> Result<()> {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    metrics::with_local_recorder(&recorder, || -> Result<()> {
+        set_up_content(&db)?;
+        Entity1::get(&0, &db)?;
+        Entity1::get(&1, &db)?;
+        Entity2::remove(&String::from("id2"), &db)?;
+        let e1 = Entity1::get_all(&db)?.remove(0);
+        let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+        e1.create_relation(
+            &e2,
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            None,
+            &db,
+        )?;
+        e1.get_related::<Entity2>(&db)?;
+        e1.remove_relation(&e2, &db)?;
+        Entity2::remove_with_override(&String::from("id1"), &[], &db)
+    })?;
+    let snapshot = snapshotter.snapshot().into_vec();
+    let counter = |metric: &str, store: &str| {
+        snapshot
+            .iter()
+            .find(|(key, _, _, _)| {
+                key.key().name() == metric
+                    && key
+                        .key()
+                        .labels()
+                        .any(|l| l.key() == "store" && l.value() == store)
+            })
+            .map(|(_, _, _, value)| value)
+    };
+    assert!(matches!(
+        counter("reindeer_get_total", "entity_1"),
+        Some(DebugValue::Counter(2))
+    ));
+    assert!(matches!(
+        counter("reindeer_save_total", "entity_2"),
+        Some(DebugValue::Counter(3))
+    ));
+    assert!(matches!(
+        counter("reindeer_remove_total", "entity_2"),
+        Some(DebugValue::Counter(2))
+    ));
+    for (metric, count) in [
+        ("reindeer_get_all_total", 1),
+        ("reindeer_create_relation_total", 1),
+        ("reindeer_get_related_total", 1),
+        ("reindeer_remove_relation_total", 1),
+    ] {
+        assert!(matches!(
+            counter(metric, "entity_1"),
+            Some(DebugValue::Counter(value)) if *value == count
+        ));
+    }
+    assert!(matches!(
+        counter("reindeer_get_each_total", "entity_2"),
+        Some(DebugValue::Counter(1))
+    ));
+    assert!(matches!(
+        counter("reindeer_get_duration_seconds", "entity_1"),
+        Some(DebugValue::Histogram(values)) if values.len() == 2
+    ));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_find() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let found = Entity1::find(|e| e.prop1.starts_with("Hello"), &db)?.unwrap();
+    assert_eq!(found.id, 0);
+    let found = Entity2::find(|e| e.prop2 > 3, &db)?.unwrap();
+    assert_eq!(found.id, "id2");
+    assert!(Entity1::find(|e| e.prop1.is_empty(), &db)?.is_none());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_store_name_collision() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let error = Impostor::register(&db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    assert!(error.to_string().contains("Entity1"));
+    Entity1::register(&db)?;
+    Token::<Session>::register(&db)?;
+    Token::<Bearer>::register(&db)?;
+    crate::release_store_name("entity_1", &db)?;
+    Impostor::register(&db)?;
+    assert!(Entity1::register(&db).is_err());
+    // a claim recorded by another build, where the type name was different
+    let claims = vec![(None::<String>, 0u64, String::from("old_module::Entity1"))];
+    db.open_tree(crate::config::internal_tree_name("store_types"))?
+        .insert("entity_1", bincode::serialize(&claims)?)?;
+    Entity1::register(&db)?;
+    assert!(Impostor::register(&db).is_err());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_register_generic() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    assert!(Entity1::register_generic(&db).is_err());
+    Token::<Session>::register_generic(&db)?;
+    FamilyDescriptor {
+        tree_name: String::from("token"),
+        sibling_trees: vec![(String::from("entity_2"), DeletionBehaviour::Cascade)],
+        child_trees: Vec::new(),
+    }
+    .save(&db)?;
+    Token::<Bearer>::register_generic(&db)?;
+    Token::<Bearer>::register_generic(&db)?;
+    let desc = FamilyDescriptor::get(&String::from("token"), &db)?.unwrap();
+    assert_eq!(desc.sibling_trees.len(), 1);
+    assert_eq!(
+        Token::<Bearer>::registered_type_tags(&db)?,
+        ["session", "bearer"]
+    );
+    assert!(Entity1::registered_type_tags(&db)?.is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_health() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let empty = crate::health(&db)?;
+    assert_eq!(empty.record_count, 0);
+    assert!(empty.writable);
+    set_up_content(&db)?;
+    let report = crate::health(&db)?;
+    assert_eq!(
+        report.record_count,
+        Entity1::get_count(&db)?
+            + Entity2::get_count(&db)?
+            + Entity3::get_count(&db)?
+            + ChildEntity1::get_count(&db)?
+            + ChildEntity2::get_count(&db)?
+            + GrandChildEntity::get_count(&db)?
+    );
+    assert_eq!(report.tree_count, db.tree_names().len());
+    assert!(report.writable);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_fetch_tree() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    Entity1 {
+        id: 3,
+        prop1: String::from("Leaf"),
+    }
+    .save(&db)?;
+    let children_of = |e: &Entity1| match e.id {
+        0 => vec![1, 2, 7],
+        1 => vec![3, 0],
+        2 => vec![0, 3],
+        _ => vec![],
+    };
+    let tree = Entity1::fetch_tree(&0, children_of, 1, &db)?;
+    assert_eq!(tree.value.id, 0);
+    assert_eq!(
+        tree.children.iter().map(|c| c.value.id).collect::<Vec<_>>(),
+        [1, 2]
+    );
+    assert!(tree.children.iter().all(|c| c.children.is_empty()));
+    let tree = Entity1::fetch_tree(&0, children_of, 10, &db)?;
+    assert_eq!(tree.children[0].children[0].value.id, 3);
+    assert!(tree.children[1].children.is_empty());
+    assert!(Entity1::fetch_tree(&7, children_of, 10, &db).is_err());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_save_with_receipt() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let mut e1 = Entity1 {
+        id: 4,
+        prop1: String::from("Hello"),
+    };
+    let receipt = e1.save_with_receipt(&db)?;
+    assert_eq!(receipt.key, 4);
+    assert!(!receipt.was_update);
+    assert_eq!(
+        receipt.bytes_written,
+        Entity1::get_tree(&db)?.get(4u32.as_bytes())?.unwrap().len()
+    );
+    e1.prop1 = String::from("Hello, World!");
+    let receipt = e1.save_with_receipt(&db)?;
+    assert!(receipt.was_update);
+    assert_eq!(receipt.bytes_written, bincode::serialize(&e1)?.len());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_save_next_child_numbering() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let parent = ChildEntity1 {
+        id: (String::from("id1"), 0),
+    };
+    // encoded as "id1\0\0\0", a prefix of `parent`'s encoded key
+    let other = ChildEntity1 {
+        id: (String::from("id"), 0x3100_0000),
+    };
+    other.save_child(
+        &mut GrandChildEntity {
+            id: (other.id.clone(), 0x0007_0000),
+        },
+        &db,
+    )?;
+    let mut grand_child = GrandChildEntity {
+        id: (parent.id.clone(), 0),
+    };
+    assert_eq!(
+        parent.save_next_child(&mut grand_child, &db)?.1,
+        0x0007_0001
+    );
+    // the last key of the store is still `other`'s child : the index comes from the recorded key
+    assert_eq!(
+        parent.save_next_child(&mut grand_child, &db)?.1,
+        0x0007_0002
+    );
+    assert_eq!(other.save_next_child(&mut grand_child, &db)?.1, 0x0007_0003);
+    let sibling = ChildEntity1 {
+        id: (String::from("id1"), 1),
+    };
+    let key = sibling.save_next_child(&mut grand_child, &db)?;
+    assert_eq!(key.1, 0x0007_0004);
+    assert_eq!(GrandChildEntity::get_count(&db)?, 5);
+    // indices of removed children are not reused
+    GrandChildEntity::remove(&key, &db)?;
+    assert_eq!(
+        sibling.save_next_child(&mut grand_child, &db)?.1,
+        0x0007_0005
+    );
+    sibling.save_child(
+        &mut GrandChildEntity {
+            id: (sibling.id.clone(), u32::MAX),
+        },
+        &db,
+    )?;
+    let error = parent.save_next_child(&mut grand_child, &db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_prune_orphans() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    // Entity2's `prop2` refers to an Entity3
+    Entity2 {
+        id: String::from("id4"),
+        prop2: 2,
+    }
+    .save(&db)?;
+    let parent_of = |e: &Entity2| e.prop2;
+    assert_eq!(
+        crate::prune_orphans::<Entity2, Entity3, _>(&db, parent_of)?,
+        3
+    );
+    assert_eq!(Entity2::get_count(&db)?, 1);
+    assert!(Entity2::exists(&String::from("id4"), &db)?);
+    assert_eq!(
+        crate::prune_orphans::<Entity2, Entity3, _>(&db, parent_of)?,
+        0
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_related_counts_batch() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let b = DeletionBehaviour::BreakLink;
+    let sources = Entity1::get_all(&db)?;
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e2_2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    sources[0].create_relation(&e2_1, b, b, Some("member"), &db)?;
+    sources[0].create_relation(&e2_1, b, b, Some("admin"), &db)?;
+    sources[0].create_relation(&e2_2, b, b, Some("member"), &db)?;
+    sources[2].create_relation(&e2_2, b, b, None, &db)?;
+    assert_eq!(
+        Entity1::related_counts_batch::<Entity2>(&sources, None, &db)?,
+        [2, 0, 1]
+    );
+    assert_eq!(
+        Entity1::related_counts_batch::<Entity2>(&sources, Some("member"), &db)?,
+        [2, 0, 0]
+    );
+    assert_eq!(
+        Entity1::related_counts_batch::<Entity3>(&sources, None, &db)?,
+        [0, 0, 0]
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_create_relation_by_keys() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let b = DeletionBehaviour::BreakLink;
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    e1.create_relation_to_key::<Entity2>(&String::from("id1"), b, b, Some("member"), &db)?;
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    assert!(e1.is_related_to_with_name(&e2, "member", &db)?);
+    assert!(e2.is_related_to_with_name(&e1, "member", &db)?);
+    let error = e1
+        .create_relation_to_key::<Entity2>(&String::from("id9"), b, b, None, &db)
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::NotFound));
+    let error = Relation::create_by_keys(
+        "entity_1",
+        &9u32.as_bytes(),
+        "entity_3",
+        &0u32.as_bytes(),
+        b,
+        b,
+        None,
+        &db,
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::NotFound));
+    assert!(Relation::create_by_keys(
+        "unknown",
+        b"a",
+        "entity_3",
+        &0u32.as_bytes(),
+        b,
+        b,
+        None,
+        &db
+    )
+    .is_err());
+    assert!(Entity3::get(&0, &db)?
+        .unwrap()
+        .get_related::<Entity1>(&db)?
+        .is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_max_value_size() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Note::register(&db)?;
+    let mut note = Note {
+        id: 0,
+        text: String::from("Short"),
+    };
+    note.save(&db)?;
+    note.text = "a".repeat(32);
+    let error = note.save(&db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    assert_eq!(Note::get(&0, &db)?.unwrap().text, "Short");
+    assert!(Note::update(&0, |n| n.text = "b".repeat(64), &db).is_err());
+    assert_eq!(Note::get(&0, &db)?.unwrap().text, "Short");
+    Note::register_mergeable(&db)?;
+    let delta = |text: &str| Note {
+        id: 0,
+        text: String::from(text),
+    };
+    Note::merge_save(&0, delta(" note"), &db)?;
+    assert_eq!(Note::get(&0, &db)?.unwrap().text, "Short note");
+    let error = Note::merge_save(&0, delta(&"c".repeat(20)), &db)
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    assert_eq!(Note::get(&0, &db)?.unwrap().text, "Short note");
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_all_as() -> Result<()> {
+    #[derive(serde_derive::Deserialize)]
+    struct Projection {
+        prop1: String,
+    }
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    assert!(Entity1::get_all_as::<Projection>(&db).is_err());
+    drop(db);
+    tear_down(&name)?;
+    let db = crate::open_json_backed(std::env::temp_dir().join(&name))?;
+    Entity1::register(&db)?;
+    set_up_content(&db)?;
+    let projections = Entity1::get_all_as::<Projection>(&db)?;
+    assert_eq!(projections.len(), 3);
+    assert_eq!(projections[1].prop1, "Hello, Nancy!");
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_reconcile_registration() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    assert!(Entity3::registration_diff(&db)?.is_empty());
+    // registration made by an older version of Entity3
+    FamilyDescriptor {
+        tree_name: String::from("entity_3"),
+        sibling_trees: vec![(String::from("entity_1"), DeletionBehaviour::Cascade)],
+        child_trees: vec![(String::from("old_child"), DeletionBehaviour::Cascade)],
+    }
+    .save(&db)?;
+    let diff = Entity3::reconcile_registration(&db)?;
+    assert_eq!(
+        diff.added,
+        [(
+            FamilyRelation::Child,
+            String::from("child_entity_2"),
+            DeletionBehaviour::Error
+        )]
+    );
+    assert_eq!(
+        diff.removed,
+        [(
+            FamilyRelation::Child,
+            String::from("old_child"),
+            DeletionBehaviour::Cascade
+        )]
+    );
+    assert_eq!(
+        diff.changed,
+        [(
+            FamilyRelation::Sibling,
+            String::from("entity_1"),
+            DeletionBehaviour::Cascade,
+            DeletionBehaviour::Error
+        )]
+    );
+    assert!(Entity3::registration_diff(&db)?.is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_get_with_filter_par() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    for id in 0..1000 {
+        Entity2 {
+            id: format!("id{:04}", id),
+            prop2: id,
+        }
+        .save(&db)?;
+    }
+    let filter = |e: &Entity2| e.prop2.is_multiple_of(7);
+    let parallel = Entity2::get_with_filter_par(filter, &db)?;
+    let serial = Entity2::get_with_filter(filter, &db)?;
+    assert_eq!(parallel.len(), 143);
+    assert_eq!(
+        parallel.iter().map(|e| &e.id).collect::<Vec<_>>(),
+        serial.iter().map(|e| &e.id).collect::<Vec<_>>()
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_with_key_prefix() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    for tenant in ["tenant-1", "tenant-10", "tenant-2"] {
+        Entity2 {
+            id: format!("{}/a", tenant),
+            prop2: 0,
+        }
+        .save(&db)?;
+    }
+    let ids = |entities: Vec<Entity2>| entities.into_iter().map(|e| e.id).collect::<Vec<_>>();
+    assert_eq!(
+        ids(Entity2::get_with_key_prefix(b"tenant-1/", &db)?),
+        ["tenant-1/a"]
+    );
+    assert_eq!(
+        ids(Entity2::get_with_key_prefix(b"tenant-1", &db)?),
+        ["tenant-1/a", "tenant-10/a"]
+    );
+    assert_eq!(Entity2::get_with_key_prefix(b"", &db)?.len(), 6);
+    let children = ChildEntity1::get_with_key_prefix_of(&String::from("id3"), &db)?;
+    assert_eq!(children.len(), 3);
+    assert!(ChildEntity1::get_with_key_prefix_of(&String::from("id1"), &db)?.is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_filter_remove_report() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    // the sibling of Entity1 2 has children that forbid its removal
+    let report = Entity1::filter_remove_report(|_| true, &db)?;
+    assert_eq!(
+        report.removed.iter().map(|e| e.id).collect::<Vec<_>>(),
+        [0, 1]
+    );
+    assert_eq!(report.blocked.len(), 1);
+    assert_eq!(report.blocked[0].0.id, 2);
+    assert!(matches!(
+        report.blocked[0].1.kind(),
+        crate::ErrorKind::IntegrityError
+    ));
+    assert!(Entity1::exists(&2, &db)?);
+    assert!(Entity1::filter_remove(|_| true, &db)?.is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_upsert_many() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let item = |id: &str, prop2| Entity2 {
+        id: String::from(id),
+        prop2,
+    };
+    let add = |incoming: &Entity2, existing: &mut Entity2| existing.prop2 += incoming.prop2;
+    let stats = Entity2::upsert_many(
+        vec![
+            item("id1", 10),
+            item("id4", 1),
+            item("id4", 2),
+            item("id1", 100),
+        ],
+        add,
+        &db,
+    )?;
+    assert_eq!(
+        stats,
+        crate::UpsertStats {
+            inserted: 1,
+            updated: 1
+        }
+    );
+    assert_eq!(Entity2::get(&String::from("id1"), &db)?.unwrap().prop2, 113);
+    assert_eq!(Entity2::get(&String::from("id4"), &db)?.unwrap().prop2, 3);
+    assert_eq!(Entity2::get(&String::from("id2"), &db)?.unwrap().prop2, 5);
+    let error = Entity2::upsert_many(
+        vec![item("id2", 0)],
+        |_, existing| existing.id = String::from("id9"),
+        &db,
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    assert!(!Entity2::exists(&String::from("id9"), &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_copy_store_to() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let dest_name = get_random_name();
+    let mut dir = std::env::temp_dir();
+    dir.push(&dest_name);
+    let dest = crate::open(dir.to_str().unwrap())?;
+    Entity1::register(&dest)?;
+    let e1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    e1.create_relation(
+        &e2,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    assert_eq!(
+        Entity2::copy_store_to(&dest, &db)?,
+        Entity2::get_count(&db)?
+    );
+    assert_eq!(Entity2::get_count(&dest)?, Entity2::get_count(&db)?);
+    assert_eq!(
+        Entity2::get(&String::from("id1"), &dest)?.unwrap().prop2,
+        e1.prop2
+    );
+    let related = e1.get_related::<Entity2>(&dest)?;
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].id, "id2");
+    assert_eq!(e2.get_related::<Entity2>(&dest)?[0].id, "id1");
+    assert!(Entity1::get_all(&dest)?.is_empty());
+    assert!(Entity2::registration_diff(&dest)?.is_empty());
+    tear_down(&name)?;
+    tear_down(&dest_name)?;
+    Ok(())
+}
+
+#[test]
+fn test_read_error_policy() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let count = Entity2::get_count(&db)?;
+    Entity2::get_tree(&db)?.insert("corrupt", vec![1u8])?;
+    assert!(Entity2::get_all(&db).is_err());
+    assert!(Entity2::get_all_with_policy(crate::ReadErrorPolicy::Abort, &db).is_err());
+    let (all, errors) = Entity2::get_all_with_policy(crate::ReadErrorPolicy::Skip, &db)?;
+    assert_eq!(all.len(), count);
+    assert!(errors.is_empty());
+    let (all, errors) = Entity2::get_all_with_policy(crate::ReadErrorPolicy::Collect, &db)?;
+    assert_eq!(all.len(), count);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, b"corrupt".to_vec());
+    assert!(matches!(
+        errors[0].1.kind(),
+        crate::ErrorKind::SerializationError
+    ));
+    let (filtered, errors) = Entity2::get_with_filter_with_policy(
+        |e| e.id == "id1",
+        crate::ReadErrorPolicy::Collect,
+        &db,
+    )?;
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(errors.len(), 1);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_child_entity_key_accessors() -> Result<()> {
+    use crate::ChildEntity;
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let mut children = Entity3 { id: 2 }.get_children::<ChildEntity2>(&db)?;
+    children.sort_by_key(|child| std::cmp::Reverse(child.child_index()));
+    assert_eq!(
+        children.iter().map(|c| c.child_index()).collect::<Vec<_>>(),
+        vec![2, 1, 0]
+    );
+    assert!(children.iter().all(|child| child.parent_key() == 2));
+    let grand_child = GrandChildEntity {
+        id: ((String::from("id0"), 1), 2),
+    };
+    assert_eq!(grand_child.parent_key(), (String::from("id0"), 1));
+    assert_eq!(grand_child.child_index(), 2);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_children_batch() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let parents = Entity3::get_all(&db)?;
+    let mut expected = Vec::new();
+    for parent in &parents {
+        let mut child = ChildEntity2 { id: (0, 0) };
+        parent.save_next_child(&mut child, &db)?;
+        expected.push(parent.get_children::<ChildEntity2>(&db)?);
+    }
+    for ratio in [4, 0] {
+        crate::set_range_scan_ratio(ratio);
+        let children = Entity3::children_batch::<ChildEntity2>(&parents, &db)?;
+        crate::set_range_scan_ratio(4);
+        assert_eq!(children.len(), parents.len());
+        for (parent, expected) in parents.iter().zip(&expected) {
+            let ids = children[&Entity3::encode_key(parent.get_key())]
+                .iter()
+                .map(|c| c.id)
+                .collect::<Vec<_>>();
+            assert_eq!(ids, expected.iter().map(|c| c.id).collect::<Vec<_>>());
+        }
+    }
+    let children = Entity3::children_batch::<ChildEntity2>(&[Entity3 { id: 9 }], &db)?;
+    assert_eq!(children[&Entity3::encode_key(&9)].len(), 0);
+    // without separator, the prefix of "p1" also holds the children of "p10"
+    let parents = ["p1", "p10"].map(|id| Entity2 {
+        id: String::from(id),
+        prop2: 0,
+    });
+    for (parent, count) in parents.iter().zip([2, 1]) {
+        parent.save(&db)?;
+        for _ in 0..count {
+            parent.save_next_child(
+                &mut ChildEntity1 {
+                    id: (String::new(), 0),
+                },
+                &db,
+            )?;
+        }
+    }
+    for ratio in [4, 0] {
+        crate::set_range_scan_ratio(ratio);
+        let children = Entity2::children_batch::<ChildEntity1>(&parents, &db)?;
+        crate::set_range_scan_ratio(4);
+        assert_eq!(children[&Entity2::encode_key(&parents[0].id)].len(), 2);
+        assert_eq!(children[&Entity2::encode_key(&parents[1].id)].len(), 1);
+    }
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_open_config_sled_mode() -> Result<()> {
+    let name = get_random_name();
+    let db = crate::OpenConfig::new(std::env::temp_dir().join(&name))
+        .mode(crate::SledMode::HighThroughput)
+        .cache_capacity(1024 * 1024)
+        .open()?;
+    Entity1::register(&db)?;
+    Entity1 {
+        id: 0,
+        prop1: String::from("Fast"),
+    }
+    .save(&db)?;
+    db.flush()?;
+    drop(db);
+    let db = reopen(&name, |path| {
+        crate::OpenConfig::new(path)
+            .mode(crate::SledMode::LowSpace)
+            .open()
+    })?;
+    assert_eq!(Entity1::get(&0, &db)?.unwrap().prop1, "Fast");
+    drop(db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_children_page() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    for index in 0..5 {
+        e2.save_child(
+            &mut ChildEntity1 {
+                id: (String::new(), index),
+            },
+            &db,
+        )?;
+    }
+    let (page, cursor) = e2.get_children_page::<ChildEntity1>(None, 2, &db)?;
+    assert_eq!(page.iter().map(|c| c.id.1).collect::<Vec<_>>(), vec![0, 1]);
+    assert_eq!(cursor, Some(1));
+    let (page, cursor) = e2.get_children_page::<ChildEntity1>(cursor, 2, &db)?;
+    assert_eq!(page.iter().map(|c| c.id.1).collect::<Vec<_>>(), vec![2, 3]);
+    let (page, cursor) = e2.get_children_page::<ChildEntity1>(cursor, 2, &db)?;
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].id, (String::from("id2"), 4));
+    assert_eq!(cursor, None);
+    let (page, cursor) = e2.get_children_page::<ChildEntity1>(None, 5, &db)?;
+    assert_eq!(page.len(), 5);
+    assert_eq!(cursor, None);
+    let error = e2
+        .get_children_page::<ChildEntity1>(None, 0, &db)
+        .err()
+        .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_storage_format_cache() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    assert_eq!(StorageFormat::of(&db)?, StorageFormat::Bincode);
+    crate::storage::set_bincode_options(&db, true, None)?;
+    assert_eq!(
+        StorageFormat::of(&db)?,
+        StorageFormat::BincodeWith {
+            varint: true,
+            limit: None
+        }
+    );
+    drop(db);
+    let db = reopen(&name, |path| Ok(crate::open(path)?))?;
+    assert_eq!(
+        StorageFormat::of(&db)?,
+        StorageFormat::BincodeWith {
+            varint: true,
+            limit: None
+        }
+    );
+    tear_down(&name)?;
+    let json_name = get_random_name();
+    let db = crate::open_json_backed(std::env::temp_dir().join(&json_name))?;
+    assert_eq!(StorageFormat::of(&db)?, StorageFormat::Json);
+    tear_down(&json_name)?;
+    Ok(())
+}
+
+#[test]
+fn test_bincode_options() -> Result<()> {
+    let name = get_random_name();
+    let db = crate::OpenConfig::new(std::env::temp_dir().join(&name))
+        .bincode_varint(true)
+        .bincode_limit(64)
+        .open()?;
+    assert_eq!(
+        StorageFormat::of(&db)?,
+        StorageFormat::BincodeWith {
+            varint: true,
+            limit: Some(64)
+        }
+    );
+    Entity1::register(&db)?;
+    let mut e1 = Entity1 {
+        id: 0,
+        prop1: String::from("hi"),
+    };
+    e1.save(&db)?;
+    assert_eq!(
+        Entity1::get_tree(&db)?.get(0u32.as_bytes())?.unwrap().len(),
+        4
+    );
+    e1.prop1 = "a".repeat(100);
+    let error = e1.save(&db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::SerializationError));
+    drop(db);
+    let error = reopen(&name, |path| {
+        crate::OpenConfig::new(path).bincode_varint(false).open()
+    })
+    .err()
+    .unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::IntegrityError));
+    let db = reopen(&name, |path| {
+        crate::OpenConfig::new(path).bincode_limit(1000).open()
+    })?;
+    assert_eq!(Entity1::get(&0, &db)?.unwrap().prop1, "hi");
+    e1.save(&db)?;
+    assert_eq!(Entity1::get(&0, &db)?.unwrap().prop1.len(), 100);
+    drop(db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_flush_on_close() -> Result<()> {
+    let name = get_random_name();
+    let db = crate::OpenConfig::new(std::env::temp_dir().join(&name))
+        .flush_every_ms(None)
+        .open()?;
+    Entity1::register(&db)?;
+    Entity1 {
+        id: 0,
+        prop1: String::from("Flushed"),
+    }
+    .save(&db)?;
+    db.flush()?;
+    drop(db);
+    let db = reopen(&name, |path| {
+        Reindeer::builder(path).register::<Entity1>().open()
+    })?;
+    assert_eq!(db.get::<Entity1>(&0)?.unwrap().prop1, "Flushed");
+    db.save(&Entity1 {
+        id: 1,
+        prop1: String::from("Dropped"),
+    })?;
+    drop(db);
+    let db = reopen(&name, |path| crate::OpenConfig::new(path).open())?;
+    assert_eq!(Entity1::get(&1, &db)?.unwrap().prop1, "Dropped");
+    drop(db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_diff() -> Result<()> {
+    let stored = Entity1 {
+        id: 0,
+        prop1: String::from("Hello"),
+    };
+    let mut proposed = Entity1 {
+        id: 0,
+        prop1: String::from("Hello"),
+    };
+    assert_eq!(stored.diff(&proposed)?, serde_json::json!({}));
+    proposed.prop1 = String::from("Goodbye");
+    assert_eq!(
+        stored.diff(&proposed)?,
+        serde_json::json!({ "prop1": { "from": "Hello", "to": "Goodbye" } })
+    );
+    proposed.id = 1;
+    assert_eq!(stored.diff(&proposed)?.as_object().unwrap().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_removal_blockers() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let mut e1 = Entity1 {
+        id: 0,
+        prop1: String::from("Blocked"),
+    };
+    let id = e1.save_next(&db)?;
+    assert!(Entity1::removal_blockers(&id, &db)?.is_empty());
+    let mut e3 = Entity3 { id: 0 };
+    e1.save_sibling(&mut e3, &db)?;
+    e3.save_next_child(&mut ChildEntity2 { id: (0, 0) }, &db)?;
+    e3.save_next_child(&mut ChildEntity2 { id: (0, 0) }, &db)?;
+    let blockers = Entity3::removal_blockers(&id, &db)?;
+    assert_eq!(blockers.len(), 3);
+    assert!(blockers.contains(&(String::from("entity_1"), id.as_bytes())));
+    assert_eq!(
+        blockers
+            .iter()
+            .filter(|(store, _)| store == "child_entity_2")
+            .count(),
+        2
+    );
+    assert_eq!(Entity1::removal_blockers(&id, &db)?.len(), 2);
+    assert!(Entity3::remove(&id, &db).is_err());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_modify() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let previous = Entity1::modify(
+        &1,
+        |e| std::mem::replace(&mut e.prop1, String::from("Bye, Nancy!")),
+        &db,
+    )?;
+    assert_eq!(previous.as_deref(), Some("Hello, Nancy!"));
+    assert_eq!(Entity1::get(&1, &db)?.unwrap().prop1, "Bye, Nancy!");
+    assert!(Entity1::modify(&42, |e| e.id, &db)?.is_none());
+    let moved = Entity1::modify(
+        &2,
+        |e| {
+            e.id = 12;
+            e.prop1.len()
+        },
+        &db,
+    )?;
+    assert_eq!(moved, Some(12));
+    assert!(Entity1::get(&2, &db)?.is_none());
+    assert!(Entity1::get(&12, &db)?.is_some());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_child_key_separator() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Folder::register(&db)?;
+    FolderItem::register(&db)?;
+    let id1 = Folder {
+        name: String::from("id1"),
+    };
+    let id10 = Folder {
+        name: String::from("id10"),
+    };
+    id1.save(&db)?;
+    id10.save(&db)?;
+    for index in 0..2 {
+        id1.save_child(
+            &mut FolderItem {
+                id: (String::new(), index),
+            },
+            &db,
+        )?;
+        id10.save_child(
+            &mut FolderItem {
+                id: (String::new(), index),
+            },
+            &db,
+        )?;
+    }
+    assert_eq!(
+        FolderItem::get_tree(&db)?.first()?.unwrap().0.as_ref(),
+        b"id1\x1f\0\0\0\0"
+    );
+    assert_eq!(id1.get_children::<FolderItem>(&db)?.len(), 2);
+    assert_eq!(
+        FolderItem::child_keys_of(&String::from("id1"), &db)?,
+        vec![(String::from("id1"), 0), (String::from("id1"), 1)]
+    );
+    assert_eq!(
+        id1.get_children_page::<FolderItem>(None, 10, &db)?.0.len(),
+        2
+    );
+    assert_eq!(
+        FolderItem::get_with_key_prefix_of(&String::from("id1"), &db)?.len(),
+        2
+    );
+    Folder::remove(&String::from("id1"), &db)?;
+    assert_eq!(FolderItem::get_count(&db)?, 2);
+    assert_eq!(id10.get_children::<FolderItem>(&db)?.len(), 2);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_migrate_child_keys() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    Folder::register(&db)?;
+    FolderItem::register(&db)?;
+    let id1 = Folder {
+        name: String::from("id1"),
+    };
+    id1.save(&db)?;
+    let legacy_key = (String::from("id1"), 3).as_bytes();
+    FolderItem::get_tree(&db)?.insert(
+        &legacy_key,
+        bincode::serialize(&FolderItem {
+            id: (String::from("id1"), 3),
+        })?,
+    )?;
+    let e1 = Entity1 {
+        id: 0,
+        prop1: String::from("Linked"),
+    };
+    e1.save(&db)?;
+    let b = DeletionBehaviour::BreakLink;
+    Relation::create_link_with_keys_and_tree_names(
+        "folder_item",
+        &legacy_key,
+        "entity_1",
+        &0.as_bytes(),
+        b,
+        None,
+        &db,
+    )?;
+    Relation::create_link_with_keys_and_tree_names(
+        "entity_1",
+        &0.as_bytes(),
+        "folder_item",
+        &legacy_key,
+        b,
+        None,
+        &db,
+    )?;
+    assert_eq!(FolderItem::all_keys(&db)?, vec![(String::from("id1"), 3)]);
+    assert!(id1.get_children::<FolderItem>(&db)?.is_empty());
+    assert_eq!(FolderItem::migrate_child_keys(&db)?, 1);
+    assert_eq!(FolderItem::migrate_child_keys(&db)?, 0);
+    assert_eq!(id1.get_children::<FolderItem>(&db)?.len(), 1);
+    assert_eq!(
+        e1.get_related_keys::<FolderItem>(&db)?,
+        vec![(String::from("id1"), 3)]
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_swap() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e1 = Entity1::get(&0, &db)?.unwrap();
+    e1.relate_to(&e2).commit(&db)?;
+    Entity1::swap(&0, &2, &db)?;
+    let swapped = Entity1::get(&0, &db)?.unwrap();
+    assert_eq!(swapped.id, 0);
+    assert_eq!(swapped.prop1, "Hello, Jack!");
+    assert_eq!(Entity1::get(&2, &db)?.unwrap().prop1, "Hello, World!");
+    assert_eq!(swapped.get_related_keys::<Entity2>(&db)?, vec!["id1"]);
+    let error = Entity1::swap(&0, &7, &db).err().unwrap();
+    assert!(matches!(error.kind(), crate::ErrorKind::NotFound));
+    assert_eq!(Entity1::get(&0, &db)?.unwrap().prop1, "Hello, Jack!");
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_all_sorted_by() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    Entity2 {
+        id: String::from("id4"),
+        prop2: 5,
+    }
+    .save(&db)?;
+    let ids = |entities: Vec<Entity2>| entities.into_iter().map(|e| e.id).collect::<Vec<_>>();
+    assert_eq!(
+        ids(Entity2::get_all_sorted_by(|e| e.prop2, &db)?),
+        vec!["id1", "id2", "id4", "id3"]
+    );
+    assert_eq!(
+        ids(Entity2::get_all_sorted_by_desc(|e| e.prop2, &db)?),
+        vec!["id3", "id2", "id4", "id1"]
+    );
+    tear_down(&name)?;
+    Ok(())
+}
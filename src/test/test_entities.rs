@@ -5,8 +5,12 @@ use sled::Db;
 
 use crate::DeletionBehaviour;
 use crate::Entity;
+use crate::FromBytes;
+use crate::Mergeable;
+use crate::Timestamped;
+use std::marker::PhantomData;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Entity1 {
     pub id: u32,
     pub prop1: String,
@@ -25,17 +29,23 @@ pub struct Entity3 {
 
 #[derive(Serialize, Deserialize)]
 pub struct ChildEntity1 {
-    id: (String, u32),
+    pub id: (String, u32),
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ChildEntity2 {
-    id: (u32, u32),
+    pub id: (u32, u32),
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct GrandChildEntity {
-    id: ((String, u32), u32),
+    pub id: ((String, u32), u32),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Counter {
+    pub id: u32,
+    pub count: u64,
 }
 
 impl Entity for Entity1 {
@@ -151,6 +161,413 @@ impl Entity for GrandChildEntity {
     }
 }
 
+impl Entity for Counter {
+    type Key = u32;
+
+    fn store_name() -> &'static str {
+        "counter"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.id = *key;
+    }
+}
+
+impl Mergeable for Counter {
+    fn merge(existing: Option<Self>, delta: Self) -> Self {
+        match existing {
+            Some(existing) => Counter {
+                id: delta.id,
+                count: existing.count + delta.count,
+            },
+            None => delta,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Blob {
+    pub hash: [u8; 32],
+    pub data: Vec<u8>,
+}
+
+impl Entity for Blob {
+    type Key = [u8; 32];
+
+    fn store_name() -> &'static str {
+        "blob"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.hash
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.hash = *key;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Sample {
+    pub time: f64,
+    pub value: u32,
+}
+
+impl Entity for Sample {
+    type Key = f64;
+
+    fn store_name() -> &'static str {
+        "sample"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.time
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.time = *key;
+    }
+}
+
+#[cfg(feature = "lazy-migration")]
+#[derive(Serialize, Deserialize)]
+pub struct Versioned {
+    pub id: u32,
+    pub name: String,
+    pub email: String,
+}
+
+#[cfg(feature = "lazy-migration")]
+impl Entity for Versioned {
+    type Key = u32;
+    const VERSION: u32 = 1;
+
+    fn store_name() -> &'static str {
+        "versioned"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.id = *key;
+    }
+
+    fn migrate(_version: u32, bytes: &[u8]) -> Result<Self> {
+        let (id, name): (u32, String) = bincode::deserialize(bytes)?;
+        Ok(Versioned {
+            id,
+            name,
+            email: String::from("unknown"),
+        })
+    }
+}
+
+pub trait TokenKind {
+    const NAME: &'static str;
+}
+
+pub struct Session;
+
+impl TokenKind for Session {
+    const NAME: &'static str = "session";
+}
+
+pub struct Bearer;
+
+impl TokenKind for Bearer {
+    const NAME: &'static str = "bearer";
+}
+
+pub struct Oversized;
+
+impl TokenKind for Oversized {
+    const NAME: &'static str = match std::str::from_utf8(&[b'x'; 300]) {
+        Ok(name) => name,
+        Err(_) => panic!("invalid tag"),
+    };
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Token<K> {
+    pub id: String,
+    pub value: String,
+    #[serde(skip)]
+    pub kind: PhantomData<K>,
+}
+
+impl<K: TokenKind> Entity for Token<K> {
+    type Key = String;
+
+    fn store_name() -> &'static str {
+        "token"
+    }
+
+    fn type_tag() -> Option<&'static str> {
+        Some(K::NAME)
+    }
+
+    fn sibling_trees_for(&self) -> Vec<(&'static str, DeletionBehaviour)> {
+        if K::NAME == Session::NAME {
+            vec![("entity_2", DeletionBehaviour::Cascade)]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.id = key.clone();
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Heartbeat {
+    pub id: u32,
+    pub status: String,
+    pub updated_at: u64,
+}
+
+impl Entity for Heartbeat {
+    type Key = u32;
+
+    const STRICT_KEYS: bool = true;
+
+    fn store_name() -> &'static str {
+        "heartbeat"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.id = *key;
+    }
+}
+
+impl Timestamped for Heartbeat {
+    fn set_updated_at(&mut self, timestamp: u64) {
+        self.updated_at = timestamp;
+    }
+
+    fn now() -> u64 {
+        1_000
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Intruder {
+    pub id: u32,
+}
+
+impl Entity for Intruder {
+    type Key = u32;
+
+    fn store_name() -> &'static str {
+        "__$rel_entity_1"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.id = *key;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Folder {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FolderItem {
+    pub id: (String, u32),
+}
+
+impl Entity for Folder {
+    type Key = String;
+
+    fn store_name() -> &'static str {
+        "folder"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.name
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.name = key.clone();
+    }
+
+    fn get_child_trees() -> Vec<(&'static str, DeletionBehaviour)> {
+        vec![("folder_item", DeletionBehaviour::Cascade)]
+    }
+}
+
+impl Entity for FolderItem {
+    type Key = (String, u32);
+    const CHILD_KEY_SEPARATOR: Option<u8> = Some(0x1F);
+
+    fn store_name() -> &'static str {
+        "folder_item"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.id = key.clone();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Impostor {
+    pub id: u32,
+}
+
+impl Entity for Impostor {
+    type Key = u32;
+
+    fn store_name() -> &'static str {
+        "entity_1"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.id = *key;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Tag {
+    pub name: String,
+}
+
+impl Entity for Tag {
+    type Key = String;
+
+    fn store_name() -> &'static str {
+        "tag"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.name
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.name = key.clone();
+    }
+
+    fn encode_key(key: &String) -> Vec<u8> {
+        key.to_lowercase().into_bytes()
+    }
+
+    fn decode_key(bytes: &[u8]) -> Result<String>
+    where
+        String: FromBytes,
+    {
+        String::from_bytes(bytes)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Opaque {
+    pub id: Vec<u8>,
+    pub data: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OpaquePart {
+    pub id: (Vec<u8>, u32),
+}
+
+impl Entity for Opaque {
+    type Key = Vec<u8>;
+
+    fn store_name() -> &'static str {
+        "opaque"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.id = key.clone();
+    }
+
+    fn get_child_trees() -> Vec<(&'static str, DeletionBehaviour)> {
+        vec![("opaque_part", DeletionBehaviour::Cascade)]
+    }
+}
+
+impl Entity for OpaquePart {
+    type Key = (Vec<u8>, u32);
+
+    fn store_name() -> &'static str {
+        "opaque_part"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.id = key.clone();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Note {
+    pub id: u32,
+    pub text: String,
+}
+
+impl Entity for Note {
+    type Key = u32;
+
+    const MAX_VALUE_SIZE: Option<usize> = Some(32);
+
+    fn store_name() -> &'static str {
+        "note"
+    }
+
+    fn get_key(&self) -> &Self::Key {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: &Self::Key) {
+        self.id = *key;
+    }
+}
+
+impl Mergeable for Note {
+    fn merge(existing: Option<Self>, delta: Self) -> Self {
+        match existing {
+            Some(existing) => Note {
+                id: delta.id,
+                text: existing.text + &delta.text,
+            },
+            None => delta,
+        }
+    }
+}
+
 pub fn set_up(name: &str) -> Result<Db> {
     let mut dir = std::env::temp_dir();
     dir.push(name);
@@ -209,6 +626,21 @@ pub fn set_up_content(db: &Db) -> Result<()> {
     Ok(())
 }
 
+/// Reopens a database that was just dropped, waiting for `sled` to release its file lock,
+/// which happens asynchronously once its background flusher stops.
+pub fn reopen<T, F: Fn(std::path::PathBuf) -> Result<T>>(name: &str, open: F) -> Result<T> {
+    let path = std::env::temp_dir().join(name);
+    for _ in 0..50 {
+        match open(path.clone()) {
+            Err(error) if matches!(error.kind(), crate::ErrorKind::SledError) => {
+                std::thread::sleep(std::time::Duration::from_millis(20))
+            }
+            result => return result,
+        }
+    }
+    open(path)
+}
+
 pub fn tear_down(name: &str) -> Result<()> {
     let mut dir = std::env::temp_dir();
     dir.push(name);